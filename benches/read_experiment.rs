@@ -0,0 +1,227 @@
+//! Loader benchmarks on synthetic scans, run with `cargo bench`.
+
+mod synthetic;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ndarray::Array2;
+use polars::prelude::*;
+use pyref_core::enums::ExperimentType;
+use pyref_core::io::col_from_array2;
+use pyref_core::loader::{
+    combine_dataframes_with_alignment, read_experiment, read_experiment_with_options, read_fits,
+    read_multiple_fits, scan_experiment,
+};
+use pyref_core::options::{LoaderOptions, Parallelism};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The system allocator, tracking the peak of the live heap bytes for the memory
+/// side of `bench_scan_stats`.
+struct PeakAlloc;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Peak heap bytes allocated while `f` runs, above those live before it.
+fn peak_bytes<T>(f: impl FnOnce() -> T) -> usize {
+    let base = LIVE.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let result = f();
+    let peak = PEAK.load(Ordering::Relaxed) - base;
+    drop(result);
+    peak
+}
+
+/// `(frames, size)` of the generated scans.
+const SCANS: [(usize, usize); 3] = [(20, 256), (100, 256), (20, 1024)];
+
+fn scan_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    files
+}
+
+fn bench_read_experiment(c: &mut Criterion) {
+    let keys = ExperimentType::Xrr.get_keys();
+    let mut group = c.benchmark_group("read_experiment");
+    group.sample_size(10);
+    for (frames, size) in SCANS {
+        let dir = synthetic::write_scan(&format!("read_{}x{}", frames, size), frames, size);
+        let id = BenchmarkId::from_parameter(format!("{} frames of {}x{}", frames, size, size));
+        group.bench_with_input(id, &dir, |b, dir| {
+            b.iter(|| read_experiment(dir.to_str().unwrap(), &keys).unwrap())
+        });
+        fs::remove_dir_all(dir).unwrap();
+    }
+    group.finish();
+}
+
+fn bench_combine(c: &mut Criterion) {
+    let keys = ExperimentType::Xrr.get_keys();
+    let mut group = c.benchmark_group("combine_dataframes_with_alignment");
+    group.sample_size(10);
+    for (frames, size) in SCANS {
+        let dir = synthetic::write_scan(&format!("combine_{}x{}", frames, size), frames, size);
+        let dfs: Vec<DataFrame> = scan_files(&dir)
+            .into_iter()
+            .map(|path| read_fits(path, &keys).unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        let id = BenchmarkId::from_parameter(format!("{} frames of {}x{}", frames, size, size));
+        group.bench_with_input(id, &dfs, |b, dfs| {
+            b.iter(|| {
+                dfs[1..].iter().fold(dfs[0].clone(), |acc, df| {
+                    combine_dataframes_with_alignment(acc, df.clone()).unwrap()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Files loaded by `bench_identical_schemas`.
+const IDENTICAL_FILES: usize = 1000;
+
+/// Loads `IDENTICAL_FILES` small frames sharing one schema, the common case the
+/// diagonal concat of the loaders has to keep cheap.
+fn bench_identical_schemas(c: &mut Criterion) {
+    let keys = ExperimentType::Xrr.get_keys();
+    let dir = synthetic::write_scan("identical_schemas", IDENTICAL_FILES, 64);
+    let files = scan_files(&dir);
+    let mut group = c.benchmark_group("identical_schemas");
+    group.sample_size(10);
+    let id = BenchmarkId::new("read_multiple_fits", format!("{} frames", IDENTICAL_FILES));
+    group.bench_with_input(id, &files, |b, files| {
+        b.iter(|| read_multiple_fits(files.clone(), &keys).unwrap())
+    });
+    group.finish();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// `(frames, size)` of the scans for the two `Parallelism` regimes: many small
+/// frames and a few large ones.
+const PARALLELISM_SCANS: [(usize, usize); 2] = [(200, 128), (4, 2048)];
+
+fn bench_parallelism(c: &mut Criterion) {
+    let keys = ExperimentType::Xrr.get_keys();
+    let mut group = c.benchmark_group("parallelism");
+    group.sample_size(10);
+    for (frames, size) in PARALLELISM_SCANS {
+        let dir = synthetic::write_scan(&format!("parallel_{}x{}", frames, size), frames, size);
+        for parallelism in [
+            Parallelism::AcrossFiles,
+            Parallelism::WithinFrames,
+            Parallelism::Auto,
+        ] {
+            let mut options = LoaderOptions {
+                parallelism,
+                ..Default::default()
+            };
+            options.image.fixed_size_list = true;
+            let id = BenchmarkId::new(
+                format!("{:?}", parallelism),
+                format!("{} frames of {}x{}", frames, size, size),
+            );
+            group.bench_with_input(id, &dir, |b, dir| {
+                b.iter(|| {
+                    read_experiment_with_options(dir.to_str().unwrap(), &keys, &options).unwrap()
+                })
+            });
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+    group.finish();
+}
+
+/// The image-free columns of a scan from `scan_experiment` against the eager
+/// `read_experiment`, which holds every `Raw` image. The peak heap of one run of
+/// each is printed before the timings.
+fn bench_scan_stats(c: &mut Criterion) {
+    let keys = ExperimentType::Xrr.get_keys();
+    let (frames, size) = (20, 1024);
+    let dir = synthetic::write_scan("scan_stats", frames, size);
+    let path = dir.to_str().unwrap();
+    let stats = || {
+        scan_experiment(path, &keys)
+            .unwrap()
+            .select([all().exclude(["Raw"])])
+            .collect()
+            .unwrap()
+    };
+    let eager = || read_experiment(path, &keys).unwrap();
+    let scan = format!("{} frames of {}x{}", frames, size, size);
+    for (name, peak) in [
+        ("scan stats", peak_bytes(stats)),
+        ("read_experiment", peak_bytes(eager)),
+    ] {
+        println!(
+            "{} on {}: peak heap {:.1} MiB",
+            name,
+            scan,
+            peak as f64 / 1048576.0
+        );
+    }
+    let mut group = c.benchmark_group("scan_stats");
+    group.sample_size(10);
+    group.bench_function(BenchmarkId::new("scan stats", &scan), |b| b.iter(stats));
+    group.bench_function(BenchmarkId::new("read_experiment", &scan), |b| {
+        b.iter(eager)
+    });
+    group.finish();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Side of the frame packed by `bench_row_major`.
+const PACKED_SIZE: usize = 2048;
+
+/// Packs one frame into `Raw` from the calling thread, where the rows are copied
+/// in parallel, and from inside a rayon pool, where `row_major` copies them in
+/// turn as it does while files load in parallel.
+fn bench_row_major(c: &mut Criterion) {
+    let frame = Array2::from_shape_fn((PACKED_SIZE, PACKED_SIZE), |(i, j)| (i * j) as f64);
+    let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+    let pack = || col_from_array2::<Float64Type>("Raw".into(), frame.view()).unwrap();
+    let size = format!("{}x{}", PACKED_SIZE, PACKED_SIZE);
+    let mut group = c.benchmark_group("row_major");
+    group.sample_size(10);
+    group.bench_function(BenchmarkId::new("parallel", &size), |b| b.iter(pack));
+    group.bench_function(BenchmarkId::new("serial in pool", &size), |b| {
+        b.iter(|| pool.install(pack))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_read_experiment,
+    bench_combine,
+    bench_identical_schemas,
+    bench_parallelism,
+    bench_scan_stats,
+    bench_row_major
+);
+criterion_main!(benches);
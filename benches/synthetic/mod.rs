@@ -0,0 +1,97 @@
+//! Synthetic FITS files shaped like the ones written at the beamline, for
+//! benchmarks and tests that need a directory of frames without real data.
+//!
+//! Include it with `mod synthetic;` from a bench, or with
+//! `#[path = "../benches/synthetic/mod.rs"] mod synthetic;` from a test.
+
+use astrors_fork::io::hdulist::{HDUList, HDU};
+use astrors_fork::io::hdus::image::{imagehdu::ImageHDU, ImageData};
+use astrors_fork::io::hdus::primaryhdu::PrimaryHDU;
+use astrors_fork::io::header::card::Card;
+use astrors_fork::io::Header;
+use ndarray::{ArrayD, IxDyn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Header cards of `ExperimentType::Xrr` for a frame at `theta`.
+pub fn xrr_cards(theta: f64) -> Vec<(String, String)> {
+    [
+        ("Sample Theta", theta.to_string()),
+        ("CCD Theta", (2.0 * theta).to_string()),
+        ("Beamline Energy", "250.0".to_string()),
+        ("Beam Current", "500.0".to_string()),
+        ("EPU Polarization", "100.0".to_string()),
+        ("Horizontal Exit Slit Size", "100.0".to_string()),
+        ("Higher Order Suppressor", "5.5".to_string()),
+        ("EXPOSURE", "0.5".to_string()),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+/// A `size` x `size` 16 bit frame with a Gaussian spot of height `peak` on a
+/// flat background, as stored with `BZERO = 32768`.
+pub fn gaussian_frame(size: usize, peak: f64) -> ImageData {
+    let center = (size as f64 - 1.0) / 2.0;
+    let sigma = (size as f64 / 20.0).max(1.0);
+    let pixels = ArrayD::from_shape_fn(IxDyn(&[size, size]), |index| {
+        let (y, x) = (index[0] as f64 - center, index[1] as f64 - center);
+        let value = 100.0 + peak * (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+        (value.min(u16::MAX as f64) - 32768.0) as i16
+    });
+    ImageData::I16(pixels)
+}
+
+/// Writes a CCD file with the layout the loader expects: the header `cards` in
+/// the primary HDU and the frame in HDU 2, after an empty image extension.
+pub fn write_frame(path: &Path, cards: &[(String, String)], data: ImageData) {
+    let mut primary = PrimaryHDU::default();
+    for (key, value) in cards {
+        primary
+            .header
+            .add_card(&Card::new(key.clone(), value.clone(), None));
+    }
+    let image = |data: ImageData| {
+        let mut header = Header::new();
+        header.add_card(&Card::new("XTENSION".into(), "IMAGE".into(), None));
+        header.add_card(&Card::new("BITPIX".into(), "16".into(), None));
+        let shape = data.get_shape();
+        header.add_card(&Card::new("NAXIS".into(), shape.len().to_string(), None));
+        for (axis, length) in shape.iter().enumerate() {
+            let key = format!("NAXIS{}", axis + 1);
+            header.add_card(&Card::new(key, length.to_string(), None));
+        }
+        header.add_card(&Card::new("BZERO".into(), "32768".into(), None));
+        ImageHDU::new(header, data)
+    };
+    let mut hdus = HDUList::new();
+    hdus.add_hdu(HDU::Primary(primary));
+    hdus.add_hdu(HDU::Image(image(ImageData::I16(ArrayD::zeros(IxDyn(&[
+        2, 2,
+    ]))))));
+    hdus.add_hdu(HDU::Image(image(data)));
+    hdus.write_to(path.to_str().expect("non UTF-8 path"))
+        .expect("failed to write synthetic FITS file");
+}
+
+/// Writes an XRR scan of `frames` frames of `size` x `size` pixels into a fresh
+/// directory under the system temp directory and returns it.
+///
+/// The files are named like beamline output, `synthetic-00001.fits` onwards,
+/// with theta stepping from 0 by 0.1 degree.
+pub fn write_scan(name: &str, frames: usize, size: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pyref_synthetic_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("failed to create synthetic scan directory");
+    for frame in 0..frames {
+        let path = dir.join(format!("synthetic-{:05}.fits", frame + 1));
+        let peak = 20_000.0 / (1.0 + frame as f64);
+        write_frame(
+            &path,
+            &xrr_cards(frame as f64 * 0.1),
+            gaussian_frame(size, peak),
+        );
+    }
+    dir
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use astrors_fork::io::header::card::{Card, CardValue};
+use astrors_fork::io::Header;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::FitsLoaderError;
+
+// ================== Metadata Cache ==================
+// Header cards of previously loaded files, kept on disk as a single JSON file
+// so that re-loading a directory skips parsing the files again. Entries are
+// keyed by the canonical file path and only reused while the modification time
+// and size of the file are unchanged.
+
+/// Name of the cache file inside `LoaderOptions::metadata_cache`.
+pub const CACHE_FILE: &str = "metadata.json";
+
+/// Card value in a form JSON can round-trip, floats as their decimal string so
+/// that `NaN` and infinities survive.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum CachedValue {
+    Int(i64),
+    Float(String),
+    String(String),
+    Logical(bool),
+    Empty,
+}
+
+impl From<&CardValue> for CachedValue {
+    fn from(value: &CardValue) -> Self {
+        match value {
+            CardValue::INT(v) => CachedValue::Int(*v),
+            CardValue::FLOAT(v) => CachedValue::Float(v.to_string()),
+            CardValue::STRING(v) => CachedValue::String(v.clone()),
+            CardValue::LOGICAL(v) => CachedValue::Logical(*v),
+            CardValue::EMPTY => CachedValue::Empty,
+        }
+    }
+}
+
+impl From<&CachedValue> for CardValue {
+    fn from(value: &CachedValue) -> Self {
+        match value {
+            CachedValue::Int(v) => CardValue::INT(*v),
+            CachedValue::Float(v) => CardValue::FLOAT(v.parse().unwrap_or(f64::NAN)),
+            CachedValue::String(v) => CardValue::STRING(v.clone()),
+            CachedValue::Logical(v) => CardValue::LOGICAL(*v),
+            CachedValue::Empty => CardValue::EMPTY,
+        }
+    }
+}
+
+/// The cards of one header together with the state of the file they came from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    modified_ns: u64,
+    len: u64,
+    hdu: usize,
+    cards: Vec<(String, CachedValue)>,
+}
+
+impl CacheEntry {
+    /// Snapshot of `header`, HDU `hdu` of the file at `path`.
+    pub(crate) fn new(path: &Path, hdu: usize, header: &Header) -> Result<Self, FitsLoaderError> {
+        let (modified_ns, len) = file_state(path)?;
+        Ok(CacheEntry {
+            modified_ns,
+            len,
+            hdu,
+            cards: header
+                .iter()
+                .map(|card| (card.keyword.clone(), CachedValue::from(&card.value)))
+                .collect(),
+        })
+    }
+
+    /// Rebuilds the header, in the original card order.
+    pub(crate) fn header(&self) -> Header {
+        let mut header = Header::new();
+        for (keyword, value) in &self.cards {
+            header.add_card(&Card {
+                keyword: keyword.clone(),
+                value: value.into(),
+                comment: None,
+            });
+        }
+        header
+    }
+}
+
+/// Modification time in nanoseconds since the epoch and size of the file.
+fn file_state(path: &Path) -> Result<(u64, u64), FitsLoaderError> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    Ok((modified, metadata.len()))
+}
+
+/// Cached headers by canonical file path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct MetadataCache {
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl MetadataCache {
+    /// Reads the cache in `dir`. A missing or unreadable cache file, e.g. one
+    /// written by an older version, is treated as empty and rebuilt.
+    pub(crate) fn load(dir: &Path) -> Self {
+        fs::read_to_string(dir.join(CACHE_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// The cached entry for HDU `hdu` of `path`, if the file has not changed.
+    pub(crate) fn get(&self, path: &Path, hdu: usize) -> Option<&CacheEntry> {
+        let entry = self.entries.get(&cache_key(path)?)?;
+        let (modified_ns, len) = file_state(path).ok()?;
+        (entry.hdu == hdu && entry.modified_ns == modified_ns && entry.len == len).then_some(entry)
+    }
+
+    pub(crate) fn insert(&mut self, path: &Path, entry: CacheEntry) {
+        if let Some(key) = cache_key(path) {
+            self.entries.insert(key, entry);
+        }
+    }
+
+    /// Writes the cache to `dir`, creating the directory if needed. The file is
+    /// replaced in one rename, so a concurrent reader never sees half of it.
+    pub(crate) fn save(&self, dir: &Path) -> Result<(), FitsLoaderError> {
+        fs::create_dir_all(dir)?;
+        let text = serde_json::to_string(self)
+            .map_err(|e| FitsLoaderError::Other(format!("Failed to write cache: {}", e)))?;
+        let tmp = dir.join(format!("{}.{}.tmp", CACHE_FILE, std::process::id()));
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, dir.join(CACHE_FILE))?;
+        Ok(())
+    }
+}
+
+fn cache_key(path: &Path) -> Option<String> {
+    fs::canonicalize(path)
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Deletes the metadata cache kept in `dir`, the `LoaderOptions::metadata_cache`
+/// directory. Clearing a cache that does not exist is not an error.
+pub fn clear_metadata_cache(dir: impl AsRef<Path>) -> Result<(), FitsLoaderError> {
+    match fs::remove_file(dir.as_ref().join(CACHE_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::errors::FitsLoaderError;
+
+// ================== FITS Checksum Verification ==================
+// Implements the CHECKSUM/DATASUM convention (FITS standard, appendix J): both
+// are 32-bit ones' complement sums over the 2880-byte blocks of an HDU. DATASUM
+// holds the sum of the data blocks as a decimal string, CHECKSUM is encoded so
+// that the sum over header and data blocks is negative zero.
+
+pub(crate) const BLOCK: usize = 2880;
+const CARD: usize = 80;
+
+/// 32-bit ones' complement sum of big-endian words, folding carries back in.
+fn ones_complement_sum(bytes: &[u8]) -> u32 {
+    let mut sum: u64 = bytes
+        .chunks_exact(4)
+        .map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]]) as u64)
+        .sum();
+    while sum >> 32 != 0 {
+        sum = (sum & 0xFFFF_FFFF) + (sum >> 32);
+    }
+    sum as u32
+}
+
+fn ones_complement_add(a: u32, b: u32) -> u32 {
+    let sum = a as u64 + b as u64;
+    ((sum & 0xFFFF_FFFF) + (sum >> 32)) as u32
+}
+
+/// Keyword and raw value string of every card up to `END`, plus the header
+/// length in bytes. Quoted values keep a `/` inside the quotes.
+pub(crate) fn parse_header(bytes: &[u8]) -> Option<(Vec<(String, String)>, usize)> {
+    let mut cards = vec![];
+    for (i, card) in bytes.chunks_exact(CARD).enumerate() {
+        let keyword = String::from_utf8_lossy(&card[..8]).trim().to_string();
+        if keyword == "END" {
+            let len = (i + 1) * CARD;
+            return Some((cards, len.div_ceil(BLOCK) * BLOCK));
+        }
+        if &card[8..10] == b"= " {
+            let value = String::from_utf8_lossy(&card[10..]);
+            let value = match value.trim_start().strip_prefix('\'') {
+                Some(quoted) => quoted_value(quoted),
+                None => value.split('/').next().unwrap_or("").trim().to_string(),
+            };
+            cards.push((keyword, value));
+        }
+    }
+    None
+}
+
+/// The text of a string value after its opening quote, up to the closing
+/// quote, with doubled quotes unescaped and trailing blanks removed.
+fn quoted_value(quoted: &str) -> String {
+    let mut value = String::new();
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            if chars.peek() != Some(&'\'') {
+                break;
+            }
+            chars.next();
+        }
+        value.push(c);
+    }
+    value.trim_end().to_string()
+}
+
+fn card_int(cards: &[(String, String)], key: &str) -> Option<i64> {
+    cards
+        .iter()
+        .find(|(k, _)| k == key)
+        .and_then(|(_, v)| v.parse().ok())
+}
+
+/// Size in bytes of the data unit described by `cards`, without padding.
+fn data_len(cards: &[(String, String)]) -> usize {
+    let naxis = card_int(cards, "NAXIS").unwrap_or(0);
+    if naxis == 0 {
+        return 0;
+    }
+    let bytes = card_int(cards, "BITPIX").unwrap_or(8).unsigned_abs() / 8;
+    let pixels: i64 = (1..=naxis)
+        .map(|n| card_int(cards, &format!("NAXIS{}", n)).unwrap_or(0))
+        .product();
+    let pcount = card_int(cards, "PCOUNT").unwrap_or(0);
+    let gcount = card_int(cards, "GCOUNT").unwrap_or(1);
+    (bytes as i64 * gcount * (pcount + pixels)).max(0) as usize
+}
+
+/// Verifies the `CHECKSUM`/`DATASUM` cards of every HDU in the file.
+///
+/// HDUs without the cards are accepted as is, the first mismatch is reported as
+/// `FitsLoaderError::ChecksumMismatch` carrying the file path and HDU index.
+pub fn verify_checksums(path: &Path) -> Result<(), FitsLoaderError> {
+    let bytes = fs::read(path)?;
+    let mismatch = |hdu| FitsLoaderError::ChecksumMismatch {
+        path: path.display().to_string(),
+        hdu,
+    };
+
+    let mut offset = 0;
+    let mut hdu = 0;
+    while offset + BLOCK <= bytes.len() {
+        let (cards, header_len) = parse_header(&bytes[offset..]).ok_or_else(|| mismatch(hdu))?;
+        let data_start = offset + header_len;
+        let data_end = data_start + data_len(&cards).div_ceil(BLOCK) * BLOCK;
+        if data_end > bytes.len() {
+            return Err(mismatch(hdu));
+        }
+
+        let data_sum = ones_complement_sum(&bytes[data_start..data_end]);
+        if let Some((_, datasum)) = cards.iter().find(|(k, _)| k == "DATASUM") {
+            if datasum.parse::<u32>().ok() != Some(data_sum) {
+                return Err(mismatch(hdu));
+            }
+        }
+        if cards.iter().any(|(k, _)| k == "CHECKSUM") {
+            let header_sum = ones_complement_sum(&bytes[offset..data_start]);
+            let total = ones_complement_add(header_sum, data_sum);
+            if total != 0xFFFF_FFFF && total != 0 {
+                return Err(mismatch(hdu));
+            }
+        }
+
+        offset = data_end;
+        hdu += 1;
+    }
+    Ok(())
+}
+
+// ================== Block Structure ==================
+
+/// Checks that the file starts with a primary header and is long enough for the
+/// data unit of every HDU its headers declare.
+///
+/// Only the header blocks are read. This guards the FITS reader, which does not
+/// return on files that end inside an HDU.
+pub fn check_structure(path: &Path) -> Result<(), FitsLoaderError> {
+    data_units(path).map(|_| ())
+}
+
+/// Start offset and unpadded length in bytes of the data unit of every HDU,
+/// erroring as `check_structure` does on a malformed file.
+///
+/// The next HDU has to start where the `NAXISn` cards say the data unit ends.
+/// When it does not, they are checked against the blocks up to the next
+/// extension header or the end of the file, and disagree as
+/// `FitsLoaderError::NaxisMismatch`. Running past the end of the file with no
+/// extension in between is a truncated file instead. A mismatch within the last
+/// block of padding goes unnoticed, the reader then decodes as the cards say.
+pub(crate) fn data_units(path: &Path) -> Result<Vec<(usize, usize)>, FitsLoaderError> {
+    let invalid = |reason: &str| {
+        FitsLoaderError::FitsError(format!(
+            "{} is not a valid FITS file: {}",
+            path.display(),
+            reason
+        ))
+    };
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+
+    let mut units = vec![];
+    let mut offset = 0;
+    while offset < len {
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let mut header = vec![];
+        let (cards, header_len) = loop {
+            let mut block = [0u8; BLOCK];
+            if file.read_exact(&mut block).is_err() {
+                return Err(invalid("truncated header"));
+            }
+            if offset == 0 && header.is_empty() && !block.starts_with(b"SIMPLE  =") {
+                return Err(invalid("missing SIMPLE card"));
+            }
+            header.extend_from_slice(&block);
+            if let Some(parsed) = parse_header(&header) {
+                break parsed;
+            }
+        };
+        let data = data_len(&cards);
+        let start = offset + header_len;
+        units.push((start, data));
+        offset = start + data.div_ceil(BLOCK) * BLOCK;
+        if offset > len || (offset < len && !starts_extension(&mut file, offset)?) {
+            let found = data_unit_span(&mut file, start, len)?;
+            if offset > len && start + found == len {
+                return Err(invalid("truncated data unit"));
+            }
+            return Err(FitsLoaderError::NaxisMismatch {
+                path: path.display().to_string(),
+                header: naxes(&cards),
+                expected_bytes: offset - start,
+                found_bytes: found,
+            });
+        }
+    }
+    if len == 0 {
+        return Err(invalid("empty file"));
+    }
+    Ok(units)
+}
+
+/// Axis lengths from the `NAXISn` cards, `NAXIS1` first.
+fn naxes(cards: &[(String, String)]) -> Vec<usize> {
+    let naxis = card_int(cards, "NAXIS").unwrap_or(0);
+    (1..=naxis)
+        .map(|n| card_int(cards, &format!("NAXIS{}", n)).map_or(0, |v| v.max(0) as usize))
+        .collect()
+}
+
+/// Whether the block at `offset` starts an extension header.
+fn starts_extension(file: &mut fs::File, offset: usize) -> Result<bool, FitsLoaderError> {
+    let mut keyword = [0u8; 9];
+    file.seek(SeekFrom::Start(offset as u64))?;
+    Ok(file.read_exact(&mut keyword).is_ok() && &keyword == b"XTENSION=")
+}
+
+/// Bytes from the data unit at `start` to the next extension header or the end
+/// of the file, whichever comes first, in whole blocks.
+fn data_unit_span(file: &mut fs::File, start: usize, len: usize) -> Result<usize, FitsLoaderError> {
+    let mut offset = start;
+    while offset < len && !starts_extension(file, offset)? {
+        offset += BLOCK;
+    }
+    Ok(offset.min(len) - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_dir, u16_image, write_fits};
+
+    #[test]
+    fn checksums_pass_until_a_data_byte_flips() {
+        let dir = test_dir("checksums");
+        let path = dir.join("film_00001.fits");
+        // Encoded as astropy encodes them, for these exact header and data
+        // bytes. The CHECKSUM value starts in column 11 as the encoding expects.
+        let mut image = u16_image(2, 2, &[1, 2, 3, 4]);
+        image.cards.extend([
+            ("DATASUM", "'327687'"),
+            ("CHECKSUM", "'f8DLi6AKf6AKf6AK'  "),
+        ]);
+        write_fits(&path, &[], Some(image));
+        verify_checksums(&path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let data_start = 2 * BLOCK;
+        bytes[data_start + 1] ^= 0x01;
+        let flipped = dir.join("film_00002.fits");
+        fs::write(&flipped, bytes).unwrap();
+        assert!(matches!(
+            verify_checksums(&flipped),
+            Err(FitsLoaderError::ChecksumMismatch { path, hdu: 1 })
+                if path == flipped.display().to_string()
+        ));
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::enums::HeaderValue;
+use crate::errors::FitsLoaderError;
+use crate::options::{ImageHduSelection, LoaderOptions};
+
+/// Per-instrument loader settings, written once per site as TOML or JSON.
+///
+/// ```toml
+/// image_extname = "CCD"
+/// header_keys = ["Sample Theta", "Beamline Energy", "Proposal"]
+///
+/// [unit_overrides]
+/// Proposal = ""
+///
+/// [card_aliases]
+/// EXPOSURE = ["EXP_TIME"]
+/// ```
+///
+/// Every field is optional, missing ones keep the `LoaderOptions` defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstrumentConfig {
+    /// Index of the HDU holding the science image.
+    pub image_hdu: Option<usize>,
+    /// `EXTNAME` of the image HDU, takes precedence over `image_hdu`.
+    pub image_extname: Option<String>,
+    /// Header cards to extract, by keyword. Keywords of the built-in
+    /// `HeaderValue`s get their units, any other card is loaded as is.
+    pub header_keys: Vec<String>,
+    /// See `LoaderOptions::unit_overrides`.
+    pub unit_overrides: HashMap<String, String>,
+    /// See `LoaderOptions::card_aliases`.
+    pub card_aliases: HashMap<String, Vec<String>>,
+}
+
+impl InstrumentConfig {
+    pub fn from_json(text: &str) -> Result<Self, FitsLoaderError> {
+        serde_json::from_str(text).map_err(|e| FitsLoaderError::InvalidConfig(e.to_string()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, FitsLoaderError> {
+        toml::from_str(text).map_err(|e| FitsLoaderError::InvalidConfig(e.to_string()))
+    }
+
+    /// Reads a `.toml` or `.json` config file, picking the format by extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FitsLoaderError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::from_toml(&text),
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::from_json(&text),
+            _ => Err(FitsLoaderError::InvalidConfig(format!(
+                "{} is neither .toml nor .json",
+                path.display()
+            ))),
+        }
+    }
+
+    /// The header items to request from every file.
+    pub fn header_items(&self) -> Vec<HeaderValue> {
+        self.header_keys
+            .iter()
+            .map(|key| HeaderValue::from_hdu(key))
+            .collect()
+    }
+
+    /// Loader options with this instrument's settings applied.
+    pub fn options(&self) -> LoaderOptions {
+        let mut options = LoaderOptions {
+            unit_overrides: self.unit_overrides.clone(),
+            card_aliases: self.card_aliases.clone(),
+            ..LoaderOptions::default()
+        };
+        if let Some(name) = &self.image_extname {
+            options.image_hdu = ImageHduSelection::ExtName(name.clone());
+        } else if let Some(index) = self.image_hdu {
+            options.image_hdu = ImageHduSelection::Index(index);
+        }
+        options
+    }
+}
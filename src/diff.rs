@@ -0,0 +1,235 @@
+use polars::prelude::*;
+
+use crate::errors::FitsLoaderError;
+
+// ================== DataFrame Comparison ==================
+// Structured differences between two loads of the same data, e.g. a golden
+// frame and the output of a changed loader. Columns are matched by name, rows
+// by position.
+
+/// Default tolerance of `diff_dataframes`, relative to the larger magnitude.
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// A column held with different dtypes by the two frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DtypeMismatch {
+    pub column: String,
+    pub a: DataType,
+    pub b: DataType,
+}
+
+/// The rows, in ascending order, where a column differs between the frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueMismatch {
+    pub column: String,
+    pub rows: Vec<usize>,
+}
+
+/// Differences between two frames, see `diff_dataframes`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataFrameDiff {
+    /// Columns of the first frame missing from the second, in its order.
+    pub only_in_a: Vec<String>,
+    /// Columns of the second frame missing from the first, in its order.
+    pub only_in_b: Vec<String>,
+    pub dtype_mismatches: Vec<DtypeMismatch>,
+    /// `(a, b)` row counts when they differ. Values are still compared over
+    /// the rows both frames have.
+    pub height_mismatch: Option<(usize, usize)>,
+    pub value_mismatches: Vec<ValueMismatch>,
+}
+
+impl DataFrameDiff {
+    /// Whether the frames agree in schema, height and every value.
+    pub fn is_empty(&self) -> bool {
+        self == &DataFrameDiff::default()
+    }
+}
+
+/// Compares `a` and `b` with `DEFAULT_TOLERANCE`, see `diff_dataframes_with_tolerance`.
+pub fn diff_dataframes(a: &DataFrame, b: &DataFrame) -> Result<DataFrameDiff, FitsLoaderError> {
+    diff_dataframes_with_tolerance(a, b, DEFAULT_TOLERANCE)
+}
+
+/// Reports how `b` differs from `a`: the columns only one of them has, the
+/// shared columns whose dtypes differ, and per shared column the rows whose
+/// values differ.
+///
+/// Numeric values match when they differ by at most `tolerance` times the
+/// larger magnitude, or by `tolerance` below a magnitude of one; `NaN` matches
+/// `NaN` and null matches null. Image and other list or array cells are
+/// compared element by element the same way, and match only with the same
+/// number of elements. Columns of different dtypes are compared as `Float64`
+/// when both are numeric and not at all otherwise. Other values must be equal.
+pub fn diff_dataframes_with_tolerance(
+    a: &DataFrame,
+    b: &DataFrame,
+    tolerance: f64,
+) -> Result<DataFrameDiff, FitsLoaderError> {
+    let missing = |from: &DataFrame, other: &DataFrame| {
+        from.get_column_names()
+            .into_iter()
+            .filter(|name| !other.schema().contains(name))
+            .map(|name| name.to_string())
+            .collect()
+    };
+    let mut diff = DataFrameDiff {
+        only_in_a: missing(a, b),
+        only_in_b: missing(b, a),
+        height_mismatch: (a.height() != b.height()).then_some((a.height(), b.height())),
+        ..Default::default()
+    };
+    let height = a.height().min(b.height());
+    for left in a.get_columns() {
+        let Ok(right) = b.column(left.name()) else {
+            continue;
+        };
+        let (left, right) = (left.slice(0, height), right.slice(0, height));
+        if left.dtype() != right.dtype() {
+            diff.dtype_mismatches.push(DtypeMismatch {
+                column: left.name().to_string(),
+                a: left.dtype().clone(),
+                b: right.dtype().clone(),
+            });
+            if !(left.dtype().is_numeric() && right.dtype().is_numeric()) {
+                continue;
+            }
+        }
+        let rows = mismatched_rows(&left, &right, tolerance)?;
+        if !rows.is_empty() {
+            diff.value_mismatches.push(ValueMismatch {
+                column: left.name().to_string(),
+                rows,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+/// Rows where `left` and `right`, of the same length, differ.
+fn mismatched_rows(
+    left: &Column,
+    right: &Column,
+    tolerance: f64,
+) -> Result<Vec<usize>, FitsLoaderError> {
+    let nested = |dtype: &DataType| matches!(dtype, DataType::List(_) | DataType::Array(_, _));
+    let mut rows = vec![];
+    if nested(left.dtype()) {
+        for row in 0..left.len() {
+            let (l, r) = (cell_values(left, row)?, cell_values(right, row)?);
+            let equal = match (l, r) {
+                (Some(l), Some(r)) => {
+                    l.len() == r.len() && l.iter().zip(&r).all(|(&l, &r)| close(l, r, tolerance))
+                }
+                (l, r) => l.is_none() && r.is_none(),
+            };
+            if !equal {
+                rows.push(row);
+            }
+        }
+    } else if left.dtype().is_numeric() {
+        let (l, r) = (
+            left.cast(&DataType::Float64)?,
+            right.cast(&DataType::Float64)?,
+        );
+        for (row, (l, r)) in l.f64()?.into_iter().zip(r.f64()?).enumerate() {
+            if !close(l, r, tolerance) {
+                rows.push(row);
+            }
+        }
+    } else {
+        for row in 0..left.len() {
+            if left.get(row)? != right.get(row)? {
+                rows.push(row);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// The elements of the list or array cell in `row`, flattened and as `f64`,
+/// `None` for a null cell. Non-numeric elements fail the cast.
+fn cell_values(column: &Column, row: usize) -> Result<Option<Vec<Option<f64>>>, FitsLoaderError> {
+    let mut cell = column.as_materialized_series().slice(row as i64, 1);
+    if cell.null_count() == 1 {
+        return Ok(None);
+    }
+    while matches!(cell.dtype(), DataType::List(_) | DataType::Array(_, _)) {
+        cell = cell.explode()?;
+    }
+    let values = cell.cast(&DataType::Float64)?;
+    Ok(Some(values.f64()?.into_iter().collect()))
+}
+
+/// Whether two optional numbers match within `tolerance`, see
+/// `diff_dataframes_with_tolerance`.
+fn close(a: Option<f64>, b: Option<f64>, tolerance: f64) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) if a.is_nan() || b.is_nan() => a.is_nan() && b.is_nan(),
+        (Some(a), Some(b)) => a == b || (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::HeaderValue;
+    use crate::loader::read_experiment;
+    use crate::test_util::{test_dir, u16_image, write_fits};
+
+    #[test]
+    fn golden_loads_match_until_a_file_changes() {
+        let dir = test_dir("diff_golden");
+        for (i, exposure) in ["0.5", "1.0"].into_iter().enumerate() {
+            write_fits(
+                &dir.join(format!("film_{:05}.fits", i + 1)),
+                &[("EXPOSURE", exposure)],
+                Some(u16_image(2, 2, &[1, 2, 3, 4])),
+            );
+        }
+        let load = || read_experiment(dir.to_str().unwrap(), &[HeaderValue::Exposure]).unwrap();
+        let golden = load();
+        assert!(diff_dataframes(&golden, &load()).unwrap().is_empty());
+
+        write_fits(
+            &dir.join("film_00002.fits"),
+            &[("EXPOSURE", "1.0")],
+            Some(u16_image(2, 2, &[1, 2, 3, 5])),
+        );
+        let diff = diff_dataframes(&golden, &load()).unwrap();
+        assert!(diff.value_mismatches.contains(&ValueMismatch {
+            column: "Raw".into(),
+            rows: vec![1],
+        }));
+        assert!(diff.only_in_a.is_empty() && diff.only_in_b.is_empty());
+        assert!(diff.dtype_mismatches.is_empty());
+        assert_eq!(diff.height_mismatch, None);
+    }
+
+    #[test]
+    fn schema_and_height_differences_are_reported() {
+        let a = df!("Exposure" => [1.0, 2.0], "Note" => ["a", "b"]).unwrap();
+        let b = df!("Exposure" => [1i64, 3, 4], "Frame" => [1u32, 2, 3]).unwrap();
+        let diff = diff_dataframes(&a, &b).unwrap();
+        assert_eq!(diff.only_in_a, ["Note"]);
+        assert_eq!(diff.only_in_b, ["Frame"]);
+        assert_eq!(
+            diff.dtype_mismatches,
+            [DtypeMismatch {
+                column: "Exposure".into(),
+                a: DataType::Float64,
+                b: DataType::Int64,
+            }]
+        );
+        assert_eq!(diff.height_mismatch, Some((2, 3)));
+        // Numeric columns of differing dtypes are still compared as `Float64`.
+        assert_eq!(
+            diff.value_mismatches,
+            [ValueMismatch {
+                column: "Exposure".into(),
+                rows: vec![1],
+            }]
+        );
+    }
+}
@@ -1,4 +1,17 @@
+use crate::options::{ImageHduSelection, LoaderOptions};
+
 /// Represents different types of experiments.
+///
+/// * `Xrr` - specular reflectivity. The observable is the specular spot on the
+///   detector, so the frame is kept for ROI integration and q is calculated from
+///   the sample and CCD angles.
+/// * `Xrs` - near-edge absorption/scattering. The observable is the transmitted
+///   beam, integrated over the whole detector into `Total Counts` per energy.
+/// * `Other` - every header card, no experiment specific processing.
+///
+/// `crate::reduce::intensity_for` computes the observable of a type as a
+/// uniformly named `Intensity` column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ExperimentType {
     Xrr,
     Xrs,
@@ -7,6 +20,7 @@ pub enum ExperimentType {
 
 impl ExperimentType {
     /// Creates an `ExperimentType` from a string.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(exp_type: &str) -> Result<Self, crate::errors::FitsLoaderError> {
         match exp_type.to_lowercase().as_str() {
             "xrr" => Ok(ExperimentType::Xrr),
@@ -18,6 +32,16 @@ impl ExperimentType {
         }
     }
 
+    /// Canonical lowercase spelling accepted by `from_str`, also stored in the
+    /// `Experiment Type` column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExperimentType::Xrr => "xrr",
+            ExperimentType::Xrs => "xrs",
+            ExperimentType::Other => "other",
+        }
+    }
+
     /// Retrieves the relevant header keys for the experiment type.
     pub fn get_keys(&self) -> Vec<HeaderValue> {
         match self {
@@ -36,6 +60,22 @@ impl ExperimentType {
         }
     }
 
+    /// Default loader options for the experiment type.
+    ///
+    /// For `Xrs` this enables `Total Counts`, which is the primary observable of a
+    /// transmission measurement. `Xrr` and `Other` use the plain defaults. Every
+    /// type records itself in `LoaderOptions::experiment_type`.
+    pub fn default_options(&self) -> LoaderOptions {
+        let mut options = LoaderOptions {
+            experiment_type: Some(*self),
+            ..LoaderOptions::default()
+        };
+        if let ExperimentType::Xrs = self {
+            options.image.total_counts = true;
+        }
+        options
+    }
+
     /// Retrieves the header names for display purposes.
     pub fn names(&self) -> Vec<&str> {
         match self {
@@ -55,7 +95,78 @@ impl ExperimentType {
     }
 }
 
+/// Detectors with known-good loader settings, so that the image HDU and frame
+/// checks do not have to be configured for every load.
+///
+/// * `PrincetonMTE` - the Princeton Instruments PI-MTE of the reflectometer:
+///   primary header, two extensions and the unsigned 16-bit 2048 x 2048 frame
+///   in the third HDU, the layout the loader defaults to.
+/// * `Andor` - Andor cameras, whose files do not put the frame at a fixed HDU
+///   index, so the largest image extension is read.
+///
+/// Both enable the frame quality checks with saturation at the 16-bit limit.
+/// No gain is set: pixels stay in ADU until `ImageOptions::gain` is given.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetectorPreset {
+    PrincetonMTE,
+    Andor,
+}
+
+impl DetectorPreset {
+    /// Creates a `DetectorPreset` from its `name`, ignoring case.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(name: &str) -> Result<Self, crate::errors::FitsLoaderError> {
+        match name.to_lowercase().as_str() {
+            "princeton_mte" => Ok(DetectorPreset::PrincetonMTE),
+            "andor" => Ok(DetectorPreset::Andor),
+            _ => Err(crate::errors::FitsLoaderError::InvalidConfig(format!(
+                "Unknown detector preset: {}",
+                name
+            ))),
+        }
+    }
+
+    /// Canonical lowercase spelling accepted by `from_str`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DetectorPreset::PrincetonMTE => "princeton_mte",
+            DetectorPreset::Andor => "andor",
+        }
+    }
+
+    /// Overwrites the settings of the preset in `options`, leaving every other
+    /// field as it is. Fields can still be changed afterwards to override the
+    /// preset.
+    pub fn apply(&self, options: &mut LoaderOptions) {
+        let quality = options.image.quality.get_or_insert_with(Default::default);
+        quality.saturation_level = u16::MAX as f64;
+        match self {
+            DetectorPreset::PrincetonMTE => {
+                options.image_hdu = ImageHduSelection::Index(2);
+                quality.expected_shape = Some((2048, 2048));
+            }
+            DetectorPreset::Andor => {
+                options.image_hdu = ImageHduSelection::LargestImage;
+            }
+        }
+    }
+
+    /// The default loader options with the preset applied.
+    pub fn options(&self) -> LoaderOptions {
+        let mut options = LoaderOptions::default();
+        self.apply(&mut options);
+        options
+    }
+}
+
+/// Cards `HeaderValue::BeamlineEnergy` is read from, in order of preference.
+/// Older files carry the monochromator reading as `Mono Energy` or `Photon
+/// Energy` instead; further names go in `LoaderOptions::card_aliases` under
+/// `"Beamline Energy"`. The card used is reported in the `Energy Source` column.
+pub const ENERGY_CARDS: [&str; 3] = ["Beamline Energy", "Mono Energy", "Photon Energy"];
+
 /// Represents different header values.
+#[derive(Clone)]
 pub enum HeaderValue {
     SampleTheta,
     CCDTheta,
@@ -65,9 +176,62 @@ pub enum HeaderValue {
     HorizontalExitSlitSize,
     HigherOrderSuppressor,
     Exposure,
+    /// Detector pixel pitch, needed to map pixels onto scattering angles.
+    PixelSize,
+    /// Sample to detector distance.
+    SampleDetectorDistance,
+    /// Transmission of the absorber foils in the beam, `1` without any.
+    FilterTransmission,
+    /// Incident flux measured by a beamline diode, read from the first of the
+    /// `I0` or `Izero` cards present.
+    I0Monitor,
+    /// Detector temperature, for monitoring drift over a run. Optional: files
+    /// without the card load with a null value.
+    CCDTemperature,
+    /// Electron energy of the storage ring. Optional like `CCDTemperature`.
+    RingEnergy,
+    /// Gap of the elliptically polarizing undulator. Together with
+    /// `EPUHarmonic` it tells which harmonic the monochromator passes, e.g. to
+    /// flag higher-harmonic contamination of XRS scans. Optional like
+    /// `CCDTemperature`.
+    EPUGap,
+    /// Undulator harmonic the beamline is tuned to, optional like `EPUGap`.
+    EPUHarmonic,
+    /// Any other header card, loaded under its keyword without a unit.
+    ///
+    /// A keyword with glob characters (`*`, `?`, `[...]`, `{a,b}`), e.g. `Mono*`,
+    /// loads every card it matches as its own column, the way cards load with
+    /// no header items, and matching no card is not an error. Files can then
+    /// differ in their columns: a card missing from one file is null in its
+    /// rows like for any schema difference, and counts as a new column for
+    /// `LoaderOptions::strict_schema`.
+    Custom(String),
 }
 
 impl HeaderValue {
+    /// Looks up the header value for an HDU keyword, falling back to `Custom`.
+    pub fn from_hdu(key: &str) -> HeaderValue {
+        match key {
+            "Sample Theta" => HeaderValue::SampleTheta,
+            "CCD Theta" => HeaderValue::CCDTheta,
+            "Beamline Energy" => HeaderValue::BeamlineEnergy,
+            "Beam Current" => HeaderValue::BeamCurrent,
+            "EPU Polarization" => HeaderValue::EPUPolarization,
+            "Horizontal Exit Slit Size" => HeaderValue::HorizontalExitSlitSize,
+            "Higher Order Suppressor" => HeaderValue::HigherOrderSuppressor,
+            "EXPOSURE" => HeaderValue::Exposure,
+            "PIXSIZE" => HeaderValue::PixelSize,
+            "SDD" => HeaderValue::SampleDetectorDistance,
+            "Filter Transmission" => HeaderValue::FilterTransmission,
+            "I0 Monitor" => HeaderValue::I0Monitor,
+            "CCDTEMP" => HeaderValue::CCDTemperature,
+            "Ring Energy" => HeaderValue::RingEnergy,
+            "EPU Gap" => HeaderValue::EPUGap,
+            "EPU Harmonic" => HeaderValue::EPUHarmonic,
+            other => HeaderValue::Custom(other.to_string()),
+        }
+    }
+
     /// Returns the unit associated with the header value.
     pub fn unit(&self) -> &str {
         match self {
@@ -79,6 +243,15 @@ impl HeaderValue {
             HeaderValue::HorizontalExitSlitSize => "[um]",
             HeaderValue::HigherOrderSuppressor => "[mm]",
             HeaderValue::Exposure => "[s]",
+            HeaderValue::PixelSize => "[um]",
+            HeaderValue::SampleDetectorDistance => "[mm]",
+            HeaderValue::FilterTransmission => "",
+            HeaderValue::I0Monitor => "",
+            HeaderValue::CCDTemperature => "[C]",
+            HeaderValue::RingEnergy => "[GeV]",
+            HeaderValue::EPUGap => "[mm]",
+            HeaderValue::EPUHarmonic => "",
+            HeaderValue::Custom(_) => "",
         }
     }
 
@@ -93,6 +266,53 @@ impl HeaderValue {
             HeaderValue::HorizontalExitSlitSize => "Horizontal Exit Slit Size",
             HeaderValue::HigherOrderSuppressor => "Higher Order Suppressor",
             HeaderValue::Exposure => "EXPOSURE",
+            HeaderValue::PixelSize => "PIXSIZE",
+            HeaderValue::SampleDetectorDistance => "SDD",
+            HeaderValue::FilterTransmission => "Filter Transmission",
+            HeaderValue::I0Monitor => "I0 Monitor",
+            HeaderValue::CCDTemperature => "CCDTEMP",
+            HeaderValue::RingEnergy => "Ring Energy",
+            HeaderValue::EPUGap => "EPU Gap",
+            HeaderValue::EPUHarmonic => "EPU Harmonic",
+            HeaderValue::Custom(key) => key,
+        }
+    }
+
+    /// Card names tried in order when reading the value, the first one present
+    /// wins. Only `Exposure`, `I0Monitor`, `CCDTemperature`, `BeamlineEnergy`
+    /// (see `ENERGY_CARDS`) and the undulator cards have alternatives,
+    /// `LoaderOptions::card_aliases` extends the list, e.g. with the card names
+    /// of another beamline.
+    pub fn candidates(&self) -> Vec<&str> {
+        match self {
+            HeaderValue::BeamlineEnergy => ENERGY_CARDS.to_vec(),
+            HeaderValue::Exposure => vec!["EXPOSURE", "EXPTIME", "ITIME"],
+            HeaderValue::I0Monitor => vec!["I0 Monitor", "I0", "Izero", "IZERO"],
+            HeaderValue::CCDTemperature => vec!["CCDTEMP", "CCD-TEMP", "CCD Temperature"],
+            HeaderValue::EPUGap => vec!["EPU Gap", "EPUGAP", "Undulator Gap"],
+            HeaderValue::EPUHarmonic => vec!["EPU Harmonic", "HARMONIC", "Undulator Harmonic"],
+            other => vec![other.hdu()],
+        }
+    }
+
+    /// Whether a file may lack the card: an optional value is null for such a
+    /// file instead of failing the load.
+    pub fn is_optional(&self) -> bool {
+        matches!(
+            self,
+            HeaderValue::CCDTemperature
+                | HeaderValue::RingEnergy
+                | HeaderValue::EPUGap
+                | HeaderValue::EPUHarmonic
+        )
+    }
+
+    /// Whether this is a `Custom` keyword holding glob characters, which load
+    /// every matching card.
+    pub fn is_wildcard(&self) -> bool {
+        match self {
+            HeaderValue::Custom(key) => key.contains(['*', '?', '[', '{']),
+            _ => false,
         }
     }
 
@@ -107,6 +327,30 @@ impl HeaderValue {
             HeaderValue::HorizontalExitSlitSize => "Horizontal Exit Slit Size [um]",
             HeaderValue::HigherOrderSuppressor => "Higher Order Suppressor [mm]",
             HeaderValue::Exposure => "EXPOSURE [s]",
+            HeaderValue::PixelSize => "PIXSIZE [um]",
+            HeaderValue::SampleDetectorDistance => "SDD [mm]",
+            HeaderValue::FilterTransmission => "Filter Transmission",
+            HeaderValue::I0Monitor => "I0 Monitor",
+            HeaderValue::CCDTemperature => "CCDTEMP [C]",
+            HeaderValue::RingEnergy => "Ring Energy [GeV]",
+            HeaderValue::EPUGap => "EPU Gap [mm]",
+            HeaderValue::EPUHarmonic => "EPU Harmonic",
+            HeaderValue::Custom(key) => key,
         }
     }
 }
+
+/// Card names known only at runtime, e.g. from a config file or user input,
+/// map onto the known values by `HeaderValue::from_hdu`, anything else
+/// becomes `Custom`.
+impl From<&str> for HeaderValue {
+    fn from(key: &str) -> Self {
+        HeaderValue::from_hdu(key)
+    }
+}
+
+impl From<String> for HeaderValue {
+    fn from(key: String) -> Self {
+        HeaderValue::from_hdu(&key)
+    }
+}
@@ -1,5 +1,6 @@
 use polars::error::PolarsError;
 use std::io::Error as IoError;
+use std::path::Path;
 use thiserror::Error;
 
 /// Custom error type for FITS processing.
@@ -14,6 +15,10 @@ pub enum FitsLoaderError {
     #[error("IO error: {0}")]
     IoError(#[from] IoError),
 
+    #[cfg(feature = "nexus")]
+    #[error("HDF5 error: {0}")]
+    Hdf5Error(#[from] hdf5_metno::Error),
+
     #[error("Invalid experiment type: {0}")]
     InvalidExperimentType(String),
 
@@ -23,12 +28,141 @@ pub enum FitsLoaderError {
     #[error("Missing required header key: {0}")]
     MissingHeaderKey(String),
 
+    #[error("Header items missing from {path}: {items:?}")]
+    MissingHeaderItems { path: String, items: Vec<String> },
+
+    #[error("Missing required column: {0}")]
+    MissingColumn(String),
+
+    #[error("Unexpected columns in {path}: {columns:?}")]
+    SchemaMismatch { path: String, columns: Vec<String> },
+
+    #[error("Checksum mismatch in {path} (HDU {hdu})")]
+    ChecksumMismatch { path: String, hdu: usize },
+
+    #[error("Image shape {found:?} in {path} differs from {expected:?}")]
+    ImageShapeMismatch {
+        path: String,
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+
+    #[error(
+        "NAXIS cards of {path} give {header:?}, {expected_bytes} bytes of data, \
+         the data unit spans {found_bytes}"
+    )]
+    NaxisMismatch {
+        path: String,
+        header: Vec<usize>,
+        expected_bytes: usize,
+        found_bytes: usize,
+    },
+
+    #[error("Image of shape {shape:?} in {path} is neither a 2D frame nor a 3D cube")]
+    UnsupportedDimensionality { path: String, shape: Vec<usize> },
+
+    #[error("Frames {start}..{end} out of range for the {depth} slices of {path}")]
+    FrameOutOfRange {
+        path: String,
+        start: usize,
+        end: usize,
+        depth: usize,
+    },
+
+    #[error("Slice table of {path} has {rows} rows for the {depth} slices of its cube")]
+    SliceTableMismatch {
+        path: String,
+        rows: usize,
+        depth: usize,
+    },
+
+    #[error("No HDU {index} in {path}, which holds {count}")]
+    HduOutOfRange {
+        path: String,
+        index: usize,
+        count: usize,
+    },
+
+    #[error("Columns {0:?} hold lists or structs, which CSV cannot store; see write_csv_no_image")]
+    NestedColumns(Vec<String>),
+
+    #[error("Invalid loader config: {0}")]
+    InvalidConfig(String),
+
     #[error("Unsupported image data type")]
     UnsupportedImageData,
 
     #[error("No data found")]
     NoData,
 
+    #[error("Empty image data in {0}")]
+    EmptyImage(String),
+
     #[error("Other error: {0}")]
     Other(String),
+
+    #[error("Failed to load {path}: {source}")]
+    File {
+        path: String,
+        source: Box<FitsLoaderError>,
+    },
+}
+
+impl FitsLoaderError {
+    /// Fills in the file path for errors raised while only the HDU was known.
+    pub fn with_path(self, path: &Path) -> Self {
+        match self {
+            FitsLoaderError::EmptyImage(p) if p.is_empty() => {
+                FitsLoaderError::EmptyImage(path.display().to_string())
+            }
+            FitsLoaderError::ImageShapeMismatch {
+                path: p,
+                expected,
+                found,
+            } if p.is_empty() => FitsLoaderError::ImageShapeMismatch {
+                path: path.display().to_string(),
+                expected,
+                found,
+            },
+            FitsLoaderError::UnsupportedDimensionality { path: p, shape } if p.is_empty() => {
+                FitsLoaderError::UnsupportedDimensionality {
+                    path: path.display().to_string(),
+                    shape,
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+/// Maps loader errors onto the closest built-in Python exception, so the bindings
+/// raise e.g. `FileNotFoundError` or `ValueError` instead of aborting.
+#[cfg(feature = "python")]
+impl From<FitsLoaderError> for pyo3::PyErr {
+    fn from(err: FitsLoaderError) -> Self {
+        use pyo3::exceptions::{
+            PyFileNotFoundError, PyKeyError, PyOSError, PyRuntimeError, PyTypeError, PyValueError,
+        };
+        let message = err.to_string();
+        // A failing file keeps the exception type of its underlying error.
+        let mut err = err;
+        while let FitsLoaderError::File { source, .. } = err {
+            err = *source;
+        }
+        match err {
+            FitsLoaderError::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                PyFileNotFoundError::new_err(message)
+            }
+            FitsLoaderError::IoError(_) => PyOSError::new_err(message),
+            FitsLoaderError::NoData => PyFileNotFoundError::new_err(message),
+            FitsLoaderError::MissingHeaderKey(_)
+            | FitsLoaderError::MissingHeaderItems { .. }
+            | FitsLoaderError::MissingColumn(_) => PyKeyError::new_err(message),
+            FitsLoaderError::UnsupportedImageData
+            | FitsLoaderError::UnsupportedDimensionality { .. }
+            | FitsLoaderError::NestedColumns(_) => PyTypeError::new_err(message),
+            FitsLoaderError::PolarsError(_) => PyRuntimeError::new_err(message),
+            _ => PyValueError::new_err(message),
+        }
+    }
 }
@@ -0,0 +1,544 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use astrors_fork::io::hdulist::HDU;
+use astrors_fork::io::hdus::bintable::bintablehdu::BinTableHDU;
+use astrors_fork::io::hdus::image::imagehdu::ImageHDU;
+use astrors_fork::io::hdus::image::ImageData;
+use astrors_fork::io::hdus::primaryhdu::PrimaryHDU;
+use astrors_fork::io::header::card::Card;
+use astrors_fork::io::Header;
+use ndarray::{Array2, ArrayD};
+use polars::prelude::*;
+
+use crate::checksum::{data_units, parse_header, BLOCK};
+use crate::errors::FitsLoaderError;
+use crate::io::{astrors_layout, image_pixels};
+use crate::loader::is_fits_file;
+use crate::options::{ImageHduSelection, TableHduSelection};
+
+// ================== FITS Files ==================
+
+/// A CCD FITS file read into memory, primary HDU first.
+pub struct CcdFits {
+    pub path: PathBuf,
+    pub hdus: Vec<HDU>,
+    /// Copy of the primary HDU as an image, when it holds data.
+    primary_image: Option<ImageHDU>,
+}
+
+impl CcdFits {
+    /// Reads every HDU of the file at `path`.
+    ///
+    /// The file is opened from the `Path` itself, so non-UTF-8 names and Windows
+    /// long paths work, where `fits::fromfile` needs a `&str`. Structurally broken
+    /// files, on which the FITS reader does not return or panics, are reported as
+    /// `FitsError` instead.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, FitsLoaderError> {
+        let path = path.as_ref();
+        if !is_fits_file(path) {
+            return Err(FitsLoaderError::NoData);
+        }
+        let units = data_units(path)?;
+        let hdus = std::panic::catch_unwind(|| read_hdus(path, &units)).map_err(|_| {
+            FitsLoaderError::FitsError(format!("Failed to parse {}", path.display()))
+        })??;
+        let primary_image = match hdus.first() {
+            Some(HDU::Primary(primary)) => primary_as_image(primary),
+            _ => None,
+        };
+        Ok(CcdFits {
+            path: path.to_path_buf(),
+            hdus,
+            primary_image,
+        })
+    }
+
+    pub fn primary(&self) -> Result<&PrimaryHDU, FitsLoaderError> {
+        match self.hdus.first() {
+            Some(HDU::Primary(hdu)) => Ok(hdu),
+            _ => Err(FitsLoaderError::NoData),
+        }
+    }
+
+    /// Header of HDU `index`, whichever its type.
+    pub fn header(&self, index: usize) -> Result<&Header, FitsLoaderError> {
+        match self.hdus.get(index) {
+            Some(hdu) => Ok(hdu_header(hdu)),
+            None => Err(FitsLoaderError::HduOutOfRange {
+                path: self.path.display().to_string(),
+                index,
+                count: self.hdus.len(),
+            }),
+        }
+    }
+
+    /// The image HDU picked by `selection`. Data in the primary HDU comes as an
+    /// image HDU with the primary header.
+    pub fn image_hdu(&self, selection: &ImageHduSelection) -> Result<&ImageHDU, FitsLoaderError> {
+        match &self.hdus[self.image_hdu_index(selection)?] {
+            HDU::Image(hdu) => Ok(hdu),
+            HDU::Primary(_) => self.primary_image.as_ref().ok_or(FitsLoaderError::NoData),
+            _ => Err(FitsLoaderError::NoData),
+        }
+    }
+
+    /// Index of the image HDU picked by `selection`, `0` for the primary HDU.
+    pub fn image_hdu_index(&self, selection: &ImageHduSelection) -> Result<usize, FitsLoaderError> {
+        let extensions = self
+            .hdus
+            .iter()
+            .enumerate()
+            .filter_map(|(i, hdu)| match hdu {
+                HDU::Image(hdu) => Some((i, hdu)),
+                _ => None,
+            });
+        let primary = self.primary_image.as_ref().map(|image| (0, image));
+        let images = primary.into_iter().chain(extensions.clone());
+        let index = match selection {
+            ImageHduSelection::Index(index) => images.map(|(i, _)| i).find(|i| i == index),
+            ImageHduSelection::LargestImage => images
+                .max_by_key(|(_, hdu)| hdu.data.get_shape().iter().product::<usize>())
+                .map(|(i, _)| i),
+            ImageHduSelection::PreferExtension => extensions.chain(primary).map(|(i, _)| i).next(),
+            ImageHduSelection::PreferPrimary => images.map(|(i, _)| i).next(),
+            ImageHduSelection::ExtName(name) => images
+                .filter(|(_, hdu)| {
+                    hdu.header.get_card("EXTNAME").is_some_and(|card| {
+                        card.value.to_string().trim().trim_matches('\'').trim() == name
+                    })
+                })
+                .map(|(i, _)| i)
+                .next(),
+        };
+        index.ok_or(FitsLoaderError::NoData)
+    }
+
+    /// The binary table extension picked by `selection`, a HDU index or an
+    /// `EXTNAME`, as a DataFrame with one column per table field.
+    ///
+    /// The fields are decoded from the file by their `TFORMn` code. Fields with
+    /// a repeat count above one become list columns, `A` fields strings, and
+    /// fields with `TSCALn`/`TZEROn` are scaled into `Float64`.
+    pub fn get_table(
+        &self,
+        selection: impl Into<TableHduSelection>,
+    ) -> Result<DataFrame, FitsLoaderError> {
+        let is_table = |hdu: &HDU| matches!(hdu, HDU::BinTable(_));
+        let index = match selection.into() {
+            TableHduSelection::Index(index) => {
+                Some(index).filter(|&i| self.hdus.get(i).is_some_and(is_table))
+            }
+            TableHduSelection::ExtName(name) => self.hdus.iter().position(|hdu| {
+                is_table(hdu)
+                    && hdu_header(hdu).get_card("EXTNAME").is_some_and(|card| {
+                        card.value.to_string().trim().trim_matches('\'').trim() == name
+                    })
+            }),
+        };
+        let index = index.ok_or(FitsLoaderError::NoData)?;
+        let units = data_units(&self.path)?;
+        let (start, len) = units.get(index).copied().ok_or(FitsLoaderError::NoData)?;
+        let header_start = header_start(&units, index);
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(header_start as u64))?;
+        let mut bytes = vec![0u8; start + len - header_start];
+        file.read_exact(&mut bytes)?;
+        let (cards, header_len) = parse_header(&bytes)
+            .ok_or_else(|| FitsLoaderError::FitsError("Unterminated table header".into()))?;
+        read_bintable(&cards, &bytes[header_len..])
+    }
+
+    /// Pixels of the default image HDU with `BZERO` applied.
+    pub fn get_data(&self) -> Result<Array2<f64>, FitsLoaderError> {
+        image_pixels(self.image_hdu(&ImageHduSelection::default())?)
+    }
+}
+
+/// Header of HDU `index` of the file at `path`, read without decoding the data
+/// unit of any HDU.
+///
+/// The file is checked like `CcdFits::new` and then read only from where the
+/// header starts, found from the block structure, so this is what to use when
+/// just the cards of a file are needed.
+pub fn read_header(path: impl AsRef<Path>, index: usize) -> Result<Header, FitsLoaderError> {
+    let path = path.as_ref();
+    if !is_fits_file(path) {
+        return Err(FitsLoaderError::NoData);
+    }
+    let units = data_units(path)?;
+    if index >= units.len() {
+        return Err(FitsLoaderError::HduOutOfRange {
+            path: path.display().to_string(),
+            index,
+            count: units.len(),
+        });
+    }
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(header_start(&units, index) as u64))?;
+    let mut header = Header::new();
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        header.read_from_file(&mut file)
+    }))
+    .map_err(|_| FitsLoaderError::FitsError(format!("Failed to parse {}", path.display())))??;
+    Ok(header)
+}
+
+/// Offset of the header of HDU `index` in a file laid out as `units`, the
+/// data units found by `data_units`.
+fn header_start(units: &[(usize, usize)], index: usize) -> usize {
+    match index.checked_sub(1).map(|i| units[i]) {
+        Some((start, len)) => start + len.div_ceil(BLOCK) * BLOCK,
+        None => 0,
+    }
+}
+
+/// Copy of the data of `primary` as an image HDU, `None` when it holds none.
+fn primary_as_image(primary: &PrimaryHDU) -> Option<ImageHDU> {
+    let data = match &primary.data {
+        ImageData::U8(data) => ImageData::U8(data.clone()),
+        ImageData::I16(data) => ImageData::I16(data.clone()),
+        ImageData::I32(data) => ImageData::I32(data.clone()),
+        ImageData::F32(data) => ImageData::F32(data.clone()),
+        ImageData::F64(data) => ImageData::F64(data.clone()),
+        _ => return None,
+    };
+    let shape = data.get_shape();
+    if shape.is_empty() || shape.contains(&0) {
+        return None;
+    }
+    let mut header = Header::new();
+    for card in primary.header.iter() {
+        header.add_card(card);
+    }
+    Some(ImageHDU::new(header, data))
+}
+
+fn hdu_header(hdu: &HDU) -> &Header {
+    match hdu {
+        HDU::Primary(hdu) => &hdu.header,
+        HDU::Image(hdu) => &hdu.header,
+        HDU::Table(hdu) => &hdu.header,
+        HDU::BinTable(hdu) => &hdu.header,
+    }
+}
+
+/// Reads the HDUs laid out as `units`, the data units found by `data_units`.
+/// Unlike `fits::fromfile`, which retries a failing HDU forever, the first error
+/// is returned. Binary tables keep only their header, the rows are decoded on
+/// demand by `CcdFits::get_table`.
+fn read_hdus(path: &Path, units: &[(usize, usize)]) -> Result<Vec<HDU>, FitsLoaderError> {
+    let mut file = fs::File::open(path)?;
+    let mut hdus = vec![];
+    for &(start, len) in units {
+        let position = file.stream_position()?;
+        let mut header = Header::new();
+        header.read_from_file(&mut file)?;
+        let xtension = header
+            .get_card("XTENSION")
+            .map(|card| card.value.to_string());
+        if xtension.is_some_and(|value| value.contains("BINTABLE")) {
+            hdus.push(HDU::BinTable(BinTableHDU::new(header, DataFrame::empty())));
+        } else {
+            file.seek(SeekFrom::Start(position))?;
+            hdus.push(HDU::read_from_file(&mut file, Some(hdus.is_empty()))?);
+        }
+        file.seek(SeekFrom::Start(
+            (start + len.div_ceil(BLOCK) * BLOCK) as u64,
+        ))?;
+    }
+    Ok(hdus)
+}
+
+// ================== Binary Tables ==================
+// astrors does not decode the rows of a BINTABLE, so the fields are read from
+// the data unit here. Variable-length arrays (`P`/`Q`), complex values and bit
+// arrays are not supported.
+
+/// Repeat count and type code of a `TFORMn` value such as `1J` or `20A`.
+fn parse_tform(tform: &str) -> Option<(usize, char)> {
+    let split = tform.find(|c: char| !c.is_ascii_digit())?;
+    let repeat = match &tform[..split] {
+        "" => 1,
+        digits => digits.parse().ok()?,
+    };
+    Some((repeat, tform[split..].chars().next()?))
+}
+
+/// Bytes per element of the fixed-width type codes.
+fn tform_width(code: char) -> Option<usize> {
+    match code {
+        'L' | 'B' | 'A' => Some(1),
+        'I' => Some(2),
+        'J' | 'E' => Some(4),
+        'K' | 'D' => Some(8),
+        _ => None,
+    }
+}
+
+/// One field of every row as a column. Elements of `size` bytes are converted by
+/// `element` and grouped into one list per row for `repeat > 1`.
+fn field_series<T, N>(
+    name: &str,
+    rows: &[&[u8]],
+    repeat: usize,
+    size: usize,
+    element: impl Fn(&[u8]) -> T,
+) -> Series
+where
+    Series: NamedFrom<Vec<T>, [T]> + NamedFrom<Vec<Series>, N>,
+    N: ?Sized,
+{
+    let values = |row: &[u8]| row.chunks_exact(size).map(&element).collect::<Vec<_>>();
+    if repeat == 1 {
+        Series::new(
+            name.into(),
+            rows.iter().map(|row| element(row)).collect::<Vec<_>>(),
+        )
+    } else {
+        let lists: Vec<Series> = rows
+            .iter()
+            .map(|row| Series::new(PlSmallStr::EMPTY, values(row)))
+            .collect();
+        Series::new(name.into(), lists)
+    }
+}
+
+/// A numeric element of type `code` as `f64`, for scaling by `TSCALn`/`TZEROn`.
+fn physical(code: char, b: &[u8]) -> f64 {
+    match code {
+        'B' => b[0] as f64,
+        'I' => i16::from_be_bytes([b[0], b[1]]) as f64,
+        'J' => i32::from_be_bytes(b[..4].try_into().unwrap()) as f64,
+        'K' => i64::from_be_bytes(b[..8].try_into().unwrap()) as f64,
+        'E' => f32::from_be_bytes(b[..4].try_into().unwrap()) as f64,
+        _ => f64::from_be_bytes(b[..8].try_into().unwrap()),
+    }
+}
+
+/// Decodes the rows of a binary table data unit described by the header
+/// `cards`, as parsed by `parse_header`.
+fn read_bintable(cards: &[(String, String)], bytes: &[u8]) -> Result<DataFrame, FitsLoaderError> {
+    let text = |key: &str| {
+        cards
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    };
+    let card = |key: &str| text(key).and_then(|value| value.parse::<i64>().ok());
+    let float = |key: &str| text(key).and_then(|value| value.replace('D', "E").parse::<f64>().ok());
+    let row_len = card("NAXIS1").unwrap_or(0).max(0) as usize;
+    let nrows = card("NAXIS2").unwrap_or(0).max(0) as usize;
+    if bytes.len() < row_len * nrows {
+        return Err(FitsLoaderError::FitsError("Truncated binary table".into()));
+    }
+    let rows: Vec<_> = bytes[..row_len * nrows]
+        .chunks_exact(row_len.max(1))
+        .collect();
+
+    let mut columns = vec![];
+    let mut offset = 0;
+    for n in 1..=card("TFIELDS").unwrap_or(0) {
+        let tform = text(&format!("TFORM{}", n)).unwrap_or_default();
+        let name = text(&format!("TTYPE{}", n)).unwrap_or(format!("Column {}", n));
+        let unsupported =
+            || FitsLoaderError::FitsError(format!("Unsupported table format {}", tform));
+        let (repeat, code) = parse_tform(&tform).ok_or_else(unsupported)?;
+        let width = repeat * tform_width(code).ok_or_else(unsupported)?;
+        if offset + width > row_len {
+            return Err(FitsLoaderError::FitsError(format!(
+                "Field {} exceeds the row length {}",
+                name, row_len
+            )));
+        }
+        let field: Vec<&[u8]> = rows
+            .iter()
+            .map(|row| &row[offset..offset + width])
+            .collect();
+        offset += width;
+        if repeat == 0 {
+            continue;
+        }
+
+        let scale = float(&format!("TSCAL{}", n)).unwrap_or(1.0);
+        let zero = float(&format!("TZERO{}", n)).unwrap_or(0.0);
+        let scaled = (scale, zero) != (1.0, 0.0);
+        let series = match code {
+            'A' => Series::new(
+                name.as_str().into(),
+                field
+                    .iter()
+                    .map(|b| {
+                        String::from_utf8_lossy(b)
+                            .trim_end_matches(['\0', ' '])
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            'L' => field_series(&name, &field, repeat, 1, |b| match b[0] {
+                b'T' => Some(true),
+                b'F' => Some(false),
+                _ => None,
+            }),
+            _ if scaled => field_series(&name, &field, repeat, width / repeat, |b| {
+                scale * physical(code, b) + zero
+            }),
+            'B' => field_series(&name, &field, repeat, 1, |b| b[0]),
+            'I' => field_series(&name, &field, repeat, 2, |b| {
+                i16::from_be_bytes([b[0], b[1]]) as i32
+            }),
+            'J' => field_series(&name, &field, repeat, 4, |b| {
+                i32::from_be_bytes(b[..4].try_into().unwrap())
+            }),
+            'K' => field_series(&name, &field, repeat, 8, |b| {
+                i64::from_be_bytes(b[..8].try_into().unwrap())
+            }),
+            'E' => field_series(&name, &field, repeat, 4, |b| {
+                f32::from_be_bytes(b[..4].try_into().unwrap())
+            }),
+            _ => field_series(&name, &field, repeat, 8, |b| {
+                f64::from_be_bytes(b[..8].try_into().unwrap())
+            }),
+        };
+
+        columns.push(series.into_column());
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+// ================== Frame Comparison ==================
+
+/// Pixels of both default images, erroring when their dimensions differ.
+fn image_pair(a: &CcdFits, b: &CcdFits) -> Result<(Array2<f64>, Array2<f64>), FitsLoaderError> {
+    let (a_data, b_data) = (a.get_data()?, b.get_data()?);
+    if a_data.dim() != b_data.dim() {
+        return Err(FitsLoaderError::ImageShapeMismatch {
+            path: b.path.display().to_string(),
+            expected: a_data.dim(),
+            found: b_data.dim(),
+        });
+    }
+    Ok((a_data, b_data))
+}
+
+/// Pixel-wise difference `a - b` of the two frames.
+pub fn image_diff(a: &CcdFits, b: &CcdFits) -> Result<Array2<f64>, FitsLoaderError> {
+    let (a, b) = image_pair(a, b)?;
+    Ok(a - b)
+}
+
+/// Pixel-wise ratio `a / b` of the two frames, `NaN` where `b` is zero.
+pub fn image_ratio(a: &CcdFits, b: &CcdFits) -> Result<Array2<f64>, FitsLoaderError> {
+    let (a, b) = image_pair(a, b)?;
+    Ok(ndarray::Zip::from(&a)
+        .and(&b)
+        .map_collect(|&a, &b| if b == 0.0 { f64::NAN } else { a / b }))
+}
+
+// ================== Writing ==================
+
+/// Pixel types `write_image_fits` can store, each with its FITS `BITPIX`.
+pub trait FitsPixel: Copy {
+    fn image_data(image: ArrayD<Self>) -> ImageData;
+}
+
+impl FitsPixel for u8 {
+    fn image_data(image: ArrayD<Self>) -> ImageData {
+        ImageData::U8(image)
+    }
+}
+
+impl FitsPixel for i16 {
+    fn image_data(image: ArrayD<Self>) -> ImageData {
+        ImageData::I16(image)
+    }
+}
+
+impl FitsPixel for i32 {
+    fn image_data(image: ArrayD<Self>) -> ImageData {
+        ImageData::I32(image)
+    }
+}
+
+impl FitsPixel for f32 {
+    fn image_data(image: ArrayD<Self>) -> ImageData {
+        ImageData::F32(image)
+    }
+}
+
+impl FitsPixel for f64 {
+    fn image_data(image: ArrayD<Self>) -> ImageData {
+        ImageData::F64(image)
+    }
+}
+
+/// Writes `image` and the numeric `header` cards to a new FITS file at `path`.
+///
+/// The cards go into an empty primary HDU, the image into the single image
+/// extension with `BZERO = 0`, i.e. at HDU index `1`. Reading the file back
+/// thus needs the default `ImageHduSelection::LargestImage` or `Index(1)`
+/// rather than the detector layout `Index(2)`. Card names longer than eight
+/// characters are written as `HIERARCH` cards.
+pub fn write_image_fits<T: FitsPixel>(
+    image: &Array2<T>,
+    header: &[(String, f64)],
+    path: impl AsRef<Path>,
+) -> Result<(), FitsLoaderError> {
+    let mut primary = PrimaryHDU::default();
+    for (key, value) in header {
+        primary
+            .header
+            .add_card(&Card::new(key.clone(), value.to_string(), None));
+    }
+
+    let mut image_header = Header::new();
+    for (key, value) in [("XTENSION", "IMAGE"), ("BITPIX", "8"), ("NAXIS", "0")] {
+        image_header.add_card(&Card::new(key.into(), value.into(), None));
+    }
+    for n in 1..=2 {
+        image_header.add_card(&Card::new(format!("NAXIS{}", n), "0".into(), None));
+    }
+    image_header.add_card(&Card::new("BZERO".into(), "0".into(), None));
+    let data = T::image_data(astrors_layout(&image.view()));
+    let mut image = ImageHDU::new(image_header, data);
+
+    let mut file = fs::File::create(path)?;
+    primary.write_to_file(&mut file)?;
+    image.write_to_file(&mut file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::card_f64;
+    use crate::test_util::{test_dir, u16_image, write_fits};
+
+    #[test]
+    fn written_image_reads_back() {
+        let dir = test_dir("write_image_fits");
+        let source = dir.join("frame.fits");
+        write_fits(&source, &[], Some(u16_image(2, 3, &[1, 2, 3, 4, 5, 60000])));
+        let corrected = CcdFits::new(&source).unwrap().get_data().unwrap() * 0.5 - 1.0;
+
+        let out = dir.join("corrected.fits");
+        let header = [
+            ("EXPOSURE".to_string(), 2.0),
+            ("Sample Theta".to_string(), 1.25),
+        ];
+        write_image_fits(&corrected, &header, &out).unwrap();
+
+        let written = CcdFits::new(&out).unwrap();
+        assert_eq!(written.get_data().unwrap(), corrected);
+        let primary = &written.primary().unwrap().header;
+        assert_eq!(card_f64(primary, "EXPOSURE"), Some(2.0));
+        assert_eq!(card_f64(primary, "Sample Theta"), Some(1.25));
+        let image = &written
+            .image_hdu(&ImageHduSelection::Index(1))
+            .unwrap()
+            .header;
+        assert_eq!(card_f64(image, "NAXIS1"), Some(3.0));
+        assert_eq!(card_f64(image, "NAXIS2"), Some(2.0));
+    }
+}
@@ -0,0 +1,1092 @@
+use std::ops::Range;
+
+use ndarray::{s, Array2, ArrayView2};
+
+use crate::errors::FitsLoaderError;
+use crate::options::{
+    BandAxis, FwhmMethod, IntegrationMethod, OverscanOptions, QualityOptions, RowReadout,
+    SpecularBandOptions,
+};
+#[cfg(feature = "png")]
+use crate::options::{Colormap, PngThumbnailOptions};
+
+// ================== Pixel Statistics ==================
+// Coordinates follow the image layout: `x` is the column index and `y` the row index.
+
+/// Intensity-weighted centroid `(x, y)` of the image.
+///
+/// Only positive pixels are weighted, negative ones count as zero: after a
+/// background subtraction the noise dips below zero, and with negative weights
+/// the centroid can land far outside the image. It thus always lies within the
+/// image. Returns `None` when the image holds no positive counts.
+pub fn centroid(image: &ArrayView2<f64>) -> Option<(f64, f64)> {
+    let mut total = 0.0;
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    for ((y, x), &value) in image.indexed_iter() {
+        if value.is_finite() && value > 0.0 {
+            total += value;
+            sum_x += value * x as f64;
+            sum_y += value * y as f64;
+        }
+    }
+    if total > 0.0 {
+        Some((sum_x / total, sum_y / total))
+    } else {
+        None
+    }
+}
+
+/// Sums every pixel whose centre lies within `radius` of `center`.
+///
+/// Apertures hanging over the image edge are clipped, only the pixels that
+/// exist contribute to the sum.
+pub fn aperture_sum(image: &ArrayView2<f64>, center: (f64, f64), radius: f64) -> f64 {
+    ring_sum(image, center, None, radius, false).0
+}
+
+/// Sum and pixel count of the annulus `inner < distance <= outer` around `center`,
+/// clipped at the image edge like `aperture_sum`.
+pub fn annulus_sum(
+    image: &ArrayView2<f64>,
+    center: (f64, f64),
+    inner: f64,
+    outer: f64,
+) -> (f64, usize) {
+    ring_sum(image, center, Some(inner), outer, false)
+}
+
+/// Sum and pixel count of the pixels with `inner < distance <= outer`, the full
+/// disk when `inner` is `None`. With `skip_nan`, non-finite pixels are left out of
+/// both.
+pub(crate) fn ring_sum(
+    image: &ArrayView2<f64>,
+    center: (f64, f64),
+    inner: Option<f64>,
+    outer: f64,
+    skip_nan: bool,
+) -> (f64, usize) {
+    let (rows, cols) = image.dim();
+    let (cx, cy) = center;
+    let x0 = (cx - outer).floor().max(0.0) as usize;
+    let y0 = (cy - outer).floor().max(0.0) as usize;
+    let x1 = ((cx + outer).ceil().max(0.0) as usize).min(cols.saturating_sub(1));
+    let y1 = ((cy + outer).ceil().max(0.0) as usize).min(rows.saturating_sub(1));
+
+    let mut total = 0.0;
+    let mut count = 0;
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+            let d2 = dx * dx + dy * dy;
+            let value = image[[y, x]];
+            if d2 <= outer * outer
+                && inner.is_none_or(|inner| d2 > inner * inner)
+                && (!skip_nan || value.is_finite())
+            {
+                total += value;
+                count += 1;
+            }
+        }
+    }
+    (total, count)
+}
+
+/// Azimuthally averaged intensity in rings of equal width around a center.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadialProfile {
+    /// Radius of the middle of each ring [px].
+    pub radii: Vec<f64>,
+    /// Mean of the finite pixels in each ring, `None` for rings without any.
+    pub intensity: Vec<Option<f64>>,
+}
+
+/// Radially averages the image about `center` into `nbins` rings.
+///
+/// The rings span from the center to the farthest image corner, so rings that
+/// cross the image edge are averaged over the pixels that exist and rings past
+/// every edge stay `None`.
+pub fn radial_profile(image: &ArrayView2<f64>, center: (f64, f64), nbins: usize) -> RadialProfile {
+    let (rows, cols) = image.dim();
+    let (cx, cy) = center;
+    let corners = [
+        (0.0, 0.0),
+        (cols as f64, 0.0),
+        (0.0, rows as f64),
+        (cols as f64, rows as f64),
+    ];
+    let r_max = corners
+        .iter()
+        .map(|(x, y)| (x - cx).hypot(y - cy))
+        .fold(0.0, f64::max);
+    let width = r_max / nbins.max(1) as f64;
+
+    let mut sums = vec![0.0; nbins];
+    let mut counts = vec![0usize; nbins];
+    if width > 0.0 {
+        for ((y, x), &value) in image.indexed_iter() {
+            let r = (x as f64 - cx).hypot(y as f64 - cy);
+            let bin = (r / width) as usize;
+            if value.is_finite() && bin < nbins {
+                sums[bin] += value;
+                counts[bin] += 1;
+            }
+        }
+    }
+    RadialProfile {
+        radii: (0..nbins).map(|i| (i as f64 + 0.5) * width).collect(),
+        intensity: sums
+            .iter()
+            .zip(&counts)
+            .map(|(&sum, &n)| (n > 0).then(|| sum / n as f64))
+            .collect(),
+    }
+}
+
+/// Downsamples the image so its longer side is at most `target` pixels.
+///
+/// Resampling is a plain area average: the image is cut into square blocks of
+/// `ceil(max(rows, cols) / target)` pixels and each block becomes one output
+/// pixel holding its mean. Using the same block size on both axes keeps the
+/// aspect ratio, blocks clipped by the image edge average the pixels they hold.
+pub fn thumbnail(image: &ArrayView2<f64>, target: usize) -> Array2<f64> {
+    let (rows, cols) = image.dim();
+    let block = rows.max(cols).div_ceil(target.max(1)).max(1);
+    let (out_rows, out_cols) = (rows.div_ceil(block), cols.div_ceil(block));
+
+    Array2::from_shape_fn((out_rows, out_cols), |(r, c)| {
+        let y1 = ((r + 1) * block).min(rows);
+        let x1 = ((c + 1) * block).min(cols);
+        let view = image.slice(ndarray::s![r * block..y1, c * block..x1]);
+        view.sum() / view.len() as f64
+    })
+}
+
+/// Sums `factor x factor` blocks of unsigned 16-bit pixels, as on-chip binning.
+///
+/// Blocks are accumulated in `u64` and returned as `u32`, so bright frames do not
+/// wrap around; a block can only saturate at `u32::MAX` for factors above 256.
+/// Rows and columns that do not fill a whole block are dropped.
+pub fn bin_image(image: &ArrayView2<u16>, factor: usize) -> Array2<u32> {
+    bin_blocks(image, factor, |sum, _| {
+        u32::try_from(sum).unwrap_or(u32::MAX)
+    })
+}
+
+/// Averages `factor x factor` blocks, returning to the `u16` pixel type.
+///
+/// Same blocks as `bin_image`, the mean is rounded to the nearest integer and
+/// always fits in `u16`.
+pub fn bin_image_mean(image: &ArrayView2<u16>, factor: usize) -> Array2<u16> {
+    bin_blocks(image, factor, |sum, n| {
+        ((sum as f64 / n as f64).round()).min(u16::MAX as f64) as u16
+    })
+}
+
+fn bin_blocks<T>(
+    image: &ArrayView2<u16>,
+    factor: usize,
+    finish: impl Fn(u64, usize) -> T,
+) -> Array2<T> {
+    let factor = factor.max(1);
+    let (rows, cols) = image.dim();
+    Array2::from_shape_fn((rows / factor, cols / factor), |(r, c)| {
+        let block = image.slice(ndarray::s![
+            r * factor..(r + 1) * factor,
+            c * factor..(c + 1) * factor
+        ]);
+        let sum = block.iter().map(|&v| v as u64).sum();
+        finish(sum, factor * factor)
+    })
+}
+
+/// Rectangular region of interest, `x0`/`y0` being the top left pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Roi {
+    pub x0: usize,
+    pub y0: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+impl Roi {
+    /// Checks that the region lies completely inside an image of `(rows, cols)`.
+    pub fn validate(&self, shape: (usize, usize)) -> Result<(), FitsLoaderError> {
+        let (rows, cols) = shape;
+        if self.w == 0 || self.h == 0 || self.x0 + self.w > cols || self.y0 + self.h > rows {
+            return Err(FitsLoaderError::Other(format!(
+                "ROI {:?} outside of {}x{} image",
+                self, rows, cols
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Rectangular region with a name, see `crate::options::ImageOptions::named_rois`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedRoi {
+    pub name: String,
+    pub x0: usize,
+    pub y0: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+impl NamedRoi {
+    pub fn roi(&self) -> Roi {
+        Roi {
+            x0: self.x0,
+            y0: self.y0,
+            w: self.w,
+            h: self.h,
+        }
+    }
+
+    /// Name of the column holding the counts of the region.
+    pub fn column(&self) -> String {
+        format!("{} ROI Counts", self.name)
+    }
+}
+
+// ================== Peak Fitting ==================
+
+/// Parameters of a 2D Gaussian `amplitude * exp(-dx²/2σx² - dy²/2σy²)`, in pixels
+/// of the full image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeakFit {
+    pub x: f64,
+    pub y: f64,
+    pub sigma_x: f64,
+    pub sigma_y: f64,
+    pub amplitude: f64,
+}
+
+impl PeakFit {
+    /// Volume under the fitted Gaussian, `2π · amplitude · σx · σy`.
+    pub fn volume(&self) -> f64 {
+        2.0 * std::f64::consts::PI * self.amplitude * self.sigma_x * self.sigma_y
+    }
+}
+
+impl PeakFit {
+    /// Full widths at half maximum `(x, y)`, `2·sqrt(2·ln 2)·σ` per axis.
+    pub fn fwhm(&self) -> (f64, f64) {
+        let scale = 2.0 * (2.0 * std::f64::consts::LN_2).sqrt();
+        (scale * self.sigma_x, scale * self.sigma_y)
+    }
+}
+
+const FIT_MAX_ITERATIONS: usize = 50;
+const FIT_TOLERANCE: f64 = 1e-8;
+
+/// Fits an axis-aligned 2D Gaussian to the image, or to `roi` when given.
+///
+/// The initial guess comes from the intensity moments of the region and is
+/// refined with Gauss-Newton steps. Returns `None` when the region lies outside
+/// the image, holds no positive counts, or the fit diverges or does not converge.
+pub fn fit_peak(image: &ArrayView2<f64>, roi: Option<Roi>) -> Option<PeakFit> {
+    let roi = roi.unwrap_or(Roi {
+        x0: 0,
+        y0: 0,
+        w: image.ncols(),
+        h: image.nrows(),
+    });
+    roi.validate(image.dim()).ok()?;
+    let view = image.slice(ndarray::s![roi.y0..roi.y0 + roi.h, roi.x0..roi.x0 + roi.w]);
+
+    // Moment-based initial guess.
+    let positive = view.mapv(|v| if v.is_finite() { v.max(0.0) } else { 0.0 });
+    let (cx, cy) = centroid(&positive.view())?;
+    let total = positive.sum();
+    let (mut var_x, mut var_y) = (0.0, 0.0);
+    for ((y, x), &v) in positive.indexed_iter() {
+        var_x += v * (x as f64 - cx).powi(2);
+        var_y += v * (y as f64 - cy).powi(2);
+    }
+    let amplitude = positive.iter().cloned().fold(0.0, f64::max);
+    let mut p = [
+        amplitude,
+        cx,
+        cy,
+        (var_x / total).sqrt().max(0.5),
+        (var_y / total).sqrt().max(0.5),
+    ];
+
+    for _ in 0..FIT_MAX_ITERATIONS {
+        let [a, x0, y0, sx, sy] = p;
+        let mut jtj = [[0.0; 5]; 5];
+        let mut jtr = [0.0; 5];
+        for ((y, x), &v) in view.indexed_iter() {
+            if !v.is_finite() {
+                continue;
+            }
+            let (dx, dy) = (x as f64 - x0, y as f64 - y0);
+            let g = (-(dx * dx) / (2.0 * sx * sx) - (dy * dy) / (2.0 * sy * sy)).exp();
+            let r = v - a * g;
+            let j = [
+                g,
+                a * g * dx / (sx * sx),
+                a * g * dy / (sy * sy),
+                a * g * dx * dx / (sx * sx * sx),
+                a * g * dy * dy / (sy * sy * sy),
+            ];
+            for k in 0..5 {
+                jtr[k] += j[k] * r;
+                for l in 0..5 {
+                    jtj[k][l] += j[k] * j[l];
+                }
+            }
+        }
+
+        let step = solve(jtj.iter().map(|row| row.to_vec()).collect(), jtr.to_vec())?;
+        for (p, s) in p.iter_mut().zip(&step) {
+            *p += s;
+        }
+        if p.iter().any(|v| !v.is_finite()) || p[3] <= 0.0 || p[4] <= 0.0 {
+            return None;
+        }
+        let converged = step
+            .iter()
+            .zip(p)
+            .all(|(s, v)| s.abs() <= FIT_TOLERANCE * v.abs().max(1.0));
+        if converged {
+            return Some(PeakFit {
+                x: p[1] + roi.x0 as f64,
+                y: p[2] + roi.y0 as f64,
+                sigma_x: p[3],
+                sigma_y: p[4],
+                amplitude: p[0],
+            });
+        }
+    }
+    None
+}
+
+/// Full widths at half maximum `(x, y)` in pixels of the peak in the image, or
+/// in `roi` when given, estimated with `method`.
+///
+/// A width is `None` when it cannot be trusted: below one pixel the peak is
+/// not resolved, and a peak wider than the region, or whose half maximum is
+/// not crossed on both sides within it, is cut off. Both are `None` when the
+/// region holds no positive counts or, for `GaussianFit`, the fit fails.
+pub fn peak_fwhm(
+    image: &ArrayView2<f64>,
+    roi: Option<Roi>,
+    method: FwhmMethod,
+) -> (Option<f64>, Option<f64>) {
+    let roi = roi.unwrap_or(Roi {
+        x0: 0,
+        y0: 0,
+        w: image.ncols(),
+        h: image.nrows(),
+    });
+    if roi.validate(image.dim()).is_err() {
+        return (None, None);
+    }
+    let (x, y) = match method {
+        FwhmMethod::GaussianFit => match fit_peak(image, Some(roi)) {
+            Some(fit) => {
+                let (x, y) = fit.fwhm();
+                (Some(x), Some(y))
+            }
+            None => (None, None),
+        },
+        FwhmMethod::HalfMaxCrossing => {
+            let view = image.slice(s![roi.y0..roi.y0 + roi.h, roi.x0..roi.x0 + roi.w]);
+            let finite = view.mapv(|v| if v.is_finite() { v } else { 0.0 });
+            let profile = |axis| finite.sum_axis(ndarray::Axis(axis)).to_vec();
+            (half_max_width(&profile(0)), half_max_width(&profile(1)))
+        }
+    };
+    let trusted = |width: Option<f64>, size: usize| {
+        width.filter(|&w| w.is_finite() && (1.0..=size as f64).contains(&w))
+    };
+    (trusted(x, roi.w), trusted(y, roi.h))
+}
+
+/// Width of the peak of `profile` between the points where it falls to half
+/// its maximum, `None` when the profile holds no positive value or does not
+/// fall below half on both sides.
+fn half_max_width(profile: &[f64]) -> Option<f64> {
+    let (peak, &max) = profile
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+    if max <= 0.0 {
+        return None;
+    }
+    let half = max / 2.0;
+    // Position between pixels `inside` and `outside` where the profile crosses `half`.
+    let crossing = |inside: usize, outside: usize| {
+        let (a, b) = (profile[inside], profile[outside]);
+        let t = (a - half) / (a - b);
+        inside as f64 + t * (outside as f64 - inside as f64)
+    };
+    let left = (1..=peak)
+        .rev()
+        .find(|&i| profile[i - 1] < half)
+        .map(|i| crossing(i, i - 1))?;
+    let right = (peak..profile.len() - 1)
+        .find(|&i| profile[i + 1] < half)
+        .map(|i| crossing(i, i + 1))?;
+    Some(right - left)
+}
+
+/// Sums of the specular band and the mean of the two off-specular bands of
+/// `bands`, centered on the intensity centroid of the image.
+///
+/// The off-specular mean estimates the diffuse scattering under the specular
+/// band, both cover the same area. Only finite pixels count. `None` when the
+/// image holds no positive counts or a band reaches past its edge.
+pub fn specular_bands(image: &ArrayView2<f64>, bands: &SpecularBandOptions) -> Option<(f64, f64)> {
+    let (cx, cy) = centroid(image)?;
+    let (center, len) = match bands.axis {
+        BandAxis::X => (cx, image.ncols()),
+        BandAxis::Y => (cy, image.nrows()),
+    };
+    let center = center.round() as usize;
+    let width = 2 * bands.half_width + 1;
+    let band_sum = |middle: Option<usize>| {
+        let start = middle?.checked_sub(bands.half_width)?;
+        if start + width > len {
+            return None;
+        }
+        let view = match bands.axis {
+            BandAxis::X => image.slice(s![.., start..start + width]),
+            BandAxis::Y => image.slice(s![start..start + width, ..]),
+        };
+        Some(view.iter().filter(|v| v.is_finite()).sum::<f64>())
+    };
+    let specular = band_sum(Some(center))?;
+    let below = band_sum(center.checked_sub(bands.offset))?;
+    let above = band_sum(Some(center + bands.offset))?;
+    Some((specular, (below + above) / 2.0))
+}
+
+/// Integrated intensity of the image, or of `roi` when given, using `method`.
+///
+/// `Sum` adds up the finite pixels of the region, `FittedArea` is the volume
+/// under the Gaussian from `fit_peak` and `None` when the fit fails.
+pub fn integrate(
+    image: &ArrayView2<f64>,
+    roi: Option<Roi>,
+    method: IntegrationMethod,
+) -> Option<f64> {
+    match method {
+        IntegrationMethod::Sum => {
+            let view = match roi {
+                Some(roi) => {
+                    roi.validate(image.dim()).ok()?;
+                    image.slice(ndarray::s![roi.y0..roi.y0 + roi.h, roi.x0..roi.x0 + roi.w])
+                }
+                None => image.view(),
+            };
+            Some(view.iter().filter(|v| v.is_finite()).sum())
+        }
+        IntegrationMethod::FittedArea => fit_peak(image, roi).map(|fit| fit.volume()),
+    }
+}
+
+/// Sum of the element-wise product of `image` and `weights` over the finite
+/// pixels, `None` when the shapes differ.
+pub fn weighted_sum(image: &ArrayView2<f64>, weights: &ArrayView2<f64>) -> Option<f64> {
+    if image.dim() != weights.dim() {
+        return None;
+    }
+    let mut sum = 0.0;
+    ndarray::Zip::from(image).and(weights).for_each(|&v, &w| {
+        if v.is_finite() {
+            sum += v * w;
+        }
+    });
+    Some(sum)
+}
+
+/// Solves the square normal equations `a x = b` by Gaussian elimination with
+/// partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < f64::EPSILON {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (v, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *v -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+// ================== Reciprocal Space ==================
+
+/// Scattering geometry of a single frame.
+#[derive(Clone, Copy, Debug)]
+pub struct DetectorGeometry {
+    /// Pixel pitch [mm].
+    pub pixel_size: f64,
+    /// Sample to detector distance [mm].
+    pub distance: f64,
+    /// Photon wavelength [Å].
+    pub wavelength: f64,
+    /// Grazing incidence angle [deg].
+    pub incidence: f64,
+    /// Pixel `(x, y)` of the specular reflection.
+    pub center: (f64, f64),
+}
+
+/// Per-pixel `(Qxy, Qz)` maps in Å⁻¹ for a grazing incidence geometry.
+///
+/// Assumptions:
+/// * the detector is flat and normal to the specularly reflected beam, which
+///   hits the detector at `geometry.center`, i.e. the exit angle equals the
+///   incidence angle there;
+/// * rows increase with the exit angle and columns with the in-plane angle;
+/// * pixel offsets are converted to angles with `atan(offset / distance)`.
+///
+/// With `k = 2π/λ`, `αf` the exit and `2θf` the in-plane angle:
+/// `qx = k (cos αf cos 2θf - cos αi)`, `qy = k cos αf sin 2θf`,
+/// `qz = k (sin αf + sin αi)` and `Qxy = sign(qy) sqrt(qx² + qy²)`.
+///
+/// When `roi` is given only that region is returned.
+pub fn q_map(
+    shape: (usize, usize),
+    geometry: &DetectorGeometry,
+    roi: Option<Roi>,
+) -> Result<(Array2<f64>, Array2<f64>), FitsLoaderError> {
+    let roi = match roi {
+        Some(roi) => {
+            roi.validate(shape)?;
+            roi
+        }
+        None => Roi {
+            x0: 0,
+            y0: 0,
+            w: shape.1,
+            h: shape.0,
+        },
+    };
+
+    let k = 2.0 * std::f64::consts::PI / geometry.wavelength;
+    let alpha_i = geometry.incidence.to_radians();
+    let (cx, cy) = geometry.center;
+
+    let mut qxy = Array2::<f64>::zeros((roi.h, roi.w));
+    let mut qz = Array2::<f64>::zeros((roi.h, roi.w));
+    for (((r, c), qxy), qz) in qxy.indexed_iter_mut().zip(qz.iter_mut()) {
+        let dy = (roi.y0 + r) as f64 - cy;
+        let dx = (roi.x0 + c) as f64 - cx;
+        let alpha_f = alpha_i + (dy * geometry.pixel_size / geometry.distance).atan();
+        let two_theta_f = (dx * geometry.pixel_size / geometry.distance).atan();
+
+        let qx = k * (alpha_f.cos() * two_theta_f.cos() - alpha_i.cos());
+        let qy = k * alpha_f.cos() * two_theta_f.sin();
+        *qxy = qy.signum() * (qx * qx + qy * qy).sqrt();
+        *qz = k * (alpha_f.sin() + alpha_i.sin());
+    }
+    Ok((qxy, qz))
+}
+
+// ================== Frame Quality ==================
+
+/// Bit set of the quality checks a frame failed, `0` being a clean frame.
+///
+/// | bit | flag             | set when                                          |
+/// |-----|------------------|---------------------------------------------------|
+/// | 0   | `SATURATED`      | a pixel reaches the saturation level              |
+/// | 1   | `LOW_COUNTS`     | the frame sums to less than the minimum counts    |
+/// | 2   | `NON_FINITE`     | a pixel is `NaN` or infinite                      |
+/// | 3   | `SHAPE_MISMATCH` | the frame dimensions differ from the expected ones |
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FrameQuality(u32);
+
+impl FrameQuality {
+    pub const SATURATED: FrameQuality = FrameQuality(1 << 0);
+    pub const LOW_COUNTS: FrameQuality = FrameQuality(1 << 1);
+    pub const NON_FINITE: FrameQuality = FrameQuality(1 << 2);
+    pub const SHAPE_MISMATCH: FrameQuality = FrameQuality(1 << 3);
+
+    /// A frame that passed every check.
+    pub fn empty() -> Self {
+        FrameQuality(0)
+    }
+
+    /// Rebuilds the flags from a `Quality` column value.
+    pub fn from_bits(bits: u32) -> Self {
+        FrameQuality(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every flag of `other` is set.
+    pub fn contains(&self, other: FrameQuality) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: FrameQuality) {
+        self.0 |= other.0;
+    }
+}
+
+impl std::ops::BitOr for FrameQuality {
+    type Output = FrameQuality;
+
+    fn bitor(self, rhs: FrameQuality) -> FrameQuality {
+        FrameQuality(self.0 | rhs.0)
+    }
+}
+
+/// Runs the quality checks configured in `options` on a frame of detector ADU.
+pub fn frame_quality(image: &ArrayView2<f64>, options: &QualityOptions) -> FrameQuality {
+    let mut quality = FrameQuality::empty();
+    if image.iter().any(|v| !v.is_finite()) {
+        quality.insert(FrameQuality::NON_FINITE);
+    }
+    if image.iter().any(|&v| v >= options.saturation_level) {
+        quality.insert(FrameQuality::SATURATED);
+    }
+    if options
+        .min_counts
+        .is_some_and(|min| image.iter().filter(|v| v.is_finite()).sum::<f64>() < min)
+    {
+        quality.insert(FrameQuality::LOW_COUNTS);
+    }
+    if options
+        .expected_shape
+        .is_some_and(|shape| shape != image.dim())
+    {
+        quality.insert(FrameQuality::SHAPE_MISMATCH);
+    }
+    quality
+}
+
+/// Share of the pixels at or above `level`, e.g. `0.05` for 5% saturated
+/// pixels. `NaN` pixels, dead zones included, count neither way; `None` for a
+/// frame without any other pixel.
+pub fn saturation_fraction(image: &ArrayView2<f64>, level: f64) -> Option<f64> {
+    let (saturated, total) = image
+        .iter()
+        .filter(|v| !v.is_nan())
+        .fold((0usize, 0usize), |(saturated, total), &v| {
+            (saturated + usize::from(v >= level), total + 1)
+        });
+    (total > 0).then(|| saturated as f64 / total as f64)
+}
+
+// ================== Deinterlacing ==================
+
+/// Frame row of each stored row of a `rows` high frame read out as `readout`.
+fn readout_rows(readout: &RowReadout, rows: usize) -> Result<Vec<usize>, FitsLoaderError> {
+    let top = rows.div_ceil(2);
+    let order: Vec<usize> = match readout {
+        RowReadout::Interlaced => (0..rows)
+            .map(|i| if i % 2 == 0 { i / 2 } else { top + i / 2 })
+            .collect(),
+        RowReadout::InterlacedMirrored => (0..rows)
+            .map(|i| if i % 2 == 0 { i / 2 } else { rows - 1 - i / 2 })
+            .collect(),
+        RowReadout::Order(order) => {
+            let mut seen = vec![false; rows];
+            let permutation = order.len() == rows
+                && order
+                    .iter()
+                    .all(|&row| row < rows && !std::mem::replace(&mut seen[row], true));
+            if !permutation {
+                return Err(FitsLoaderError::InvalidConfig(format!(
+                    "readout order {:?} is not a permutation of the {} rows",
+                    order, rows
+                )));
+            }
+            order.clone()
+        }
+    };
+    Ok(order)
+}
+
+/// Reassembles a frame whose rows were stored in `readout` order, e.g. by a
+/// detector reading out in two halves that end up interlaced in the raw array.
+///
+/// ```rust
+/// use ndarray::array;
+/// use pyref_core::image::deinterlace_rows;
+/// use pyref_core::options::RowReadout;
+///
+/// // Rows 0 and 1 of the top half alternate with rows 2 and 3 of the bottom.
+/// let stored = array![[0, 0], [2, 2], [1, 1], [3, 3]];
+/// let frame = deinterlace_rows(&stored.view(), &RowReadout::Interlaced)?;
+/// assert_eq!(frame, array![[0, 0], [1, 1], [2, 2], [3, 3]]);
+///
+/// // The bottom half read from the bottom edge up.
+/// let stored = array![[0, 0], [3, 3], [1, 1], [2, 2]];
+/// let frame = deinterlace_rows(&stored.view(), &RowReadout::InterlacedMirrored)?;
+/// assert_eq!(frame, array![[0, 0], [1, 1], [2, 2], [3, 3]]);
+/// # Ok::<(), pyref_core::errors::FitsLoaderError>(())
+/// ```
+pub fn deinterlace_rows<T: Clone>(
+    image: &ArrayView2<T>,
+    readout: &RowReadout,
+) -> Result<Array2<T>, FitsLoaderError> {
+    let order = readout_rows(readout, image.nrows())?;
+    let mut stored_row = vec![0; order.len()];
+    for (stored, &row) in order.iter().enumerate() {
+        stored_row[row] = stored;
+    }
+    Ok(image.select(ndarray::Axis(0), &stored_row))
+}
+
+// ================== Overscan ==================
+
+/// Science region of `range` removed from `0..len`, checking that `range` is a
+/// non-empty band at either edge.
+fn science_range(
+    range: &Option<Range<usize>>,
+    len: usize,
+    axis: &str,
+) -> Result<Range<usize>, FitsLoaderError> {
+    let Some(range) = range else {
+        return Ok(0..len);
+    };
+    let invalid = |reason: &str| {
+        FitsLoaderError::Other(format!(
+            "Overscan {} {:?} {} of {} {}",
+            axis, range, reason, len, axis
+        ))
+    };
+    if range.is_empty() || range.end > len {
+        return Err(invalid("outside"));
+    }
+    match (range.start == 0, range.end == len) {
+        (true, true) => Err(invalid("covers all")),
+        (true, false) => Ok(range.end..len),
+        (false, true) => Ok(0..range.start),
+        (false, false) => Err(invalid("is not at an edge")),
+    }
+}
+
+/// Splits a frame into its overscan bias and science region.
+///
+/// The bias is the mean of the finite pixels in the overscan columns and rows,
+/// `None` when there are none. The science region is what remains after
+/// cutting the overscan bands off, which therefore have to sit at an image edge.
+pub fn split_overscan(
+    image: &ArrayView2<f64>,
+    overscan: &OverscanOptions,
+) -> Result<(Option<f64>, Array2<f64>), FitsLoaderError> {
+    let (rows, cols) = image.dim();
+    let science_rows = science_range(&overscan.rows, rows, "rows")?;
+    let science_cols = science_range(&overscan.columns, cols, "columns")?;
+
+    let (mut sum, mut count) = (0.0, 0usize);
+    for ((y, x), &value) in image.indexed_iter() {
+        let in_overscan = !science_rows.contains(&y) || !science_cols.contains(&x);
+        if in_overscan && value.is_finite() {
+            sum += value;
+            count += 1;
+        }
+    }
+    let science = image.slice(s![science_rows, science_cols]).to_owned();
+    Ok(((count > 0).then(|| sum / count as f64), science))
+}
+
+// ================== Dead Zones ==================
+
+/// Pixels of a `(rows, cols)` frame covered by any of `zones`, checking that
+/// every zone lies inside the frame.
+pub fn dead_zone_mask(
+    zones: &[Roi],
+    shape: (usize, usize),
+) -> Result<Array2<bool>, FitsLoaderError> {
+    let mut mask = Array2::from_elem(shape, false);
+    for zone in zones {
+        zone.validate(shape)?;
+        mask.slice_mut(ndarray::s![
+            zone.y0..zone.y0 + zone.h,
+            zone.x0..zone.x0 + zone.w
+        ])
+        .fill(true);
+    }
+    Ok(mask)
+}
+
+// ================== Polynomial Background ==================
+
+/// Powers `(i, j)` of the terms `u^i v^j` of a 2D polynomial of total degree
+/// `order`, by increasing degree and then falling power of `u`.
+pub(crate) fn poly_terms(order: usize) -> Vec<(usize, usize)> {
+    (0..=order)
+        .flat_map(|degree| (0..=degree).map(move |j| (degree - j, j)))
+        .collect()
+}
+
+/// Column `u` and row `v` of pixel `(y, x)`, scaled to `[-1, 1]` across the frame.
+fn unit_coords((rows, cols): (usize, usize), y: usize, x: usize) -> (f64, f64) {
+    let scale = |i: usize, n: usize| {
+        if n > 1 {
+            2.0 * i as f64 / (n - 1) as f64 - 1.0
+        } else {
+            0.0
+        }
+    };
+    (scale(x, cols), scale(y, rows))
+}
+
+/// Least-squares fit of a 2D polynomial of total degree `order` to the finite
+/// pixels outside `mask`.
+///
+/// The coefficients are in `poly_terms` order, `c00, c10, c01, c20, c11, c02,
+/// ...`, where `c{i}{j}` multiplies `u^i v^j` and `u`/`v` are the column and row
+/// scaled to `[-1, 1]`, which keeps the normal equations well conditioned. An
+/// invalid mask, fewer usable pixels than terms, or a singular fit are errors.
+pub fn fit_poly_background(
+    image: &ArrayView2<f64>,
+    order: usize,
+    mask: Option<Roi>,
+) -> Result<Vec<f64>, FitsLoaderError> {
+    if let Some(mask) = mask {
+        mask.validate(image.dim())?;
+    }
+    let terms = poly_terms(order);
+    let n = terms.len();
+    let masked = |y: usize, x: usize| {
+        mask.is_some_and(|m| (m.y0..m.y0 + m.h).contains(&y) && (m.x0..m.x0 + m.w).contains(&x))
+    };
+
+    let mut ata = vec![vec![0.0; n]; n];
+    let mut atb = vec![0.0; n];
+    let mut count = 0;
+    let mut basis = vec![0.0; n];
+    for ((y, x), &value) in image.indexed_iter() {
+        if !value.is_finite() || masked(y, x) {
+            continue;
+        }
+        let (u, v) = unit_coords(image.dim(), y, x);
+        for (b, &(i, j)) in basis.iter_mut().zip(&terms) {
+            *b = u.powi(i as i32) * v.powi(j as i32);
+        }
+        for k in 0..n {
+            atb[k] += basis[k] * value;
+            for l in 0..n {
+                ata[k][l] += basis[k] * basis[l];
+            }
+        }
+        count += 1;
+    }
+    if count < n {
+        return Err(FitsLoaderError::Other(format!(
+            "Polynomial background of order {} needs {} pixels outside the mask, found {}",
+            order, n, count
+        )));
+    }
+    solve(ata, atb).ok_or_else(|| {
+        FitsLoaderError::Other(format!(
+            "Polynomial background of order {} is singular on this frame",
+            order
+        ))
+    })
+}
+
+/// `image` minus the polynomial of degree `order` with `coefficients` as
+/// returned by `fit_poly_background`.
+pub(crate) fn subtract_poly(
+    image: &ArrayView2<f64>,
+    order: usize,
+    coefficients: &[f64],
+) -> Array2<f64> {
+    let terms = poly_terms(order);
+    let mut out = image.to_owned();
+    let shape = image.dim();
+    for ((y, x), value) in out.indexed_iter_mut() {
+        let (u, v) = unit_coords(shape, y, x);
+        let background: f64 = terms
+            .iter()
+            .zip(coefficients)
+            .map(|(&(i, j), c)| c * u.powi(i as i32) * v.powi(j as i32))
+            .sum();
+        *value -= background;
+    }
+    out
+}
+
+/// Subtracts a smooth background from `image`, for diffuse scattering on a
+/// background that varies across the detector.
+///
+/// A polynomial of total degree `order` is fitted to the pixels outside the
+/// peak region `mask` with `fit_poly_background` and subtracted from every
+/// pixel, the masked ones included.
+pub fn subtract_poly_background(
+    image: &ArrayView2<f64>,
+    order: usize,
+    mask: Option<Roi>,
+) -> Result<Array2<f64>, FitsLoaderError> {
+    let coefficients = fit_poly_background(image, order, mask)?;
+    Ok(subtract_poly(image, order, &coefficients))
+}
+
+// ================== PNG Previews ==================
+
+/// Percentile `p` (in percent) of the sorted `values`, interpolating linearly
+/// between neighbouring ranks.
+#[cfg(feature = "png")]
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// RGB color of `t` in `[0, 1]` on `colormap`, interpolated between nine
+/// evenly spaced samples of the matplotlib maps.
+#[cfg(feature = "png")]
+fn colorize(colormap: Colormap, t: f64) -> [u8; 3] {
+    const VIRIDIS: [[f64; 3]; 9] = [
+        [68.0, 1.0, 84.0],
+        [71.0, 44.0, 122.0],
+        [59.0, 81.0, 139.0],
+        [44.0, 113.0, 142.0],
+        [33.0, 144.0, 141.0],
+        [39.0, 173.0, 129.0],
+        [92.0, 200.0, 99.0],
+        [170.0, 220.0, 50.0],
+        [253.0, 231.0, 37.0],
+    ];
+    const INFERNO: [[f64; 3]; 9] = [
+        [0.0, 0.0, 4.0],
+        [31.0, 12.0, 72.0],
+        [85.0, 15.0, 109.0],
+        [136.0, 34.0, 106.0],
+        [186.0, 54.0, 85.0],
+        [227.0, 89.0, 51.0],
+        [249.0, 140.0, 10.0],
+        [249.0, 201.0, 50.0],
+        [252.0, 255.0, 164.0],
+    ];
+    let samples = match colormap {
+        Colormap::Gray => {
+            let v = (t * 255.0).round() as u8;
+            return [v, v, v];
+        }
+        Colormap::Viridis => &VIRIDIS,
+        Colormap::Inferno => &INFERNO,
+    };
+    let at = t * (samples.len() - 1) as f64;
+    let i = (at.floor() as usize).min(samples.len() - 2);
+    let f = at - i as f64;
+    std::array::from_fn(|c| (samples[i][c] + (samples[i + 1][c] - samples[i][c]) * f).round() as u8)
+}
+
+/// Renders `image` as a PNG preview and returns it base64 encoded, ready for a
+/// `data:image/png;base64,` URL.
+///
+/// The image is first downsampled with `thumbnail` to `options.size`. The
+/// contrast is then stretched linearly between the `options.contrast`
+/// percentiles of the finite thumbnail pixels: values at or below the lower
+/// percentile get the first color of the colormap, values at or above the upper
+/// one the last, so a few hot or dead pixels do not wash out the frame.
+/// Non-finite pixels, e.g. masked dead zones, get the first color, and a flat
+/// frame renders in it entirely. Row 0 of the image is the top row of the PNG.
+#[cfg(feature = "png")]
+pub fn png_thumbnail(
+    image: &ArrayView2<f64>,
+    options: &PngThumbnailOptions,
+) -> Result<String, FitsLoaderError> {
+    use base64::Engine;
+
+    let preview = thumbnail(image, options.size);
+    let (rows, cols) = preview.dim();
+    let mut finite: Vec<f64> = preview.iter().copied().filter(|v| v.is_finite()).collect();
+    finite.sort_by(f64::total_cmp);
+    let (lo, hi) = if finite.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let (p_lo, p_hi) = options.contrast;
+        (percentile(&finite, p_lo), percentile(&finite, p_hi))
+    };
+    let stretch = |v: f64| {
+        if v.is_finite() && hi > lo {
+            ((v - lo) / (hi - lo)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    };
+
+    let (color, pixels): (_, Vec<u8>) = if options.colormap == Colormap::Gray {
+        let pixels = preview
+            .iter()
+            .map(|&v| colorize(Colormap::Gray, stretch(v))[0])
+            .collect();
+        (png::ColorType::Grayscale, pixels)
+    } else {
+        let pixels = preview
+            .iter()
+            .flat_map(|&v| colorize(options.colormap, stretch(v)))
+            .collect();
+        (png::ColorType::Rgb, pixels)
+    };
+
+    let invalid =
+        |e: png::EncodingError| FitsLoaderError::Other(format!("Failed to encode PNG: {}", e));
+    let mut bytes = vec![];
+    let mut encoder = png::Encoder::new(&mut bytes, cols as u32, rows as u32);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(invalid)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn binning_does_not_wrap_near_the_u16_limit() {
+        let image = array![
+            [u16::MAX, u16::MAX, 1, 0],
+            [u16::MAX, u16::MAX - 1, 0, 0],
+            [7, 7, 7, 9]
+        ];
+        // The partial last row is dropped.
+        let summed = bin_image(&image.view(), 2);
+        assert_eq!(summed, array![[4 * 65535 - 1, 1]]);
+        let mean = bin_image_mean(&image.view(), 2);
+        assert_eq!(mean, array![[u16::MAX, 0]]);
+    }
+
+    #[test]
+    fn centroid_with_negative_wings_stays_within_the_image() {
+        // A background-subtracted peak in column 3. Weighted with their signs,
+        // the pixels sum to one count and put the centroid at column 20.
+        let image = array![[-4.0, -4.0, 1.0, 10.0, -2.0], [-1.0, -1.0, 0.0, 0.0, -1.0]];
+        let (x, y) = centroid(&image.view()).unwrap();
+        assert!((x - 32.0 / 11.0).abs() < 1e-12);
+        assert_eq!(y, 0.0);
+        assert!((0.0..5.0).contains(&x));
+
+        assert_eq!(
+            centroid(&array![[-1.0, 0.0], [f64::NAN, -3.0]].view()),
+            None
+        );
+    }
+}
@@ -2,13 +2,31 @@ use astrors_fork::io::hdus::{
     image::{imagehdu::ImageHDU, ImageData},
     primaryhdu::PrimaryHDU,
 };
-use ndarray::{ArrayBase, Axis, Dim, IxDynImpl, OwnedRepr};
-use polars::export::chrono::NaiveDateTime;
+use astrors_fork::io::header::card::{Card, CardValue};
+use astrors_fork::io::Header;
+use ndarray::{Array2, ArrayBase, ArrayD, ArrayView2, Axis, Dimension, Ix2, IxDyn, RawData};
+use polars::export::chrono::DateTime;
 use polars::prelude::*;
+use polars_arrow::array::{Array, FixedSizeListArray, PrimitiveArray};
+use polars_arrow::bitmap::Bitmap;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
 use std::ops::Mul;
+use std::path::Path;
 
 use crate::enums::HeaderValue;
 use crate::errors::FitsLoaderError;
+use crate::image::{
+    centroid, dead_zone_mask, deinterlace_rows, fit_peak, fit_poly_background, frame_quality,
+    integrate, peak_fwhm, poly_terms, q_map, radial_profile, ring_sum, saturation_fraction,
+    specular_bands, split_overscan, subtract_poly, thumbnail, weighted_sum, DetectorGeometry,
+};
+use crate::loader::{expand_braces, glob_match};
+use crate::options::{
+    ApertureCenter, BeamReference, ImageOptions, IntegrationMethod, LoaderOptions, NanPolicy,
+    PeakDetection, QMapOptions, QUnit, StringPadding,
+};
 
 // Find the theta offset between theta and the ccd theta or 2theta
 pub fn theta_offset(theta: f64, ccd_theta: f64) -> f64 {
@@ -25,70 +43,259 @@ pub fn q(lam: f64, theta: f64, angle_offset: f64) -> f64 {
     }
 }
 
-pub fn col_from_array(
+/// Packs a 2D array into a single `Array(Array(T, cols), rows)` cell, keeping
+/// the element type of `array`.
+///
+/// The nested sizes come from the actual dimensions, so frames need not be
+/// square. Anything but a 2D array is an error.
+pub fn col_from_array<T>(name: PlSmallStr, array: ArrayD<T::Native>) -> Result<Column, PolarsError>
+where
+    T: PolarsNumericType,
+{
+    let array = array
+        .into_dimensionality::<Ix2>()
+        .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+    let (rows, cols) = array.dim();
+    // The decoded frame is owned, so its buffer is reused unless it is strided.
+    let values = if array.is_standard_layout() {
+        array.into_raw_vec()
+    } else {
+        row_major(array.view())
+    };
+    fixed_size_cell::<T>(name, values, &[rows, cols])
+}
+
+/// Pixels of `array` in row-major order.
+///
+/// Filling the buffer of a multi-megapixel frame is dominated by page faults on
+/// the fresh allocation, so the rows are copied in parallel. On a pool thread,
+/// e.g. while files load in parallel, see `Parallelism`, every thread already
+/// has a frame of its own and the rows are copied in turn.
+fn row_major<T: Copy + Default + Send + Sync>(array: ArrayView2<T>) -> Vec<T> {
+    if rayon::current_thread_index().is_some() {
+        return array.iter().copied().collect();
+    }
+    let cols = array.ncols().max(1);
+    let mut values = vec![T::default(); array.len()];
+    values
+        .par_chunks_mut(cols)
+        .zip(array.axis_iter(Axis(0)).collect::<Vec<_>>())
+        .for_each(|(out, row)| match row.as_slice() {
+            Some(row) => out.copy_from_slice(row),
+            None => out
+                .iter_mut()
+                .zip(row)
+                .for_each(|(out, &value)| *out = value),
+        });
+    values
+}
+
+/// Wraps row-major `values` into a single `Array` cell of shape `shape`
+/// (outermost first) without going through a list builder: the values buffer
+/// is moved into one fixed-size list array per dimension.
+fn fixed_size_cell<T>(
     name: PlSmallStr,
-    array: ArrayBase<OwnedRepr<i64>, Dim<IxDynImpl>>,
-) -> Result<Column, PolarsError> {
-    let size0 = array.len_of(Axis(0));
-    let size1 = array.len_of(Axis(1));
-
-    let mut s = Column::new_empty(
-        name,
-        &DataType::List(Box::new(DataType::List(Box::new(DataType::Int64)))),
-    );
+    values: Vec<T::Native>,
+    shape: &[usize],
+) -> Result<Column, PolarsError>
+where
+    T: PolarsNumericType,
+{
+    let mut dtype = T::get_dtype().to_arrow(CompatLevel::newest());
+    let mut array: Box<dyn Array> = PrimitiveArray::from_vec(values).boxed();
+    let mut len = array.len();
+    for &size in shape.iter().rev() {
+        // Empty dimensions are rejected before packaging, `max` only avoids a
+        // division by zero here.
+        len /= size.max(1);
+        dtype = FixedSizeListArray::default_datatype(dtype, size);
+        array = FixedSizeListArray::try_new(dtype.clone(), len, array, None)?.boxed();
+    }
+    Ok(Series::from_arrow(name, array)?.into_column())
+}
 
-    let mut chunked_builder = ListPrimitiveChunkedBuilder::<Int64Type>::new(
-        PlSmallStr::EMPTY,
-        array.len_of(Axis(0)),
-        array.len_of(Axis(1)) * array.len_of(Axis(0)),
-        DataType::Int64,
-    );
-    for row in array.axis_iter(Axis(0)) {
-        match row.as_slice() {
-            Some(row) => chunked_builder.append_slice(row),
-            None => chunked_builder.append_slice(&row.to_owned().into_raw_vec()),
+/// Marks the pixels of an image cell as null where `valid` is unset, `valid`
+/// holding one bit per pixel in row-major order.
+fn with_null_pixels(column: Column, valid: &Bitmap) -> Result<Column, PolarsError> {
+    fn mask(array: &dyn Array, valid: &Bitmap) -> Box<dyn Array> {
+        match array.as_any().downcast_ref::<FixedSizeListArray>() {
+            Some(list) => FixedSizeListArray::new(
+                list.dtype().clone(),
+                list.len(),
+                mask(list.values().as_ref(), valid),
+                list.validity().cloned(),
+            )
+            .boxed(),
+            None => array.with_validity(Some(valid.clone())),
         }
     }
-    let new_series = chunked_builder
-        .finish()
-        .into_series()
-        .implode()
-        .unwrap()
-        .into_column();
-    let _ = s.extend(&new_series);
-    let s = s.cast(&DataType::Array(
-        Box::new(DataType::Array(Box::new(DataType::Int32), size1)),
-        size0,
-    ));
-    s
+    let series = column.as_materialized_series();
+    let array = mask(series.rechunk().chunks()[0].as_ref(), valid);
+    Ok(Series::from_arrow(series.name().clone(), array)?.into_column())
+}
+
+/// Packs a 2D array into a single `Array(Array(T, cols), rows)` cell.
+pub fn col_from_array2<T>(
+    name: PlSmallStr,
+    array: ArrayView2<T::Native>,
+) -> Result<Column, PolarsError>
+where
+    T: PolarsNumericType,
+{
+    let (rows, cols) = array.dim();
+    fixed_size_cell::<T>(name, row_major(array), &[rows, cols])
+}
+
+/// Packs a 2D array into a single flat `Array(T, rows * cols)` cell in row-major order.
+pub fn col_from_array_flat<T>(
+    name: PlSmallStr,
+    array: ArrayView2<T::Native>,
+) -> Result<Column, PolarsError>
+where
+    T: PolarsNumericType,
+{
+    let (rows, cols) = array.dim();
+    fixed_size_cell::<T>(name, row_major(array), &[rows * cols])
+}
+
+/// Unpacks the image stored in row `idx` of a nested list/array column into `f64`.
+///
+/// Null pixels become `NaN`. Flat (single level) cells are read as one row.
+pub fn image_from_column(column: &Column, idx: usize) -> Result<Array2<f64>, FitsLoaderError> {
+    let cell = column.as_materialized_series().slice(idx as i64, 1);
+    let mut rows = cell.explode()?;
+    if !matches!(rows.dtype(), DataType::List(_) | DataType::Array(_, _)) {
+        rows = rows.implode()?.into_series();
+    }
+    let pixels = rows.explode()?.cast(&DataType::Float64)?;
+    let (n_rows, n_pixels) = (rows.len(), pixels.len());
+    if n_rows == 0 || n_pixels % n_rows != 0 {
+        return Err(FitsLoaderError::EmptyImage(format!(
+            "row {} of {}",
+            idx,
+            column.name()
+        )));
+    }
+    let values: Vec<f64> = pixels
+        .f64()?
+        .into_iter()
+        .map(|v| v.unwrap_or(f64::NAN))
+        .collect();
+    Array2::from_shape_vec((n_rows, n_pixels / n_rows), values)
+        .map_err(|e| FitsLoaderError::FitsError(e.to_string()))
+}
+
+/// Packs one optional image per row into a nested `Float64` column, `None` rows
+/// are null.
+pub fn images_to_column(
+    name: PlSmallStr,
+    images: &[Option<Array2<f64>>],
+) -> Result<Column, FitsLoaderError> {
+    let mut cells = Vec::with_capacity(images.len());
+    for image in images {
+        cells.push(
+            image
+                .as_ref()
+                .map(|image| col_from_array2::<Float64Type>(name.clone(), image.view()))
+                .transpose()?,
+        );
+    }
+    let dtype = cells
+        .iter()
+        .flatten()
+        .map(|cell| cell.dtype().clone())
+        .next()
+        .unwrap_or(DataType::List(Box::new(DataType::List(Box::new(
+            DataType::Float64,
+        )))));
+
+    let mut out = Column::new_empty(name.clone(), &dtype);
+    for cell in cells {
+        let cell = match cell {
+            Some(cell) if cell.dtype() == &dtype => cell,
+            Some(cell) => cell.cast(&dtype)?,
+            None => Column::full_null(name.clone(), 1, &dtype),
+        };
+        out.append(&cell)?;
+    }
+    Ok(out)
 }
+
 // ================== CCD Raw Data Processing ============
-pub fn add_calculated_domains(lzf: LazyFrame) -> DataFrame {
+pub fn add_calculated_domains(lzf: LazyFrame) -> Result<DataFrame, FitsLoaderError> {
+    add_calculated_domains_with_unit(lzf, QUnit::default())
+}
+
+/// Same as `add_calculated_domains`, with q in `unit`, e.g. `Q [nm⁻¹]` for
+/// `QUnit::InverseNanometer`, ten times the value in inverse angstroms.
+pub fn add_calculated_domains_with_unit(
+    lzf: LazyFrame,
+    unit: QUnit,
+) -> Result<DataFrame, FitsLoaderError> {
+    Ok(calculated_domains_with_unit(lzf, unit).collect()?)
+}
+
+/// Columns giving the order the frames of a scan were taken in: the `Point
+/// Index` card when loaded, then the frame number parsed from the file name,
+/// which also orders files without the card. Sorted with nulls last, those
+/// files come after the ones with a `Point Index`.
+pub(crate) fn frame_order(schema: &Schema) -> Vec<&'static str> {
+    match schema.contains("Point Index") {
+        true => vec!["Point Index", "Frame Number"],
+        false => vec!["Frame Number"],
+    }
+}
+
+/// Lazy form of `add_calculated_domains`, for callers that keep building the query.
+pub fn calculated_domains(lzf: LazyFrame) -> LazyFrame {
+    calculated_domains_with_unit(lzf, QUnit::default())
+}
+
+/// Lazy form of `add_calculated_domains_with_unit`.
+pub fn calculated_domains_with_unit(lzf: LazyFrame, unit: QUnit) -> LazyFrame {
+    let q_scale = unit.per_inverse_angstrom();
     let h = physical_constants::PLANCK_CONSTANT_IN_EV_PER_HZ;
     let c = physical_constants::SPEED_OF_LIGHT_IN_VACUUM * 1e10;
+    let mut lzf = lzf;
+    // Columns can be renamed by unit overrides or be missing for non-XRR key sets,
+    // so only touch the ones that are actually present.
+    let schema = lzf.collect_schema().unwrap_or_default();
+    let has = |name: &str| schema.contains(name);
+
+    let rounding: Vec<Expr> = [
+        ("EXPOSURE [s]", 3),
+        ("Higher Order Suppressor [mm]", 2),
+        ("Horizontal Exit Slit Size [um]", 1),
+        ("Beamline Energy [eV]", 1),
+    ]
+    .into_iter()
+    .filter(|(name, _)| has(name))
+    .map(|(name, decimals)| col(name).round(decimals).alias(name))
+    .collect();
+
     // Calculate lambda and q values in angstrom
-    let lz = lzf
+    let mut lz = lzf
         .sort(["Sample Name"], Default::default())
         .sort(["Scan ID"], Default::default())
-        .sort(["Frame Number"], Default::default())
-        .with_columns(&[
-            col("EXPOSURE [s]").round(3).alias("EXPOSURE [s]"),
-            col("Higher Order Suppressor [mm]")
-                .round(2)
-                .alias("Higher Order Suppressor [mm]"),
-            col("Horizontal Exit Slit Size [um]")
-                .round(1)
-                .alias("Horizontal Exit Slit Size [um]"),
-            col("Beamline Energy [eV]")
-                .round(1)
-                .alias("Beamline Energy [eV]"),
-        ])
-        .with_column(
-            col("Beamline Energy [eV]")
-                .pow(-1)
-                .mul(lit(h * c))
-                .alias("Lambda [Å]"),
+        .sort(
+            frame_order(&schema),
+            SortMultipleOptions::default().with_nulls_last(true),
         );
+    if !rounding.is_empty() {
+        lz = lz.with_columns(&rounding);
+    }
+    if !has("Beamline Energy [eV]") {
+        return lz;
+    }
+    let lz = lz.with_column(
+        col("Beamline Energy [eV]")
+            .pow(-1)
+            .mul(lit(h * c))
+            .alias("Lambda [Å]"),
+    );
+    if !has("Sample Theta [deg]") || !has("CCD Theta [deg]") {
+        return lz;
+    }
 
     let angle_offset = lz
         .clone()
@@ -122,29 +329,29 @@ pub fn add_calculated_domains(lzf: LazyFrame) -> DataFrame {
         )
         .select(&[col("Theta Offset [deg]")])
         .collect()
-        .unwrap()
-        .get(0)
-        .unwrap()[0]
-        .try_extract::<f64>()
-        .unwrap();
+        .ok()
+        // Without a frame at theta = 0 there is nothing to reference, assume no offset.
+        .and_then(|df| df.get(0).and_then(|row| row[0].try_extract::<f64>().ok()))
+        .unwrap_or(0.0);
     // get the row cor
-    let lz = lz
-        .with_column(lit(angle_offset).alias("Theta Offset [deg]"))
+    lz.with_column(lit(angle_offset).alias("Theta Offset [deg]"))
         .with_column(
-            as_struct(vec![col("Sample Theta [deg]"), col("Lambda [Å]")])
+            as_struct(vec![col("Sample Theta [deg]"), col("Lambda [Å]")])
                 .map(
                     move |s| {
                         let struc = s.struct_()?;
                         let th_series = struc.field_by_name("Sample Theta [deg]")?;
                         let theta = th_series.f64()?;
-                        let lam_series = struc.field_by_name("Lambda [Å]")?;
+                        let lam_series = struc.field_by_name("Lambda [Å]")?;
                         let lam = lam_series.f64()?;
 
                         let out: Float64Chunked = theta
                             .into_iter()
                             .zip(lam.iter())
                             .map(|(theta, lam)| match (theta, lam) {
-                                (Some(theta), Some(lam)) => Some(q(lam, theta, angle_offset)),
+                                (Some(theta), Some(lam)) => {
+                                    Some(q(lam, theta, angle_offset) * q_scale)
+                                }
                                 _ => None,
                             })
                             .collect();
@@ -153,83 +360,1090 @@ pub fn add_calculated_domains(lzf: LazyFrame) -> DataFrame {
                     },
                     GetOutput::from_type(DataType::Float64),
                 )
-                .alias("Q [Å⁻¹]"),
-        );
-    lz.collect().unwrap()
+                .alias(unit.column()),
+        )
 }
 
 //
 // ================== CCD Data Loader ==================
-pub fn process_image(img: &ImageHDU) -> Result<Vec<Column>, FitsLoaderError> {
-    let bzero = img
-        .header
-        .get_card("BZERO")
-        .ok_or_else(|| FitsLoaderError::MissingHeaderKey("BZERO".into()))?
-        .value
-        .as_int()
-        .ok_or_else(|| FitsLoaderError::FitsError("BZERO not an integer".into()))?;
+/// `BZERO` of an integer image, `0` when the card is absent as the standard
+/// defines it.
+fn image_bzero(img: &ImageHDU) -> Result<i64, FitsLoaderError> {
+    match img.header.get_card("BZERO") {
+        Some(card) => card
+            .value
+            .as_int()
+            .ok_or_else(|| FitsLoaderError::FitsError("BZERO not an integer".into())),
+        None => Ok(0),
+    }
+}
+
+/// Number of frames stacked in a 3D image cube, `None` for a 2D image.
+///
+/// The frame index is the outermost axis on disk (`NAXIS3`).
+pub fn cube_depth(img: &ImageHDU) -> Option<usize> {
+    match img.data.get_shape().as_slice() {
+        [_, _, depth] => Some(*depth),
+        _ => None,
+    }
+}
 
+/// Integer pixel values of the 2D image, or of slice `frame` of a cube, with
+/// `BZERO` applied, as `(NAXIS2, NAXIS1)`, see `slice_frame`.
+///
+/// The values arrive in native byte order: `astrors_fork` decodes the big-endian
+/// data unit for every `BITPIX`, so no byte swapping happens here.
+fn frame_data(img: &ImageHDU, frame: Option<usize>) -> Result<ArrayD<i64>, FitsLoaderError> {
+    let bzero = image_bzero(img)?;
     match &img.data {
-        ImageData::I16(image) => {
-            let data = image.map(|&x| i64::from(x as i64 + bzero));
-            Ok(vec![col_from_array("Raw".into(), data.clone()).unwrap()])
-        }
+        ImageData::U8(image) => slice_frame(image, frame, |x| i64::from(x) + bzero),
+        ImageData::I16(image) => slice_frame(image, frame, |x| i64::from(x) + bzero),
+        ImageData::I32(image) => slice_frame(image, frame, |x| i64::from(x) + bzero),
         _ => Err(FitsLoaderError::UnsupportedImageData),
     }
 }
 
-pub fn process_metadata(
+/// Whether the image HDU holds floating point data (`BITPIX` -32 or -64).
+fn is_float_image(img: &ImageHDU) -> bool {
+    matches!(img.data, ImageData::F32(_) | ImageData::F64(_))
+}
+
+/// Same as `frame_data` for floating point images, `BZERO` is optional here.
+fn float_frame_data(img: &ImageHDU, frame: Option<usize>) -> Result<ArrayD<f64>, FitsLoaderError> {
+    let bzero = card_f64(&img.header, "BZERO").unwrap_or(0.0);
+    match &img.data {
+        ImageData::F32(image) => slice_frame(image, frame, |x| f64::from(x) + bzero),
+        ImageData::F64(image) => slice_frame(image, frame, |x| x + bzero),
+        _ => Err(FitsLoaderError::UnsupportedImageData),
+    }
+}
+
+/// The 2D image, or slice `frame` of a cube, as `(rows, cols)` =
+/// `(NAXIS2, NAXIS1)`, the way astropy and numpy read FITS.
+///
+/// astrors shapes the data unit as `[NAXIS1, NAXIS2, NAXIS3]` but fills it in
+/// disk order, where `NAXIS1` varies fastest, so its indices do not address
+/// pixels. The values are taken in disk order instead, a slice being the
+/// contiguous run of `NAXIS1 * NAXIS2` values, and reshaped with the axes
+/// reversed. Other dimensionalities are returned as they are, to be rejected
+/// by the caller.
+fn slice_frame<T: Copy, U>(
+    image: &ArrayD<T>,
+    frame: Option<usize>,
+    convert: impl Fn(T) -> U,
+) -> Result<ArrayD<U>, FitsLoaderError> {
+    let shape = image.shape();
+    let (n1, n2, skip) = match (shape, frame) {
+        ([n1, n2], None) => (*n1, *n2, 0),
+        (_, None) => return Ok(image.mapv(convert)),
+        ([n1, n2, depth], Some(frame)) if frame < *depth => (*n1, *n2, frame * n1 * n2),
+        _ => {
+            return Err(FitsLoaderError::FitsError(format!(
+                "no frame {} in image of shape {:?}",
+                frame.unwrap_or_default(),
+                shape
+            )))
+        }
+    };
+    let values: Vec<U> = image
+        .iter()
+        .skip(skip)
+        .take(n1 * n2)
+        .map(|&x| convert(x))
+        .collect();
+    ArrayD::from_shape_vec(IxDyn(&[n2, n1]), values)
+        .map_err(|e| FitsLoaderError::FitsError(e.to_string()))
+}
+
+/// `image` laid out for astrors to write, the inverse of `slice_frame`: the
+/// values in row-major order under the shape `[cols, rows]`, which astrors
+/// writes as `NAXIS1 = cols` and `NAXIS2 = rows`.
+pub(crate) fn astrors_layout<T: Clone>(image: &ArrayView2<T>) -> ArrayD<T> {
+    let (rows, cols) = image.dim();
+    let values: Vec<T> = image.iter().cloned().collect();
+    ArrayD::from_shape_vec(IxDyn(&[cols, rows]), values).expect("shape matches the values")
+}
+
+/// Decodes the image HDU into `f64` pixel values with `BZERO` applied.
+///
+/// Integer and floating point images are both accepted.
+pub fn image_pixels(img: &ImageHDU) -> Result<Array2<f64>, FitsLoaderError> {
+    frame_pixels(img, None)
+}
+
+/// Same as `image_pixels` for slice `frame` of a cube, see `cube_depth`.
+pub fn frame_pixels(img: &ImageHDU, frame: Option<usize>) -> Result<Array2<f64>, FitsLoaderError> {
+    let pixels = if is_float_image(img) {
+        float_frame_data(img, frame)?
+    } else {
+        frame_data(img, frame)?.mapv(|x| x as f64)
+    };
+    let shape = pixels.shape().to_vec();
+    pixels
+        .into_dimensionality::<Ix2>()
+        .map_err(|_| FitsLoaderError::UnsupportedDimensionality {
+            path: String::new(),
+            shape,
+        })
+}
+
+/// Decodes the image HDU into `u16` pixels for callers of the legacy unsigned path.
+///
+/// Values outside `[0, u16::MAX]` after applying `BZERO` saturate at the nearest
+/// bound instead of wrapping, `NaN` becomes `0`. Returns the image together with
+/// the number of pixels that had to be clamped.
+pub fn pixels_u16_saturating(img: &ImageHDU) -> Result<(Array2<u16>, usize), FitsLoaderError> {
+    let bzero = card_f64(&img.header, "BZERO").unwrap_or(0.0);
+    let data = match &img.data {
+        ImageData::U8(image) => slice_frame(image, None, |x| x as f64 + bzero)?,
+        ImageData::I16(image) => slice_frame(image, None, |x| x as f64 + bzero)?,
+        ImageData::I32(image) => slice_frame(image, None, |x| x as f64 + bzero)?,
+        ImageData::F32(image) => slice_frame(image, None, |x| x as f64 + bzero)?,
+        ImageData::F64(image) => slice_frame(image, None, |x| x + bzero)?,
+        _ => return Err(FitsLoaderError::UnsupportedImageData),
+    };
+    let clamped = data
+        .iter()
+        .filter(|&&x| x.is_nan() || !(0.0..=u16::MAX as f64).contains(&x))
+        .count();
+    let data = data.mapv(|x| x.clamp(0.0, u16::MAX as f64) as u16);
+    let shape = data.shape().to_vec();
+    let data = data.into_dimensionality::<Ix2>().map_err(|_| {
+        FitsLoaderError::UnsupportedDimensionality {
+            path: String::new(),
+            shape,
+        }
+    })?;
+    Ok((data, clamped))
+}
+
+/// Reads a numeric card as `f64`, accepting both float and integer values.
+pub(crate) fn card_f64(header: &Header, key: &str) -> Option<f64> {
+    let value = &header.get_card(key)?.value;
+    value
+        .as_float()
+        .or_else(|| value.as_int().map(|v| v as f64))
+}
+
+/// Computes the per-pixel `Qxy`/`Qz` maps of a frame as image-shaped list columns.
+///
+/// The geometry is read from the primary header (`PIXSIZE`, `SDD`,
+/// `Beamline Energy` and `Sample Theta`), the beam center is the intensity
+/// centroid of the frame. See `crate::image::q_map` for the assumptions made.
+pub fn process_q_map(
     hdu: &PrimaryHDU,
-    keys: &Vec<HeaderValue>,
+    img: &ImageHDU,
+    options: &QMapOptions,
 ) -> Result<Vec<Column>, FitsLoaderError> {
-    if keys.is_empty() {
-        Ok(hdu
-            .header
-            .iter()
-            .map(|card| {
-                let name = card.keyword.as_str();
-                let value = card.value.as_float().unwrap_or(0.0);
-                Column::new(name.into(), &[value])
+    process_frame_q_map(hdu, img, None, options)
+}
+
+/// Same as `process_q_map` for slice `frame` of a cube, see `cube_depth`.
+pub fn process_frame_q_map(
+    hdu: &PrimaryHDU,
+    img: &ImageHDU,
+    frame: Option<usize>,
+    options: &QMapOptions,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    let card = |key: HeaderValue| {
+        card_f64(&hdu.header, key.hdu())
+            .ok_or_else(|| FitsLoaderError::MissingHeaderKey(key.hdu().to_string()))
+    };
+    let pixel_size = card(HeaderValue::PixelSize)? * 1e-3;
+    let distance = card(HeaderValue::SampleDetectorDistance)?;
+    let energy = card(HeaderValue::BeamlineEnergy)?;
+    let theta = card(HeaderValue::SampleTheta)?;
+
+    let pixels = frame_pixels(img, frame)?;
+    let (rows, cols) = pixels.dim();
+    let center =
+        centroid(&pixels.view()).unwrap_or(((cols as f64 - 1.0) / 2.0, (rows as f64 - 1.0) / 2.0));
+
+    let h = physical_constants::PLANCK_CONSTANT_IN_EV_PER_HZ;
+    let c = physical_constants::SPEED_OF_LIGHT_IN_VACUUM * 1e10;
+    let geometry = DetectorGeometry {
+        pixel_size,
+        distance,
+        wavelength: h * c / energy,
+        incidence: theta,
+        center,
+    };
+    let (qxy, qz) = q_map((rows, cols), &geometry, options.roi)?;
+    Ok(vec![
+        col_from_array2::<Float64Type>("Qxy [Å⁻¹]".into(), qxy.view())?,
+        col_from_array2::<Float64Type>("Qz [Å⁻¹]".into(), qz.view())?,
+    ])
+}
+
+pub fn process_image(
+    img: &ImageHDU,
+    options: &ImageOptions,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    process_frame(img, None, options)
+}
+
+/// Same as `process_image` for slice `frame` of a cube, see `cube_depth`.
+pub fn process_frame(
+    img: &ImageHDU,
+    frame: Option<usize>,
+    options: &ImageOptions,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    // Truncated files can decode into a zero-length array, bail before packaging.
+    if img.data.get_shape().iter().product::<usize>() == 0 {
+        return Err(FitsLoaderError::EmptyImage(String::new()));
+    }
+    check_dimensionality(img)?;
+
+    // Floating point images have no integer `Raw`, they are stored as floats of
+    // their own width.
+    let mut data = if is_float_image(img) {
+        None
+    } else {
+        Some(frame_data(img, frame)?)
+    };
+    let mut pixels = frame_pixels(img, frame)?;
+    // The integer pixels are reordered as well, so `Raw` keeps its dtype.
+    if let Some(readout) = &options.deinterlace {
+        pixels = deinterlace_rows(&pixels.view(), readout)?;
+        data = data
+            .map(|data| {
+                let data = data
+                    .into_dimensionality::<Ix2>()
+                    .map_err(|e| FitsLoaderError::FitsError(e.to_string()))?;
+                Ok::<_, FitsLoaderError>(deinterlace_rows(&data.view(), readout)?.into_dyn())
             })
-            .collect())
+            .transpose()?;
+    }
+    let bias = match &options.overscan {
+        Some(overscan) => {
+            let (bias, science) = split_overscan(&pixels.view(), overscan)?;
+            pixels = science;
+            Some(bias)
+        }
+        None => None,
+    };
+
+    let (rows, cols) = pixels.dim();
+    let dead = if options.dead_zones.is_empty() {
+        None
     } else {
-        keys.iter()
-            .map(|key| {
-                let val = hdu
-                    .header
-                    .get_card(key.hdu())
-                    .ok_or_else(|| FitsLoaderError::MissingHeaderKey(key.hdu().to_string()))?
-                    .value
-                    .as_float()
-                    .ok_or_else(|| {
-                        FitsLoaderError::Other(format!("Value for {} is not a float", key.hdu()))
-                    })?;
-                Ok(Column::new(key.name().into(), &[val]))
+        Some(dead_zone_mask(&options.dead_zones, (rows, cols))?)
+    };
+    // Saturation and the other checks look at the detector ADU, before the bias,
+    // with the dead zones counting as empty pixels.
+    let quality = options.quality.as_ref().map(|quality| match &dead {
+        Some(dead) => {
+            let live = ndarray::Zip::from(&pixels)
+                .and(dead)
+                .map_collect(|&v, &dead| if dead { 0.0 } else { v });
+            frame_quality(&live.view(), quality)
+        }
+        None => frame_quality(&pixels.view(), quality),
+    });
+    if let Some(dead) = &dead {
+        ndarray::Zip::from(&mut pixels)
+            .and(dead)
+            .for_each(|v, &dead| {
+                if dead {
+                    *v = f64::NAN;
+                }
+            });
+    }
+    let saturation = options
+        .quality
+        .as_ref()
+        .filter(|quality| quality.saturation_fraction)
+        .map(|quality| saturation_fraction(&pixels.view(), quality.saturation_level));
+    if let Some(bias) = bias.flatten() {
+        pixels.mapv_inplace(|x| x - bias);
+    }
+    for reference in [&options.dark, &options.flat].into_iter().flatten() {
+        if reference.dim() != pixels.dim() {
+            return Err(FitsLoaderError::ImageShapeMismatch {
+                path: String::new(),
+                expected: reference.dim(),
+                found: pixels.dim(),
+            });
+        }
+    }
+    if let Some(dark) = &options.dark {
+        pixels -= dark.as_ref();
+    }
+    if let Some(flat) = &options.flat {
+        pixels /= flat.as_ref();
+    }
+    if let Some(gain) = options.gain {
+        pixels.mapv_inplace(|x| x * gain);
+    }
+    let background = match &options.poly_background {
+        Some(background) => {
+            let coefficients =
+                fit_poly_background(&pixels.view(), background.order, background.mask)?;
+            pixels = subtract_poly(&pixels.view(), background.order, &coefficients);
+            Some((background, coefficients))
+        }
+        None => None,
+    };
+    // `pixel_scale` only changes what is stored, the statistics below see `pixels`.
+    let scaled = options.pixel_scale.map(|scale| pixels.mapv(|x| x * scale));
+    let stored_pixels = scaled.as_ref().unwrap_or(&pixels);
+    let transpose = options.transpose;
+    let raw = if options.drop_image {
+        None
+    } else if let Some(dtype) = &options.image_dtype {
+        Some(typed_pixels(
+            stored_axes(stored_pixels.view(), transpose),
+            dtype,
+            options.fixed_size_list,
+        )?)
+    } else if options.int32 {
+        let data = stored_pixels.mapv(|x| x.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32);
+        let data = stored_axes(data.view(), transpose);
+        if options.fixed_size_list {
+            Some(col_from_array_flat::<Int32Type>("Raw".into(), data)?)
+        } else {
+            Some(col_from_array2::<Int32Type>("Raw".into(), data)?)
+        }
+    } else {
+        let float = options.gain.is_some()
+            || options.pixel_scale.is_some()
+            || options.dark.is_some()
+            || options.flat.is_some()
+            || options.overscan.is_some()
+            || options.poly_background.is_some();
+        let stored = stored_axes(stored_pixels.view(), transpose);
+        let fixed_size_list = options.fixed_size_list;
+        Some(match data.filter(|_| !float) {
+            Some(data) if raw_needs_int64(img)? => {
+                raw_column::<Int64Type>(stored_axes(data, transpose), fixed_size_list)?
+            }
+            Some(data) => raw_column::<Int32Type>(
+                stored_axes(data.mapv(|x| x as i32), transpose),
+                fixed_size_list,
+            )?,
+            // Single precision frames keep their dtype, at half the size.
+            None if !float && matches!(img.data, ImageData::F32(_)) => {
+                typed_pixels(stored, &DataType::Float32, fixed_size_list)?
+            }
+            None if fixed_size_list => col_from_array_flat::<Float64Type>("Raw".into(), stored)?,
+            None => col_from_array2::<Float64Type>("Raw".into(), stored)?,
+        })
+    };
+
+    let raw = match (raw, &dead) {
+        (Some(raw), Some(dead)) => {
+            let valid: Bitmap = stored_axes(dead.view(), transpose)
+                .iter()
+                .map(|&dead| !dead)
+                .collect();
+            Some(with_null_pixels(raw, &valid)?)
+        }
+        (raw, _) => raw,
+    };
+    let mut columns: Vec<Column> = raw.into_iter().collect();
+    if options.hdu_info {
+        let bitpix = match img.header.get_card("BITPIX").map(|card| &card.value) {
+            Some(CardValue::INT(bitpix)) => *bitpix as i32,
+            _ => return Err(FitsLoaderError::MissingHeaderKey("BITPIX".into())),
+        };
+        columns.push(Column::new("BITPIX".into(), [bitpix]));
+    }
+    if let Some(quality) = quality {
+        columns.push(Column::new("Quality".into(), [quality.bits()]));
+    }
+    if let Some(saturation) = saturation {
+        columns.push(Column::new("Saturation Fraction".into(), [saturation]));
+    }
+    if let Some(bias) = bias {
+        columns.push(Column::new("Bias".into(), [bias]));
+    }
+    if let Some((background, coefficients)) = background.filter(|(b, _)| b.coefficients) {
+        for ((i, j), c) in poly_terms(background.order).into_iter().zip(coefficients) {
+            columns.push(Column::new(format!("Background c{}{}", i, j).into(), [c]));
+        }
+    }
+    if options.fixed_size_list {
+        let (rows, cols) = if transpose {
+            (cols, rows)
+        } else {
+            (rows, cols)
+        };
+        columns.push(Column::new("Image Rows".into(), [rows as u32]));
+        columns.push(Column::new("Image Cols".into(), [cols as u32]));
+    }
+    if let Some(size) = options.thumbnail_size {
+        let preview = thumbnail(&stored_pixels.view(), size);
+        columns.push(col_from_array2::<Float64Type>(
+            "Thumbnail".into(),
+            stored_axes(preview.view(), transpose),
+        )?);
+    }
+    #[cfg(feature = "png")]
+    if let Some(png) = &options.png_thumbnail {
+        let encoded = crate::image::png_thumbnail(&pixels.view(), png)?;
+        columns.push(Column::new("PNG Thumbnail".into(), [encoded]));
+    }
+    let rois = [
+        options.peak_fit.as_ref().and_then(|peak| peak.roi),
+        options.fwhm.and_then(|fwhm| fwhm.roi),
+        options
+            .integration
+            .as_ref()
+            .and_then(|integration| integration.roi),
+        options.roi_ratio.map(|ratio| ratio.peak),
+        options.roi_ratio.map(|ratio| ratio.reference),
+    ];
+    let named = options.named_rois.iter().map(|named| Some(named.roi()));
+    for roi in rois.into_iter().chain(named).flatten() {
+        roi.validate(pixels.dim())?;
+    }
+    for (i, named) in options.named_rois.iter().enumerate() {
+        if options.named_rois[..i]
+            .iter()
+            .any(|other| other.name == named.name)
+        {
+            return Err(FitsLoaderError::InvalidConfig(format!(
+                "duplicate ROI name {:?}",
+                named.name
+            )));
+        }
+    }
+    if let Some(integration) = &options.integration {
+        if let Some(weights) = &integration.weight_mask {
+            if integration.roi.is_some() || integration.method != IntegrationMethod::Sum {
+                return Err(FitsLoaderError::InvalidConfig(
+                    "weight_mask replaces the integration roi and method".into(),
+                ));
+            }
+            if weights.dim() != pixels.dim() {
+                return Err(FitsLoaderError::ImageShapeMismatch {
+                    path: String::new(),
+                    expected: weights.dim(),
+                    found: pixels.dim(),
+                });
+            }
+        }
+    }
+    if let Some(bands) = &options.specular_bands {
+        if bands.offset <= 2 * bands.half_width {
+            return Err(FitsLoaderError::InvalidConfig(
+                "specular_bands offset must exceed twice the half width".into(),
+            ));
+        }
+    }
+    if matches!(options.peak_detection, Some(PeakDetection::Snr(_)))
+        && (options.aperture_radius.is_none() || options.background_annulus.is_none())
+    {
+        return Err(FitsLoaderError::InvalidConfig(
+            "peak detection by SNR needs aperture_radius and background_annulus".into(),
+        ));
+    }
+    let masked = dead.map_or(0, |dead| dead.iter().filter(|&&dead| dead).count());
+    columns.extend(image_stats(&pixels.view(), options, masked));
+    Ok(columns)
+}
+
+/// Errors with `UnsupportedDimensionality` unless the image is a 2D frame or a
+/// 3D cube of frames.
+fn check_dimensionality(img: &ImageHDU) -> Result<(), FitsLoaderError> {
+    let shape = img.data.get_shape();
+    if matches!(shape.len(), 2 | 3) {
+        return Ok(());
+    }
+    Err(FitsLoaderError::UnsupportedDimensionality {
+        path: String::new(),
+        shape: shape.to_vec(),
+    })
+}
+
+/// `array` as stored in the image columns: with its axes swapped to
+/// `(cols, rows)` when `ImageOptions::transpose` is set.
+fn stored_axes<S: RawData, D: Dimension>(
+    array: ArrayBase<S, D>,
+    transpose: bool,
+) -> ArrayBase<S, D> {
+    if transpose {
+        array.reversed_axes()
+    } else {
+        array
+    }
+}
+
+/// Whether the uncorrected `Raw` pixels of `img` need `Int64`.
+///
+/// 8 and 16 bit data fits `Int32` for any `BZERO` the detectors write, 32 bit
+/// data only without an offset: unsigned 32 bit frames (`BZERO = 2^31`) reach
+/// past `i32::MAX`.
+fn raw_needs_int64(img: &ImageHDU) -> Result<bool, FitsLoaderError> {
+    Ok(matches!(img.data, ImageData::I32(_)) && image_bzero(img)? != 0)
+}
+
+/// Packs the integer pixels as `Raw`, nested or flat as `fixed_size_list` asks.
+fn raw_column<T>(data: ArrayD<T::Native>, fixed_size_list: bool) -> Result<Column, FitsLoaderError>
+where
+    T: PolarsNumericType,
+{
+    if !fixed_size_list {
+        return Ok(col_from_array::<T>("Raw".into(), data)?);
+    }
+    let data = data
+        .into_dimensionality::<Ix2>()
+        .map_err(|e| FitsLoaderError::FitsError(e.to_string()))?;
+    Ok(col_from_array_flat::<T>("Raw".into(), data.view())?)
+}
+
+/// Packs the corrected pixels as `Raw` of `dtype`, see `ImageOptions::image_dtype`.
+fn typed_pixels(
+    pixels: ArrayView2<f64>,
+    dtype: &DataType,
+    fixed_size_list: bool,
+) -> Result<Column, FitsLoaderError> {
+    fn pack<T: PolarsNumericType>(
+        pixels: ArrayView2<f64>,
+        convert: impl Fn(f64) -> T::Native,
+        fixed_size_list: bool,
+    ) -> Result<Column, FitsLoaderError> {
+        let data = pixels.mapv(convert);
+        Ok(if fixed_size_list {
+            col_from_array_flat::<T>("Raw".into(), data.view())?
+        } else {
+            col_from_array2::<T>("Raw".into(), data.view())?
+        })
+    }
+    // `as` saturates at the bounds of the target and maps NaN to zero.
+    let fixed = fixed_size_list;
+    match dtype {
+        DataType::Float64 => pack::<Float64Type>(pixels, |x| x, fixed),
+        DataType::Float32 => pack::<Float32Type>(pixels, |x| x as f32, fixed),
+        DataType::Int8 => pack::<Int8Type>(pixels, |x| x.round() as i8, fixed),
+        DataType::Int16 => pack::<Int16Type>(pixels, |x| x.round() as i16, fixed),
+        DataType::Int32 => pack::<Int32Type>(pixels, |x| x.round() as i32, fixed),
+        DataType::Int64 => pack::<Int64Type>(pixels, |x| x.round() as i64, fixed),
+        DataType::UInt8 => pack::<UInt8Type>(pixels, |x| x.round() as u8, fixed),
+        DataType::UInt16 => pack::<UInt16Type>(pixels, |x| x.round() as u16, fixed),
+        DataType::UInt32 => pack::<UInt32Type>(pixels, |x| x.round() as u32, fixed),
+        DataType::UInt64 => pack::<UInt64Type>(pixels, |x| x.round() as u64, fixed),
+        other => Err(FitsLoaderError::InvalidConfig(format!(
+            "image_dtype must be a numeric type, got {}",
+            other
+        ))),
+    }
+}
+
+/// Columns holding intensities integrated over (part of) the frame, the ones
+/// scaled by `CurrentNormalization`.
+pub const INTEGRATED_COLUMNS: [&str; 11] = [
+    "Total Counts",
+    "Aperture Counts",
+    "Background Counts",
+    "Net Counts",
+    "Net Counts Error",
+    "Intensity",
+    "Peak ROI Counts",
+    "Reference ROI Counts",
+    "Specular",
+    "Off-Specular",
+    "Specular Net",
+];
+
+/// Resolves the placement of an aperture on a frame, `None` when a centroid
+/// is requested but the frame holds no positive counts and there is no fallback.
+fn aperture_center(pixels: &ArrayView2<f64>, center: &ApertureCenter) -> Option<(f64, f64)> {
+    match *center {
+        ApertureCenter::Centroid { fallback } => centroid(pixels).or(fallback),
+        ApertureCenter::Fixed(center) => Some(center),
+    }
+}
+
+/// Scalar statistics derived from the pixel values, one column per enabled option.
+///
+/// `masked` is the number of dead-zone pixels, which are `NaN` and always left
+/// out of the sums.
+fn image_stats(pixels: &ArrayView2<f64>, options: &ImageOptions, masked: usize) -> Vec<Column> {
+    let mut columns = vec![];
+    let mut snr = None;
+    if options.nan_policy == NanPolicy::Skip {
+        let skipped = pixels.iter().filter(|v| !v.is_finite()).count() - masked;
+        columns.push(Column::new("NaN Pixels".into(), [skipped as u32]));
+    }
+    let skip_nan = options.nan_policy == NanPolicy::Skip || masked > 0;
+    if options.total_counts {
+        let total: f64 = if skip_nan {
+            pixels
+                .iter()
+                .filter(|v| v.is_finite())
+                .fold(0.0, |sum, v| sum + v)
+        } else {
+            pixels.sum()
+        };
+        columns.push(Column::new("Total Counts".into(), [total]));
+    }
+    if let Some(radius) = options.aperture_radius {
+        let center = aperture_center(pixels, &options.aperture_center);
+        let aperture = center.map(|center| ring_sum(pixels, center, None, radius, skip_nan));
+        let counts = aperture.map(|(sum, _)| sum);
+        columns.push(Column::new("Aperture Counts".into(), [counts]));
+        columns.push(Column::new("Aperture X [px]".into(), [center.map(|c| c.0)]));
+        columns.push(Column::new("Aperture Y [px]".into(), [center.map(|c| c.1)]));
+
+        if let Some((inner, outer)) = options.background_annulus {
+            // Background per pixel from the annulus, scaled by k = n_aperture / n_annulus.
+            // Var(net) = Var(gross) + k² Var(annulus) = gross + k * background, which is
+            // sqrt(counts + background) for an annulus as large as the aperture.
+            let net = center
+                .zip(aperture)
+                .and_then(|(center, (gross, n_aperture))| {
+                    let (annulus, n_annulus) =
+                        ring_sum(pixels, center, Some(inner), outer, skip_nan);
+                    (n_annulus > 0).then(|| {
+                        let k = n_aperture as f64 / n_annulus as f64;
+                        let background = annulus * k;
+                        let error = (gross + k * background).max(0.0).sqrt();
+                        (background, gross - background, error)
+                    })
+                });
+            columns.push(Column::new("Background Counts".into(), [net.map(|n| n.0)]));
+            columns.push(Column::new("Net Counts".into(), [net.map(|n| n.1)]));
+            columns.push(Column::new("Net Counts Error".into(), [net.map(|n| n.2)]));
+            snr = net.and_then(|(_, net, error)| (error > 0.0).then(|| net / error));
+            if options.snr {
+                columns.push(Column::new("SNR".into(), [snr]));
+            }
+        }
+    }
+    if let Some(reference) = &options.beam_offset {
+        // Against the first frame, the centroid itself until the frames are combined.
+        let (x0, y0) = match reference {
+            BeamReference::FirstFrame => (0.0, 0.0),
+            BeamReference::Fixed(center) => *center,
+        };
+        let offset = centroid(pixels).map(|(x, y)| (x - x0, y - y0));
+        columns.push(Column::new(
+            "Beam Offset X [px]".into(),
+            [offset.map(|o| o.0)],
+        ));
+        columns.push(Column::new(
+            "Beam Offset Y [px]".into(),
+            [offset.map(|o| o.1)],
+        ));
+    }
+    if let Some(peak) = &options.peak_fit {
+        let fit = fit_peak(pixels, peak.roi);
+        columns.extend([
+            Column::new("Peak X [px]".into(), [fit.map(|f| f.x)]),
+            Column::new("Peak Y [px]".into(), [fit.map(|f| f.y)]),
+            Column::new("Peak Sigma X [px]".into(), [fit.map(|f| f.sigma_x)]),
+            Column::new("Peak Sigma Y [px]".into(), [fit.map(|f| f.sigma_y)]),
+            Column::new("Peak Amplitude".into(), [fit.map(|f| f.amplitude)]),
+        ]);
+    }
+    if let Some(profile) = &options.radial_profile {
+        let profile = aperture_center(pixels, &profile.center)
+            .map(|center| radial_profile(pixels, center, profile.bins));
+        let radii = profile.as_ref().map(|p| Series::new("".into(), &p.radii));
+        let intensity = profile.map(|p| Series::new("".into(), p.intensity));
+        columns.push(Column::new("Radial Profile".into(), [intensity]));
+        columns.push(Column::new("Radial Profile R [px]".into(), [radii]));
+    }
+    if let Some(fwhm) = &options.fwhm {
+        let (x, y) = peak_fwhm(pixels, fwhm.roi, fwhm.method);
+        columns.push(Column::new("FWHM X [px]".into(), [x]));
+        columns.push(Column::new("FWHM Y [px]".into(), [y]));
+    }
+    if let Some(integration) = &options.integration {
+        let (intensity, method) = match &integration.weight_mask {
+            Some(weights) => (weighted_sum(pixels, &weights.view()), "weighted"),
+            None => (
+                integrate(pixels, integration.roi, integration.method),
+                integration.method.name(),
+            ),
+        };
+        columns.push(Column::new("Intensity".into(), [intensity]));
+        columns.push(Column::new("Integration Method".into(), [method]));
+    }
+    if let Some(ratio) = &options.roi_ratio {
+        let sum = |roi| integrate(pixels, Some(roi), IntegrationMethod::Sum);
+        let (peak, reference) = (sum(ratio.peak), sum(ratio.reference));
+        let value = peak
+            .zip(reference)
+            .and_then(|(peak, reference)| (reference != 0.0).then(|| peak / reference));
+        columns.push(Column::new("Peak ROI Counts".into(), [peak]));
+        columns.push(Column::new("Reference ROI Counts".into(), [reference]));
+        columns.push(Column::new("ROI Ratio".into(), [value]));
+    }
+    if let Some(bands) = &options.specular_bands {
+        let sums = specular_bands(pixels, bands);
+        columns.push(Column::new("Specular".into(), [sums.map(|s| s.0)]));
+        columns.push(Column::new("Off-Specular".into(), [sums.map(|s| s.1)]));
+        columns.push(Column::new(
+            "Specular Net".into(),
+            [sums.map(|s| s.0 - s.1)],
+        ));
+    }
+    for named in &options.named_rois {
+        let counts = integrate(pixels, Some(named.roi()), IntegrationMethod::Sum);
+        columns.push(Column::new(named.column().into(), [counts]));
+    }
+    if let Some(detection) = options.peak_detection {
+        let found = match detection {
+            PeakDetection::Snr(threshold) => snr.is_some_and(|snr| snr >= threshold),
+            PeakDetection::AboveBackground(threshold) => {
+                peak_above_background(pixels).is_some_and(|height| height >= threshold)
+            }
+        };
+        if !found {
+            let centroid = matches!(options.aperture_center, ApertureCenter::Centroid { .. });
+            for column in columns.iter_mut() {
+                let name = column.name().as_str();
+                if PEAK_COLUMNS.contains(&name)
+                    || (centroid && matches!(name, "Aperture X [px]" | "Aperture Y [px]"))
+                    || matches!(name, "Beam Offset X [px]" | "Beam Offset Y [px]")
+                {
+                    *column = Column::full_null(column.name().clone(), 1, column.dtype());
+                }
+            }
+        }
+        columns.push(Column::new("Peak Found".into(), [found]));
+    }
+    columns
+}
+
+/// Columns of the Gaussian peak fit and the peak width, nulled on frames
+/// without a peak.
+const PEAK_COLUMNS: [&str; 7] = [
+    "Peak X [px]",
+    "Peak Y [px]",
+    "Peak Sigma X [px]",
+    "Peak Sigma Y [px]",
+    "Peak Amplitude",
+    "FWHM X [px]",
+    "FWHM Y [px]",
+];
+
+/// Height of the brightest finite pixel above the median finite pixel, `None`
+/// for a frame without finite pixels.
+fn peak_above_background(pixels: &ArrayView2<f64>) -> Option<f64> {
+    let mut values: Vec<f64> = pixels.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let middle = values.len() / 2;
+    let (_, median, _) = values.select_nth_unstable_by(middle, |a, b| a.total_cmp(b));
+    Some(max - *median)
+}
+
+/// Builds a column name of the form `"{keyword} [{unit}]"`.
+///
+/// A unit found in `unit_overrides` wins over the built-in `default_unit`; keys with
+/// neither keep the bare keyword. Units may be given with or without brackets.
+pub fn unit_column_name(
+    keyword: &str,
+    default_unit: Option<&str>,
+    unit_overrides: &HashMap<String, String>,
+) -> String {
+    let unit = unit_overrides
+        .get(keyword)
+        .map(|unit| unit.as_str())
+        .or(default_unit)
+        .map(|unit| unit.trim().trim_start_matches('[').trim_end_matches(']'));
+    match unit {
+        Some(unit) if !unit.is_empty() => format!("{} [{}]", keyword, unit),
+        _ => keyword.to_string(),
+    }
+}
+
+/// Splits `"Beamline Energy [eV]"` into `("Beamline Energy", Some("eV"))`.
+pub fn split_unit(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once(" [") {
+        Some((keyword, unit)) if unit.ends_with(']') => (keyword, Some(&unit[..unit.len() - 1])),
+        _ => (name, None),
+    }
+}
+
+/// Arrow field metadata key holding the unit of a column.
+pub const UNIT_METADATA_KEY: &str = "unit";
+
+/// Arrow schema of `df` with the units moved out of the column names into field
+/// metadata, for tools that label axes from Arrow metadata.
+///
+/// A column `"Beamline Energy [eV]"` becomes the field `"Beamline Energy"` with
+/// `unit = "eV"` under `UNIT_METADATA_KEY`, columns without a unit suffix are
+/// left as they are. A suffixed name whose bare name is already a column keeps
+/// its suffix. The fields are in column order, so the arrays of
+/// `DataFrame::iter_chunks` line up with them.
+pub fn arrow_schema_with_units(df: &DataFrame) -> ArrowSchema {
+    let schema = df.schema().to_arrow(CompatLevel::newest());
+    schema
+        .iter_values()
+        .map(|field| match split_unit(&field.name) {
+            (name, Some(unit)) if !schema.contains(name) => {
+                let metadata = [(UNIT_METADATA_KEY.into(), unit.into())]
+                    .into_iter()
+                    .collect();
+                ArrowField::new(name.into(), field.dtype.clone(), field.is_nullable)
+                    .with_metadata(metadata)
+            }
+            _ => field.clone(),
+        })
+        .collect()
+}
+
+/// The unit of column `name` in a schema from `arrow_schema_with_units`, `None`
+/// for a column without one or not in the schema.
+pub fn column_unit<'a>(schema: &'a ArrowSchema, name: &str) -> Option<&'a str> {
+    schema
+        .get(name)?
+        .metadata
+        .as_ref()?
+        .get(UNIT_METADATA_KEY)
+        .map(|unit| unit.as_str())
+}
+
+/// Converts a header card into a single-row column typed after the card value.
+///
+/// Integers become `Int64`, so counters past the `f64` mantissa stay exact,
+/// floats `Float64`, strings `String`, logicals `Boolean` and valueless cards a
+/// null `Float64`. A `quantity` card, one of the physical `HeaderValue`s, is
+/// always `Float64`, since writers store e.g. `0.0` as `0`. Strings lose their
+/// padding as `padding` asks.
+fn card_column(
+    name: PlSmallStr,
+    value: &CardValue,
+    quantity: bool,
+    padding: StringPadding,
+) -> Column {
+    match value {
+        CardValue::FLOAT(v) => Column::new(name, [*v]),
+        CardValue::INT(v) if quantity => Column::new(name, [*v as f64]),
+        CardValue::INT(v) => Column::new(name, [*v]),
+        CardValue::STRING(v) => Column::new(name, [padding.apply(v)]),
+        CardValue::LOGICAL(v) => Column::new(name, [*v]),
+        CardValue::EMPTY => Column::full_null(name, 1, &DataType::Float64),
+    }
+}
+
+/// Splits a delimited card value such as `"10, 20, 30"` into a single-row list
+/// column, `List(Float64)` when every item is numeric and `List(String)` otherwise.
+fn list_card_column(
+    name: PlSmallStr,
+    value: &CardValue,
+    delimiter: char,
+) -> Result<Column, FitsLoaderError> {
+    let text = match value {
+        CardValue::STRING(v) => v.clone(),
+        CardValue::EMPTY => String::new(),
+        other => other.to_string(),
+    };
+    let items: Vec<&str> = text
+        .split(delimiter)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect();
+    let numbers: Option<Vec<f64>> = items.iter().map(|item| item.parse().ok()).collect();
+    let series = match numbers {
+        Some(numbers) => Series::new(name, numbers),
+        None => Series::new(name, items),
+    };
+    Ok(series.implode()?.into_series().into_column())
+}
+
+/// Joins the text of every `COMMENT` and `HISTORY` card, in header order, into
+/// the `Comments` and `History` columns, null when the header has none.
+///
+/// Commentary cards carry their text in columns 9-80. The FITS reader drops a
+/// card repeating an earlier one verbatim, so identical lines appear once.
+pub fn process_commentary(header: &Header) -> Vec<Column> {
+    let entries = |keyword: &str| {
+        let lines: Vec<&str> = header
+            .iter()
+            .filter(|card| {
+                card.keyword
+                    .get(..8)
+                    .is_some_and(|k| k.trim_end() == keyword)
             })
-            .collect()
+            .map(|card| card.keyword.get(8..).unwrap_or(""))
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    };
+    vec![
+        Column::new("Comments".into(), [entries("COMMENT")]),
+        Column::new("History".into(), [entries("HISTORY")]),
+    ]
+}
+
+/// Serializes the keyword cards of `header` into the single-row `Header JSON`
+/// column, an object mapping each keyword to its value, sorted by keyword.
+///
+/// Numbers, strings and logicals keep their JSON type, valueless cards and
+/// non-finite floats are `null`, and string values are trimmed. `COMMENT`,
+/// `HISTORY` and blank cards carry no keyword and are left out, see
+/// `process_commentary`.
+pub fn header_json(header: &Header) -> Column {
+    let mut object = serde_json::Map::new();
+    for card in header.iter() {
+        // Commentary cards hold their text right after the keyword field.
+        let field = card.keyword.get(..8).unwrap_or(&card.keyword).trim_end();
+        if matches!(field, "" | "COMMENT" | "HISTORY" | "END") {
+            continue;
+        }
+        let keyword = card.keyword.trim();
+        let value = match &card.value {
+            CardValue::INT(v) => serde_json::Value::from(*v),
+            CardValue::FLOAT(v) => {
+                serde_json::Number::from_f64(*v).map_or(serde_json::Value::Null, Into::into)
+            }
+            CardValue::STRING(v) => serde_json::Value::from(v.trim()),
+            CardValue::LOGICAL(v) => serde_json::Value::from(*v),
+            CardValue::EMPTY => serde_json::Value::Null,
+        };
+        object.entry(keyword).or_insert(value);
+    }
+    let text = serde_json::Value::Object(object).to_string();
+    Column::new("Header JSON".into(), [text])
+}
+
+/// The card `key` is read from: the first of its candidates and configured
+/// aliases present in the header.
+pub(crate) fn find_card<'a>(
+    header: &'a Header,
+    key: &HeaderValue,
+    options: &LoaderOptions,
+) -> Option<&'a Card> {
+    let aliases = options.card_aliases.get(key.hdu()).into_iter().flatten();
+    key.candidates()
+        .into_iter()
+        .chain(aliases.map(String::as_str))
+        .find_map(|name| header.get_card(name))
+}
+
+pub fn process_metadata(
+    hdu: &PrimaryHDU,
+    keys: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    process_header_metadata(&hdu.header, keys, options)
+}
+
+/// Same as `process_metadata` for the header of any HDU, see
+/// `LoaderOptions::metadata_hdu`.
+pub fn process_header_metadata(
+    header: &Header,
+    keys: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    let unit_overrides = &options.unit_overrides;
+    if keys.is_empty() {
+        return header
+            .iter()
+            .map(|card| header_card_column(card, options))
+            .collect();
+    }
+    let mut columns: Vec<Column> = vec![];
+    for key in keys {
+        if key.is_wildcard() {
+            let explicit =
+                |keyword: &str| keys.iter().any(|k| !k.is_wildcard() && k.hdu() == keyword);
+            for card in wildcard_cards(header, key.hdu()) {
+                if explicit(&card.keyword) {
+                    continue;
+                }
+                let column = header_card_column(card, options)?;
+                if !columns.iter().any(|c| c.name() == column.name()) {
+                    columns.push(column);
+                }
+            }
+            continue;
+        }
+        let name = unit_column_name(key.hdu(), Some(key.unit()), unit_overrides);
+        let card = match find_card(header, key, options) {
+            Some(card) => card,
+            None if key.is_optional() => {
+                columns.push(Column::full_null(name.into(), 1, &DataType::Float64));
+                continue;
+            }
+            // A custom card has no known dtype, the concat takes it from the
+            // files holding the card.
+            None if !options.strict_schema => {
+                let dtype = match key {
+                    HeaderValue::Custom(_) => DataType::Null,
+                    _ => DataType::Float64,
+                };
+                columns.push(Column::full_null(name.into(), 1, &dtype));
+                continue;
+            }
+            None => return Err(FitsLoaderError::MissingHeaderKey(key.hdu().to_string())),
+        };
+        columns.push(match options.list_cards.get(key.hdu()) {
+            Some(&delimiter) => list_card_column(name.into(), &card.value, delimiter)?,
+            None => {
+                let quantity = !matches!(key, HeaderValue::Custom(_));
+                card_column(name.into(), &card.value, quantity, options.string_padding)
+            }
+        });
+        if matches!(key, HeaderValue::BeamlineEnergy) {
+            let source = card.keyword.trim();
+            columns.push(Column::new("Energy Source".into(), [source]));
+        }
     }
+    Ok(columns)
 }
 
-pub fn process_file_name(path: std::path::PathBuf) -> Vec<Column> {
+/// Column of a card loaded under its own keyword, as when every card is loaded.
+fn header_card_column(card: &Card, options: &LoaderOptions) -> Result<Column, FitsLoaderError> {
+    let name = unit_column_name(card.keyword.as_str(), None, &options.unit_overrides);
+    match options.list_cards.get(card.keyword.as_str()) {
+        Some(&delimiter) => list_card_column(name.into(), &card.value, delimiter),
+        None => {
+            let quantity = !matches!(
+                HeaderValue::from_hdu(card.keyword.as_str()),
+                HeaderValue::Custom(_)
+            );
+            Ok(card_column(
+                name.into(),
+                &card.value,
+                quantity,
+                options.string_padding,
+            ))
+        }
+    }
+}
+
+/// The cards of `header` whose keyword matches the glob `pattern`, in card
+/// order, see `HeaderValue::is_wildcard`.
+fn wildcard_cards<'a>(header: &'a Header, pattern: &str) -> impl Iterator<Item = &'a Card> {
+    let patterns = expand_braces(pattern);
+    header
+        .iter()
+        .filter(move |card| patterns.iter().any(|p| glob_match(p, &card.keyword)))
+}
+
+/// Columns derived from the file name `<sample><scan id>-<frame>.fits`, led by
+/// the bare name without extension as `File Stem`, which is emitted even when
+/// the name does not follow the pattern.
+pub fn process_file_name(path: std::path::PathBuf) -> Result<Vec<Column>, FitsLoaderError> {
     let ts = path
-        .metadata()
-        .unwrap()
-        .modified()
-        .unwrap()
+        .metadata()?
+        .modified()?
         .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
+        .unwrap_or_default()
         .as_secs();
 
-    let ts = NaiveDateTime::from_timestamp(ts as i64, 0);
+    let ts = DateTime::from_timestamp(ts as i64, 0)
+        .unwrap_or_default()
+        .naive_utc();
 
-    let file_name = path.file_stem().unwrap().to_str().unwrap_or("");
+    let file_name = path
+        .file_stem()
+        .ok_or_else(|| FitsLoaderError::InvalidFileName(path.display().to_string()))?
+        .to_string_lossy();
 
     let filename_segments = file_name.split('-');
 
     let frame = filename_segments
         .clone()
-        .last()
-        .unwrap()
+        .next_back()
+        .unwrap_or("")
         .parse::<u32>()
         .unwrap_or(0);
 
@@ -239,12 +1453,14 @@ pub fn process_file_name(path: std::path::PathBuf) -> Vec<Column> {
         .collect::<String>();
 
     if remaining.is_empty() {
-        return vec![
-            Column::new("Scan ID".into(), vec![0]),
+        return Ok(vec![
+            // Same dtypes as the parsed case so frames stay stackable.
+            Column::new("File Stem".into(), vec![file_name.to_string()]),
+            Column::new("Scan ID".into(), vec![""]),
             Column::new("Sample Name".into(), vec![""]),
-            Column::new("Frame Number".into(), vec![0]),
+            Column::new("Frame Number".into(), vec![0u32]),
             Column::new("Timestamp".into(), vec![ts]),
-        ];
+        ]);
     }
 
     let scan_id = remaining
@@ -258,16 +1474,330 @@ pub fn process_file_name(path: std::path::PathBuf) -> Vec<Column> {
 
     let sample_name = remaining
         .chars()
-        .take(remaining.len() - 5)
+        .take(remaining.chars().count().saturating_sub(5))
         .collect::<String>()
-        .trim_end_matches(|c| c == '-' || c == '_')
+        .trim_end_matches(['-', '_'])
         .to_string();
 
-    vec![
+    Ok(vec![
+        Column::new("File Stem".into(), vec![file_name.to_string()]),
         Column::new("File Name".into(), vec![remaining]),
         Column::new("Scan ID".into(), vec![scan_id]),
         Column::new("Sample Name".into(), vec![sample_name]),
         Column::new("Frame Number".into(), vec![frame]),
         Column::new("Timestamp".into(), vec![ts]),
-    ]
+    ])
+}
+
+// ================== CSV Export ==================
+
+/// Whether a column of `dtype` nests values, like `Raw`, a list card or an
+/// `Image Handle`, and so has no CSV representation.
+fn is_nested(dtype: &DataType) -> bool {
+    matches!(
+        dtype,
+        DataType::List(_) | DataType::Array(_, _) | DataType::Struct(_)
+    )
+}
+
+/// Writes `df` to `path` as CSV with a header row.
+///
+/// CSV holds only scalars, so a frame with image, list or struct columns fails
+/// with `NestedColumns` naming them, before the file is created. Use
+/// `write_csv_no_image` to leave them out.
+pub fn write_csv(df: &DataFrame, path: impl AsRef<Path>) -> Result<(), FitsLoaderError> {
+    let nested: Vec<String> = df
+        .get_columns()
+        .iter()
+        .filter(|column| is_nested(column.dtype()))
+        .map(|column| column.name().to_string())
+        .collect();
+    if !nested.is_empty() {
+        return Err(FitsLoaderError::NestedColumns(nested));
+    }
+    let mut file = fs::File::create(path)?;
+    CsvWriter::new(&mut file).finish(&mut df.clone())?;
+    Ok(())
+}
+
+/// Writes the scalar columns of `df` to `path` as CSV, e.g. a reduced scan for
+/// a spreadsheet. Image, list and struct columns such as `Raw`, `Q Map` or
+/// `Image Handle` are dropped; every other column is written as is.
+pub fn write_csv_no_image(df: &DataFrame, path: impl AsRef<Path>) -> Result<(), FitsLoaderError> {
+    let scalar: Vec<Column> = df
+        .get_columns()
+        .iter()
+        .filter(|column| !is_nested(column.dtype()))
+        .cloned()
+        .collect();
+    write_csv(&DataFrame::new(scalar)?, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::fits::CcdFits;
+    use crate::loader::read_fits_with_options;
+    use crate::options::{ImageHduSelection, RowReadout};
+    use crate::test_util::{be_bytes, test_dir, u16_image, write_fits, Extension};
+
+    fn raw(path: &Path, options: &LoaderOptions) -> Array2<f64> {
+        let df = read_fits_with_options(path.to_path_buf(), &[], options).unwrap();
+        image_from_column(df.column("Raw").unwrap(), 0).unwrap()
+    }
+
+    #[test]
+    fn raw_is_rows_by_cols() {
+        // Two rows of three columns: NAXIS1 = 3, NAXIS2 = 2.
+        let path = test_dir("axis_order").join("frame.fits");
+        write_fits(&path, &[], Some(u16_image(2, 3, &[1, 2, 3, 4, 5, 6])));
+        let mut options = LoaderOptions::default();
+        assert_eq!(
+            raw(&path, &options),
+            array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]
+        );
+        options.image.transpose = true;
+        assert_eq!(
+            raw(&path, &options),
+            array![[1.0, 4.0], [2.0, 5.0], [3.0, 6.0]]
+        );
+    }
+
+    #[test]
+    fn cube_slices_are_rows_by_cols() {
+        let path = test_dir("cube_axis_order").join("cube.fits");
+        let mut image = u16_image(2, 3, &(1..=12).collect::<Vec<_>>());
+        image.axes = vec![3, 2, 2];
+        write_fits(&path, &[], Some(image));
+        let df = read_fits_with_options(path, &[], &LoaderOptions::default()).unwrap();
+        let raw = df.column("Raw").unwrap();
+        assert_eq!(
+            image_from_column(raw, 1).unwrap(),
+            array![[7.0, 8.0, 9.0], [10.0, 11.0, 12.0]]
+        );
+    }
+
+    /// Pixels of a 2 x 2 image of `bitpix` holding `data`, read back by the loader.
+    fn decoded(
+        name: &str,
+        bitpix: i32,
+        cards: Vec<(&'static str, &'static str)>,
+        data: Vec<u8>,
+    ) -> Array2<f64> {
+        let path = test_dir(name).join("frame.fits");
+        let axes = vec![2, 2];
+        write_fits(
+            &path,
+            &[],
+            Some(Extension {
+                bitpix,
+                axes,
+                cards,
+                data,
+            }),
+        );
+        CcdFits::new(&path).unwrap().get_data().unwrap()
+    }
+
+    #[test]
+    fn big_endian_pixels_for_every_bitpix() {
+        let bzero = vec![("BZERO", "0")];
+        let u8_pixels = decoded("be_u8", 8, bzero.clone(), vec![1, 2, 200, 255]);
+        assert_eq!(u8_pixels, array![[1.0, 2.0], [200.0, 255.0]]);
+
+        let i16_values = [0x0102, -2, i16::MAX, i16::MIN];
+        let i16_pixels = decoded(
+            "be_i16",
+            16,
+            bzero.clone(),
+            be_bytes(&i16_values, i16::to_be_bytes),
+        );
+        assert_eq!(i16_pixels, array![[258.0, -2.0], [32767.0, -32768.0]]);
+
+        let i32_values = [0x01020304, -100_000, i32::MAX, i32::MIN];
+        let i32_pixels = decoded("be_i32", 32, bzero, be_bytes(&i32_values, i32::to_be_bytes));
+        assert_eq!(
+            i32_pixels,
+            array![[16909060.0, -100000.0], [i32::MAX as f64, i32::MIN as f64]]
+        );
+
+        let f32_values = [1.5e6f32, -2.25, 0.1, f32::MAX];
+        let f32_pixels = decoded(
+            "be_f32",
+            -32,
+            vec![],
+            be_bytes(&f32_values, f32::to_be_bytes),
+        );
+        assert_eq!(
+            f32_pixels,
+            array![[1.5e6, -2.25], [0.1f32 as f64, f32::MAX as f64]]
+        );
+
+        let f64_values = [1e300, -2.5e-300, 0.1, -0.0];
+        let f64_pixels = decoded(
+            "be_f64",
+            -64,
+            vec![],
+            be_bytes(&f64_values, f64::to_be_bytes),
+        );
+        assert_eq!(f64_pixels, array![[1e300, -2.5e-300], [0.1, -0.0]]);
+    }
+
+    #[test]
+    fn bzero_offsets_detector_pixels() {
+        let path = test_dir("be_bzero").join("frame.fits");
+        write_fits(&path, &[], Some(u16_image(1, 3, &[0, 32768, 65535])));
+        let pixels = CcdFits::new(&path).unwrap().get_data().unwrap();
+        assert_eq!(pixels, array![[0.0, 32768.0, 65535.0]]);
+    }
+
+    #[test]
+    fn zero_length_image_is_an_error_naming_the_file() {
+        let path = test_dir("zero_length_image").join("empty.fits");
+        let image = Extension {
+            bitpix: 16,
+            axes: vec![0, 4],
+            cards: vec![("BZERO", "32768")],
+            data: vec![],
+        };
+        write_fits(&path, &[], Some(image));
+        let options = LoaderOptions {
+            image_hdu: ImageHduSelection::Index(1),
+            ..LoaderOptions::default()
+        };
+        match read_fits_with_options(path.clone(), &[], &options) {
+            Err(FitsLoaderError::EmptyImage(p)) => assert_eq!(p, path.display().to_string()),
+            other => panic!("expected EmptyImage, got {:?}", other.map(|df| df.shape())),
+        }
+    }
+
+    #[test]
+    fn non_square_frames_keep_their_shape_and_dtype() {
+        let (rows, cols) = (1024, 2048);
+        let mut image = Array2::from_shape_fn((rows, cols), |(r, c)| (r * cols + c) as f32);
+        image[[rows - 1, 0]] = f32::NAN;
+        let expected = image.mapv(f64::from);
+        let same = |a: &Array2<f64>| {
+            a.dim() == expected.dim()
+                && a.iter()
+                    .zip(&expected)
+                    .all(|(x, y)| x == y || (x.is_nan() && y.is_nan()))
+        };
+
+        let nested = col_from_array2::<Float32Type>("Raw".into(), image.view()).unwrap();
+        let inner = DataType::Array(Box::new(DataType::Float32), cols);
+        assert_eq!(nested.dtype(), &DataType::Array(Box::new(inner), rows));
+        assert!(same(&image_from_column(&nested, 0).unwrap()));
+
+        let owned = col_from_array::<Float32Type>("Raw".into(), image.clone().into_dyn()).unwrap();
+        assert!(same(&image_from_column(&owned, 0).unwrap()));
+
+        let flat = col_from_array_flat::<Float32Type>("Raw".into(), image.view()).unwrap();
+        assert_eq!(
+            flat.dtype(),
+            &DataType::Array(Box::new(DataType::Float32), rows * cols)
+        );
+        let flat = image_from_column(&flat, 0).unwrap();
+        assert_eq!(flat.dim(), (1, rows * cols));
+        assert_eq!(flat[[0, cols + 1]], expected[[1, 1]]);
+    }
+
+    #[test]
+    fn non_square_file_loads_as_rows_by_cols() {
+        let (rows, cols) = (1024, 2048);
+        let values: Vec<u16> = (0..rows * cols).map(|i| (i % 65536) as u16).collect();
+        let path = test_dir("non_square_file").join("frame.fits");
+        write_fits(&path, &[], Some(u16_image(rows, cols, &values)));
+        let raw = raw(&path, &LoaderOptions::default());
+        assert_eq!(raw.dim(), (rows, cols));
+        assert_eq!(raw[[1, 0]], cols as f64);
+        assert_eq!(
+            raw[[rows - 1, cols - 1]],
+            ((rows * cols - 1) % 65536) as f64
+        );
+    }
+
+    #[test]
+    fn interlaced_frames_are_reassembled() {
+        // Five rows whose value is ten times the frame row, stored with the top
+        // half (rows 0 to 2) interlaced with the bottom half (rows 3 and 4).
+        let path = test_dir("interlaced_frame").join("frame.fits");
+        let stored = [0, 1, 30, 31, 10, 11, 40, 41, 20, 21];
+        write_fits(&path, &[], Some(u16_image(5, 2, &stored)));
+        let frame = array![
+            [0.0, 1.0],
+            [10.0, 11.0],
+            [20.0, 21.0],
+            [30.0, 31.0],
+            [40.0, 41.0]
+        ];
+        let mut options = LoaderOptions::default();
+        options.image.deinterlace = Some(RowReadout::Interlaced);
+        assert_eq!(raw(&path, &options), frame);
+
+        // The bottom half read from the bottom edge up.
+        let stored = [0, 1, 40, 41, 10, 11, 30, 31, 20, 21];
+        write_fits(&path, &[], Some(u16_image(5, 2, &stored)));
+        options.image.deinterlace = Some(RowReadout::InterlacedMirrored);
+        assert_eq!(raw(&path, &options), frame);
+        options.image.deinterlace = Some(RowReadout::Order(vec![0, 4, 1, 3, 2]));
+        assert_eq!(raw(&path, &options), frame);
+    }
+
+    #[test]
+    fn unsigned_pixels_saturate_and_count_the_clamped() {
+        let dir = test_dir("u16_saturating");
+        let path = dir.join("film_00001.fits");
+        let values = [-5.0f32, 70000.0, f32::NAN, 12.5];
+        let image = Extension {
+            bitpix: -32,
+            axes: vec![2, 2],
+            cards: vec![],
+            data: be_bytes(&values, f32::to_be_bytes),
+        };
+        write_fits(&path, &[], Some(image));
+        let fits = CcdFits::new(&path).unwrap();
+        let hdu = fits.image_hdu(&ImageHduSelection::default()).unwrap();
+        let (pixels, clamped) = pixels_u16_saturating(hdu).unwrap();
+        assert_eq!(pixels, array![[0, u16::MAX], [0, 12]]);
+        assert_eq!(clamped, 3);
+    }
+
+    #[test]
+    fn raw_keeps_the_dtype_of_the_stored_pixels() {
+        let dir = test_dir("raw_dtype");
+        let path = dir.join("film_00001.fits");
+        let raw_dtype = |bitpix, cards, data| {
+            let image = Extension {
+                bitpix,
+                axes: vec![2, 2],
+                cards,
+                data,
+            };
+            write_fits(&path, &[], Some(image));
+            let df = read_fits_with_options(path.clone(), &[], &LoaderOptions::default()).unwrap();
+            let raw = df.column("Raw").unwrap();
+            (raw.dtype().clone(), image_from_column(raw, 0).unwrap())
+        };
+        let nested = |dtype| DataType::Array(Box::new(DataType::Array(Box::new(dtype), 2)), 2);
+
+        let floats = [0.25f32, 70000.5, -1.5, 3.75];
+        let (dtype, pixels) = raw_dtype(-32, vec![], be_bytes(&floats, f32::to_be_bytes));
+        assert_eq!(dtype, nested(DataType::Float32));
+        assert_eq!(pixels, array![[0.25, 70000.5], [-1.5, 3.75]]);
+
+        let ints = [70000i32, -5, 100000, 1];
+        let (dtype, pixels) = raw_dtype(32, vec![], be_bytes(&ints, i32::to_be_bytes));
+        assert_eq!(dtype, nested(DataType::Int32));
+        assert_eq!(pixels, array![[70000.0, -5.0], [100000.0, 1.0]]);
+
+        // Unsigned 32-bit pixels, stored offset by `BZERO = 2^31`.
+        let unsigned = [70000i64, 3_000_000_000, 0, 5].map(|v| (v - (1 << 31)) as i32);
+        let cards = vec![("BZERO", "2147483648")];
+        let (dtype, pixels) = raw_dtype(32, cards, be_bytes(&unsigned, i32::to_be_bytes));
+        assert_eq!(dtype, nested(DataType::Int64));
+        assert_eq!(pixels, array![[70000.0, 3e9], [0.0, 5.0]]);
+    }
 }
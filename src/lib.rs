@@ -1,7 +1,19 @@
+pub mod cache;
+pub mod checksum;
+pub mod config;
+pub mod diff;
 pub mod enums;
 pub mod errors;
+pub mod fits;
+pub mod image;
 pub mod io;
 pub mod loader;
+#[cfg(feature = "nexus")]
+pub mod nexus;
+pub mod options;
+pub mod reduce;
+#[cfg(test)]
+mod test_util;
 pub mod documentation {
     // No code changes needed here
 }
@@ -1,79 +1,2417 @@
-use astrors_fork::fits;
-use astrors_fork::io::hdulist::HDU;
-
+use astrors_fork::io::hdus::image::{imagehdu::ImageHDU, ImageData};
+use astrors_fork::io::header::card::{Card, CardValue};
+use astrors_fork::io::Header;
+use ndarray::Array2;
+use notify::Watcher;
+use polars::export::chrono::NaiveDateTime;
 use polars::{lazy::prelude::*, prelude::*}; // Add the import statement for PolarsError
+use polars_arrow::array::Utf8ViewArray;
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
+use std::hash::{DefaultHasher, Hasher};
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::enums::HeaderValue;
+use crate::cache::{CacheEntry, MetadataCache};
+use crate::checksum::{data_units, verify_checksums};
+use crate::config::InstrumentConfig;
+use crate::enums::{DetectorPreset, ExperimentType, HeaderValue};
 use crate::errors::FitsLoaderError;
-use crate::io::{add_calculated_domains, process_file_name, process_image, process_metadata};
+use crate::fits::{read_header, CcdFits};
+use crate::image::{deinterlace_rows, split_overscan};
+use crate::io::{
+    astrors_layout, calculated_domains_with_unit, card_f64, cube_depth, find_card, frame_order,
+    frame_pixels, header_json, process_commentary, process_file_name, process_frame,
+    process_frame_q_map, process_header_metadata, unit_column_name, INTEGRATED_COLUMNS,
+};
+use crate::options::{
+    BeamReference, ExtensionOp, FilterCorrection, ImageHduSelection, ImageOptions, LoaderOptions,
+    NormalizationOutput, Parallelism, TableHduSelection, WatchOptions,
+};
 // Enum representing different types of experiments.
 
 // Polars Helper Function
 
+/// Expands shell-style brace groups (`{a,b}`) into every concrete pattern.
+///
+/// Groups may be nested, e.g. `{Y6,PM6}_{refl,bg}_*.fits` expands into four
+/// patterns. Unbalanced braces are left untouched.
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
+    let bytes = pattern.as_bytes();
+    let open = match pattern.find('{') {
+        Some(open) => open,
+        None => return vec![pattern.to_string()],
+    };
+
+    let mut depth = 0;
+    let mut close = None;
+    let mut splits = vec![];
+    for (i, &b) in bytes.iter().enumerate().skip(open) {
+        match b {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            b',' if depth == 1 => splits.push(i),
+            _ => {}
+        }
+    }
+    let close = match close {
+        Some(close) => close,
+        None => return vec![pattern.to_string()],
+    };
+
+    let prefix = &pattern[..open];
+    let suffix = &pattern[close + 1..];
+    let mut start = open + 1;
+    let mut alternatives = vec![];
+    for split in splits.into_iter().chain(std::iter::once(close)) {
+        alternatives.push(&pattern[start..split]);
+        start = split + 1;
+    }
+
+    alternatives
+        .into_iter()
+        .flat_map(|alt| expand_braces(&format!("{}{}{}", prefix, alt, suffix)))
+        .collect()
+}
+
+/// Matches `text` against a glob pattern supporting `*`, `?` and `[...]` classes.
+///
+/// Brace groups are not handled here, run the pattern through `expand_braces` first.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                '*' => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                '?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                '[' => {
+                    if let Some((matched, next)) = match_class(&pattern, p, text[t]) {
+                        if matched {
+                            p = next;
+                            t += 1;
+                            continue;
+                        }
+                    } else if text[t] == '[' {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c if c == text[t] => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match backtrack {
+            Some((star, matched)) => {
+                p = star + 1;
+                t = matched + 1;
+                backtrack = Some((star, matched + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches a single character against the `[...]` class starting at `start`.
+///
+/// Returns whether the class matched and the index just past its closing bracket,
+/// or `None` when the class is never closed.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() && (first || pattern[i] != ']') {
+        first = false;
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            if pattern[i] <= c && c <= pattern[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if pattern[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+    if i >= pattern.len() {
+        return None;
+    }
+    Some((matched != negate, i + 1))
+}
+
+/// Checks for a `.fits` extension, ignoring case so `.FITS` files are accepted.
+pub(crate) fn is_fits_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("fits"))
+}
+
+/// Lossy string form of a path for columns and messages, without the `\\?\`
+/// verbatim prefix that `fs::canonicalize` adds on Windows.
+pub(crate) fn display_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", unc)
+    } else if let Some(local) = path.strip_prefix(r"\\?\") {
+        local.to_string()
+    } else {
+        path.into_owned()
+    }
+}
+
 // workhorse functions for loading and processing CCD data.
 pub fn read_fits(
     file_path: std::path::PathBuf,
-    header_items: &Vec<HeaderValue>,
+    header_items: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    read_fits_with_options(file_path, header_items, &LoaderOptions::default())
+}
+
+/// Same as `read_fits`, with explicit `LoaderOptions`.
+pub fn read_fits_with_options(
+    file_path: std::path::PathBuf,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
 ) -> Result<DataFrame, FitsLoaderError> {
-    if file_path.extension().and_then(|ext| ext.to_str()) != Some("fits") {
+    let options = with_reference_frames(options)?;
+    Ok(read_fits_grouped(file_path, header_items, &options, None)?.0)
+}
+
+/// Wall-clock time spent on a single file, in milliseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileTiming {
+    pub path: PathBuf,
+    /// Opening the file and parsing its HDUs, including checksum verification
+    /// when enabled.
+    pub read_ms: f64,
+    /// Decoding the image and computing the image columns and statistics.
+    pub image_ms: f64,
+    /// The whole file, from opening it to its packaged DataFrame.
+    pub total_ms: f64,
+}
+
+/// Names of the columns a file contributes, by where they go in the output,
+/// and the distinct `SCANAXIS` cards seen.
+#[derive(Default)]
+struct ColumnGroups {
+    metadata: Vec<PlSmallStr>,
+    image: Vec<PlSmallStr>,
+    file: Vec<PlSmallStr>,
+    scan_axes: Vec<String>,
+}
+
+impl ColumnGroups {
+    /// Adds the names of `other` not seen yet, keeping first-seen order.
+    fn merge(&mut self, other: ColumnGroups) {
+        let groups = [
+            (&mut self.metadata, other.metadata),
+            (&mut self.image, other.image),
+            (&mut self.file, other.file),
+        ];
+        for (names, new) in groups {
+            for name in new {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        for axis in other.scan_axes {
+            if !self.scan_axes.contains(&axis) {
+                self.scan_axes.push(axis);
+            }
+        }
+    }
+
+    /// Column to sort the rows by: the scanned motor when every file names the
+    /// same one in `SCANAXIS` and it was loaded, under its keyword or with a unit.
+    fn sort_column(&self, schema: &Schema) -> Option<PlSmallStr> {
+        let [axis] = self.scan_axes.as_slice() else {
+            return None;
+        };
+        schema
+            .iter_names()
+            .find(|name| {
+                name.as_str() == axis
+                    || name
+                        .strip_prefix(axis.as_str())
+                        .is_some_and(|unit| unit.starts_with(" ["))
+            })
+            .cloned()
+    }
+
+    /// Output order: metadata, then the columns added after loading (calculated
+    /// domains and user expressions) in schema order, then image and file columns.
+    fn order(&self, schema: &Schema) -> Vec<Expr> {
+        let grouped = |name: &PlSmallStr| {
+            self.metadata.contains(name) || self.image.contains(name) || self.file.contains(name)
+        };
+        let added = schema.iter_names().filter(|name| !grouped(name)).cloned();
+        self.metadata
+            .iter()
+            .cloned()
+            .chain(added)
+            .chain(self.image.iter().cloned())
+            .chain(self.file.iter().cloned())
+            .filter(|name| schema.contains(name))
+            .map(col)
+            .collect()
+    }
+}
+
+/// The motor scanned in this file as recorded by its `SCANAXIS` card.
+fn scan_axis(header: &Header) -> Option<String> {
+    let axis = header.get_card("SCANAXIS")?.value.to_string();
+    let axis = axis.trim().trim_matches('\'').trim();
+    (!axis.is_empty()).then(|| axis.to_string())
+}
+
+/// Cards holding the sequential index of a scan point, in the order they are tried.
+const POINT_INDEX_CARDS: [&str; 3] = ["Point Index", "SCANPT", "POINTNUM"];
+
+/// The index of the scan point in this file, the first of `POINT_INDEX_CARDS`
+/// holding a non-negative integer.
+fn point_index(header: &Header) -> Option<u32> {
+    POINT_INDEX_CARDS
+        .iter()
+        .find_map(|keyword| match &header.get_card(keyword)?.value {
+            CardValue::INT(v) => u32::try_from(*v).ok(),
+            CardValue::FLOAT(v) if v.fract() == 0.0 && *v >= 0.0 => Some(*v as u32),
+            CardValue::STRING(v) => v.trim().parse().ok(),
+            _ => None,
+        })
+}
+
+/// `DATE-OBS` of `header` in microseconds since the epoch, `None` when the card
+/// is missing or not an ISO 8601 date and time.
+fn observation_time(header: &Header) -> Option<i64> {
+    let date = header.get_card("DATE-OBS")?.value.to_string();
+    let date = date.trim().trim_matches('\'').trim().trim_end_matches('Z');
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .into_iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .map(|time| time.and_utc().timestamp_micros())
+}
+
+/// `Observation Time` of `header` as a `Datetime` column, see
+/// `LoaderOptions::frame_interval`.
+fn observation_time_column(header: &Header) -> Result<Column, FitsLoaderError> {
+    let time = Column::new("Observation Time".into(), [observation_time(header)]);
+    Ok(time.cast(&DataType::Datetime(TimeUnit::Microseconds, None))?)
+}
+
+/// Columns describing the file itself: `Source Path`, `Source Dir`, `Experiment
+/// Type` and the file name fields, as far as enabled.
+fn file_columns(
+    file_path: &Path,
+    options: &LoaderOptions,
+    source_dir: Option<String>,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    let mut columns = vec![];
+    if options.source_path {
+        let source = fs::canonicalize(file_path)?;
+        columns.push(Column::new("Source Path".into(), [display_path(&source)]));
+    }
+    if let Some(dir) = source_dir {
+        columns.push(Column::new("Source Dir".into(), [dir]));
+    }
+    if let Some(experiment) = options.experiment_type {
+        columns.push(experiment_type_column(experiment)?);
+    }
+    columns.extend(process_file_name(file_path.to_path_buf())?);
+    Ok(columns)
+}
+
+/// Frame without rows of a load matching no file, see `LoaderOptions::allow_empty`.
+fn empty_frame(
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    let mut groups = ColumnGroups::default();
+    let mut columns = vec![];
+    for key in header_items.iter().filter(|key| !key.is_wildcard()) {
+        let name = unit_column_name(key.hdu(), Some(key.unit()), &options.unit_overrides);
+        let dtype = match (options.list_cards.contains_key(key.hdu()), key) {
+            (true, _) => DataType::List(Box::new(DataType::Float64)),
+            // Custom cards are mostly strings such as the proposal number.
+            (false, HeaderValue::Custom(_)) => DataType::String,
+            (false, _) => DataType::Float64,
+        };
+        groups.metadata.push(name.as_str().into());
+        columns.push(Column::full_null(name.into(), 0, &dtype));
+        if matches!(key, HeaderValue::BeamlineEnergy) {
+            groups.metadata.push("Energy Source".into());
+            columns.push(Column::new("Energy Source".into(), Vec::<String>::new()));
+        }
+    }
+    let mut file = vec![];
+    if options.source_path {
+        file.push(Column::full_null(
+            "Source Path".into(),
+            0,
+            &DataType::String,
+        ));
+    }
+    if let Some(experiment) = options.experiment_type {
+        file.push(experiment_type_column(experiment)?.clear());
+    }
+    // Same dtypes as `process_file_name` gives for a name that parses.
+    for name in ["File Stem", "File Name", "Scan ID", "Sample Name"] {
+        file.push(Column::new(name.into(), Vec::<String>::new()));
+    }
+    file.push(Column::new("Frame Number".into(), Vec::<u32>::new()));
+    file.push(Column::new("Timestamp".into(), Vec::<NaiveDateTime>::new()));
+    groups.file = file.iter().map(|column| column.name().clone()).collect();
+    columns.extend(file);
+    finish_frame(DataFrame::new(columns)?.lazy(), &groups, options)
+}
+
+fn read_fits_grouped(
+    file_path: std::path::PathBuf,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+    source_dir: Option<String>,
+) -> Result<(DataFrame, ColumnGroups, FileTiming), FitsLoaderError> {
+    let start = Instant::now();
+    let ms = |since: Instant| since.elapsed().as_secs_f64() * 1e3;
+    if !is_fits_file(&file_path) {
         return Err(FitsLoaderError::NoData);
     }
+    if options.verify_checksums {
+        verify_checksums(&file_path)?;
+    }
+    let fits = CcdFits::new(&file_path)?;
+    let read_ms = ms(start);
 
-    let hdul = fits::fromfile(
-        file_path
-            .to_str()
-            .ok_or_else(|| FitsLoaderError::InvalidFileName("Invalid path".into()))?,
-    )?;
+    let primary = fits.primary()?;
+    let header = fits.header(options.metadata_hdu)?;
+    let mut meta = process_header_metadata(header, header_items, options)?;
+    if options.commentary {
+        meta.extend(process_commentary(header));
+    }
+    if options.header_json {
+        meta.push(header_json(header));
+    }
+    let scan_axis = scan_axis(header);
+    meta.extend(point_index(header).map(|index| Column::new("Point Index".into(), [index])));
+    meta.extend(
+        scan_axis
+            .iter()
+            .map(|axis| Column::new("Scan Axis".into(), [axis.as_str()])),
+    );
+    if options.frame_interval {
+        meta.push(observation_time_column(header)?);
+    }
+
+    let header_only = options.header_only_files
+        && options.combine_extensions.is_empty()
+        && fits
+            .image_hdu_index(&ImageHduSelection::LargestImage)
+            .is_err();
+    if header_only {
+        let file_columns = file_columns(&file_path, options, source_dir)?;
+        let raw = (!options.image.drop_image && !options.image.image_handle)
+            .then(|| Column::full_null("Raw".into(), 1, &DataType::Null));
+        let names = |columns: &[Column]| columns.iter().map(|c| c.name().clone()).collect();
+        let groups = ColumnGroups {
+            metadata: names(&meta),
+            image: raw.iter().map(|raw| raw.name().clone()).collect(),
+            file: names(&file_columns),
+            scan_axes: scan_axis.into_iter().collect(),
+        };
+        let columns = meta.into_iter().chain(raw).chain(file_columns).collect();
+        let timing = FileTiming {
+            path: file_path,
+            read_ms,
+            image_ms: 0.0,
+            total_ms: ms(start),
+        };
+        return Ok((DataFrame::new(columns)?, groups, timing));
+    }
+
+    let combined = combined_extensions(&fits, &options.combine_extensions, &file_path)?;
+    let (image_index, image) = match &combined {
+        Some((index, image)) => (*index, image),
+        None => (
+            fits.image_hdu_index(&options.image_hdu)?,
+            fits.image_hdu(&options.image_hdu)?,
+        ),
+    };
+    let frames = selected_frames(image, options, &file_path)?;
+    let slice_table = match (&options.slice_table, cube_depth(image)) {
+        (Some(selection), Some(depth)) => Some(slice_table(
+            &fits, selection, depth, &meta, options, &file_path,
+        )?),
+        _ => None,
+    };
+
+    let file_columns = file_columns(&file_path, options, source_dir)?;
+
+    let names = |columns: &[Column]| columns.iter().map(|c| c.name().clone()).collect();
+    let mut metadata: Vec<PlSmallStr> = names(&meta);
+    for column in slice_table.iter().flat_map(|table| table.get_columns()) {
+        if !metadata.contains(column.name()) {
+            metadata.push(column.name().clone());
+        }
+    }
+    let mut groups = ColumnGroups {
+        metadata,
+        image: vec![],
+        file: names(&file_columns),
+        scan_axes: scan_axis.into_iter().collect(),
+    };
+    // Flag column, whether the image is divided as well and the monitor reading
+    // of the file when normalization is on, `None` for a missing, zero or
+    // negative card.
+    let i0 = HeaderValue::I0Monitor;
+    let monitor = match (&options.normalize_beam_current, &options.normalize_i0) {
+        (Some(_), Some(_)) => {
+            return Err(FitsLoaderError::InvalidConfig(
+                "normalize_beam_current and normalize_i0 are exclusive".into(),
+            ))
+        }
+        (Some(normalization), None) => {
+            let names = HeaderValue::BeamCurrent.candidates();
+            Some((
+                "Current Normalized",
+                normalization.image,
+                monitor_reading(header, &names),
+            ))
+        }
+        (None, Some(normalization)) => {
+            let names: Vec<&str> = match &normalization.card {
+                Some(card) => vec![card.as_str()],
+                None => {
+                    let aliases = options.card_aliases.get(i0.hdu()).into_iter().flatten();
+                    i0.candidates()
+                        .into_iter()
+                        .chain(aliases.map(String::as_str))
+                        .collect()
+                }
+            };
+            Some((
+                "I0 Normalized",
+                normalization.image,
+                monitor_reading(header, &names),
+            ))
+        }
+        (None, None) => None,
+    };
+    let transmission = options
+        .filter_correction
+        .as_ref()
+        .map(|correction| filter_transmission(header, correction));
+    // The statistics are computed on the unnormalized frame and divided below,
+    // only the stored pixels are scaled. Skipped frames are scaled as well, so
+    // `Raw` has the same dtype in every file.
+    let image_options = match monitor {
+        Some((_, true, reading)) => ImageOptions {
+            pixel_scale: Some(options.image.pixel_scale.unwrap_or(1.0) / reading.unwrap_or(1.0)),
+            ..options.image.clone()
+        },
+        _ => options.image.clone(),
+    };
+    let image_options = ImageOptions {
+        drop_image: image_options.drop_image || image_options.image_handle,
+        ..image_options
+    };
+
+    let mut df: Option<DataFrame> = None;
+    let mut image_ms = 0.0;
+    for frame in frames {
+        let mut columns = meta.clone();
+        if let Some((table, frame)) = slice_table.as_ref().zip(frame) {
+            for column in table.get_columns() {
+                let value = column.slice(frame as i64, 1);
+                match columns.iter_mut().find(|c| c.name() == column.name()) {
+                    Some(existing) => *existing = value,
+                    None => columns.push(value),
+                }
+            }
+        }
+        let meta_len = columns.len();
+        let image_start = Instant::now();
+        let mut image_columns =
+            process_frame(image, frame, &image_options).map_err(|e| e.with_path(&file_path))?;
+        if options.image.image_handle {
+            image_columns.insert(0, image_handle(&file_path, image, image_index, frame)?);
+        }
+        if options.image.hdu_info {
+            image_columns.push(Column::new("Image HDU".into(), [image_index as u32]));
+        }
+        if let Some((flag, _, reading)) = monitor {
+            if let Some(reading) = reading {
+                for column in image_columns.iter_mut() {
+                    if INTEGRATED_COLUMNS.contains(&column.name().as_str()) {
+                        *column = &*column / reading;
+                    }
+                }
+            }
+            image_columns.push(Column::new(flag.into(), [reading.is_some()]));
+        }
+        if let Some(transmission) = transmission {
+            if let Some(transmission) = transmission {
+                for column in image_columns.iter_mut() {
+                    if INTEGRATED_COLUMNS.contains(&column.name().as_str()) {
+                        *column = &*column / transmission;
+                    }
+                }
+            }
+            image_columns.push(Column::new("Filter Transmission".into(), [transmission]));
+        }
+        columns.extend(image_columns);
+        if let Some(frame) = frame {
+            columns.push(Column::new("Frame".into(), [frame as u32]));
+        }
+        if let Some(q_map) = &options.q_map {
+            columns.extend(process_frame_q_map(primary, image, frame, q_map)?);
+        }
+        image_ms += ms(image_start);
+        if groups.image.is_empty() {
+            groups.image = names(&columns[meta_len..]);
+        }
+        columns.extend(file_columns.iter().cloned());
+        let frame_df = DataFrame::new(columns)?;
+        match df.as_mut() {
+            Some(df) => {
+                df.vstack_mut(&frame_df)?;
+            }
+            None => df = Some(frame_df),
+        }
+    }
+    let df = df.ok_or(FitsLoaderError::NoData)?;
+    let timing = FileTiming {
+        path: file_path,
+        read_ms,
+        image_ms,
+        total_ms: ms(start),
+    };
+    Ok((df, groups, timing))
+}
+
+/// The per-slice values of `LoaderOptions::slice_table`, one row per slice of
+/// a cube `depth` deep, with the fields renamed to the columns they fill.
+///
+/// A field of a header column in `meta` is cast to its dtype, so rows from
+/// files with and without a table stack.
+fn slice_table(
+    fits: &CcdFits,
+    selection: &TableHduSelection,
+    depth: usize,
+    meta: &[Column],
+    options: &LoaderOptions,
+    path: &Path,
+) -> Result<DataFrame, FitsLoaderError> {
+    let table = fits.get_table(selection.clone())?;
+    if table.height() != depth {
+        return Err(FitsLoaderError::SliceTableMismatch {
+            path: display_path(path),
+            rows: table.height(),
+            depth,
+        });
+    }
+    let mut columns = vec![];
+    for column in table.get_columns() {
+        let field = column.name().as_str();
+        let name = match HeaderValue::from_hdu(field) {
+            HeaderValue::Custom(_) => unit_column_name(field, None, &options.unit_overrides),
+            key => unit_column_name(key.hdu(), Some(key.unit()), &options.unit_overrides),
+        };
+        let column = column.clone().with_name(name.into());
+        columns.push(match meta.iter().find(|c| c.name() == column.name()) {
+            Some(header) => column.cast(header.dtype())?,
+            None => column,
+        });
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// The frame of `LoaderOptions::combine_extensions` as a `Float64` image HDU,
+/// with the header of the first extension and its index, `None` without any
+/// extensions to combine.
+fn combined_extensions(
+    fits: &CcdFits,
+    extensions: &[(String, ExtensionOp)],
+    path: &Path,
+) -> Result<Option<(usize, ImageHDU)>, FitsLoaderError> {
+    let Some(((first, _), rest)) = extensions.split_first() else {
+        return Ok(None);
+    };
+    let extension = |name: &str| {
+        let selection = ImageHduSelection::ExtName(name.to_string());
+        let missing = |_| {
+            FitsLoaderError::FitsError(format!("No image extension {} in {}", name, path.display()))
+        };
+        let index = fits.image_hdu_index(&selection).map_err(missing)?;
+        let image = fits.image_hdu(&selection)?;
+        let pixels = frame_pixels(image, None).map_err(|e| e.with_path(path))?;
+        Ok::<_, FitsLoaderError>((index, image, pixels))
+    };
+    let (index, image, mut frame) = extension(first)?;
+    let mut means = 1.0;
+    for (name, op) in rest {
+        let (_, _, pixels) = extension(name)?;
+        if pixels.dim() != frame.dim() {
+            return Err(FitsLoaderError::ImageShapeMismatch {
+                path: path.display().to_string(),
+                expected: frame.dim(),
+                found: pixels.dim(),
+            });
+        }
+        match op {
+            ExtensionOp::Sum => frame += &pixels,
+            ExtensionOp::Diff => frame -= &pixels,
+            ExtensionOp::Mean => {
+                frame = (frame * means + pixels) / (means + 1.0);
+                means += 1.0;
+            }
+        }
+    }
+    // The offset is already applied, and the pixels are no longer integers.
+    let mut header = Header::new();
+    for card in image.header.iter() {
+        if !matches!(card.keyword.as_str(), "BITPIX" | "BZERO" | "BSCALE") {
+            header.add_card(card);
+        }
+    }
+    header.add_card(&Card::new("BITPIX".into(), "-64".into(), None));
+    let data = ImageData::F64(astrors_layout(&frame.view()));
+    Ok(Some((index, ImageHDU::new(header, data))))
+}
+
+/// The cube slices `LoaderOptions::frames` keeps, `[None]` for a 2D image.
+fn selected_frames(
+    image: &ImageHDU,
+    options: &LoaderOptions,
+    path: &Path,
+) -> Result<Vec<Option<usize>>, FitsLoaderError> {
+    match cube_depth(image) {
+        None => Ok(vec![None]),
+        Some(depth) => {
+            let range = options.frames.clone().unwrap_or(0..depth);
+            if range.is_empty() || range.end > depth {
+                return Err(FitsLoaderError::FrameOutOfRange {
+                    path: path.display().to_string(),
+                    start: range.start,
+                    end: range.end,
+                    depth,
+                });
+            }
+            Ok(range.map(Some).collect())
+        }
+    }
+}
+
+/// The first of the cards `names` present in `header`, `None` when it is missing
+/// or not positive.
+fn monitor_reading(header: &Header, names: &[&str]) -> Option<f64> {
+    names
+        .iter()
+        .find_map(|name| card_f64(header, name))
+        .filter(|reading| reading.is_finite() && *reading > 0.0)
+}
+
+/// Transmission of the absorber in the beam, `None` when the card is missing,
+/// the index is not in the table or the transmission is not in `(0, 1]`.
+fn filter_transmission(header: &Header, correction: &FilterCorrection) -> Option<f64> {
+    let transmission = match correction {
+        FilterCorrection::Transmission => card_f64(header, HeaderValue::FilterTransmission.hdu())?,
+        FilterCorrection::Table {
+            card,
+            transmissions,
+        } => {
+            let index = card_f64(header, card)?;
+            if index < 0.0 || index.fract() != 0.0 {
+                return None;
+            }
+            *transmissions.get(index as usize)?
+        }
+    };
+    (transmission > 0.0 && transmission <= 1.0).then_some(transmission)
+}
 
-    let meta = match hdul.hdus.get(0) {
-        Some(HDU::Primary(hdu)) => process_metadata(hdu, header_items)?,
-        _ => return Err(FitsLoaderError::NoData),
+/// Stacks two frames vertically, aligning their schemas first.
+///
+/// The alignment is that of the loaders, see `promote_mixed_cards`: columns
+/// present in only one of the frames are added to the other as nulls of the
+/// same dtype, the result follows the column order of `acc` with any new
+/// columns appended. A column that is all nulls of the `Null` dtype in one frame
+/// is cast to its dtype in the other, and numeric columns of differing dtypes
+/// are promoted as by `promoted_dtype`.
+pub fn combine_dataframes_with_alignment(
+    acc: DataFrame,
+    df: DataFrame,
+) -> Result<DataFrame, FitsLoaderError> {
+    let frames = promote_mixed_cards(vec![acc.lazy(), df.lazy()])?;
+    Ok(concat_lf_diagonal(frames, UnionArgs::default())?.collect()?)
+}
+
+/// Flat images can only be stacked and reshaped when every frame has the same size.
+fn check_image_shapes(
+    first: &DataFrame,
+    dataframes: &[(PathBuf, DataFrame)],
+) -> Result<(), FitsLoaderError> {
+    let shape = |df: &DataFrame| -> Result<(usize, usize), FitsLoaderError> {
+        let dim = |name: &str| -> Result<usize, FitsLoaderError> {
+            Ok(df.column(name)?.u32()?.get(0).unwrap_or(0) as usize)
+        };
+        Ok((dim("Image Rows")?, dim("Image Cols")?))
+    };
+    // Header-only files have no image to compare.
+    let framed = |df: &&DataFrame| df.column("Image Rows").is_ok();
+    let frames = dataframes.iter().map(|(_, df)| df);
+    let Some(reference) = std::iter::once(first).chain(frames).find(framed) else {
+        return Ok(());
+    };
+    let expected = shape(reference)?;
+    for (path, df) in dataframes.iter().filter(|(_, df)| framed(&df)) {
+        let found = shape(df)?;
+        if found != expected {
+            return Err(FitsLoaderError::ImageShapeMismatch {
+                path: path.display().to_string(),
+                expected,
+                found,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Errors if any frame carries columns the first frame does not have.
+fn check_schema(
+    first: &DataFrame,
+    dataframes: &[(PathBuf, DataFrame)],
+) -> Result<(), FitsLoaderError> {
+    for (path, df) in dataframes {
+        let extra: Vec<String> = df
+            .get_column_names()
+            .into_iter()
+            .filter(|name| first.column(name).is_err())
+            .map(|name| name.to_string())
+            .collect();
+        if !extra.is_empty() {
+            return Err(FitsLoaderError::SchemaMismatch {
+                path: path.display().to_string(),
+                columns: extra,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Reads a set of FITS files in parallel and combines them into a single frame.
+///
+/// A file on which the FITS reader panics, e.g. on malformed data, fails with
+/// a `FitsError` naming it like any other unreadable file, instead of aborting
+/// the whole load. The panic message is still printed by the panic hook.
+pub fn read_multiple_fits(
+    file_paths: Vec<PathBuf>,
+    header_items: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    read_multiple_fits_with_options(file_paths, header_items, &LoaderOptions::default())
+}
+
+/// Same as `read_multiple_fits`, with explicit `LoaderOptions`.
+///
+/// Unless `LoaderOptions::columns` picks the columns, they come out in a fixed
+/// order regardless of which file introduced them:
+///
+/// 1. header metadata, in `header_items` order (card order for `Other`),
+/// 2. calculated domains (`Lambda [Å]`, `Theta Offset [deg]`, `Q [Å⁻¹]`) and
+///    `LoaderOptions::expressions`,
+/// 3. image columns and statistics, in the order `process_frame` emits them,
+/// 4. `Source Path`, `Source Dir` and the file name fields.
+pub fn read_multiple_fits_with_options(
+    file_paths: Vec<PathBuf>,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    Ok(read_multiple_fits_timed(file_paths, header_items, options)?.0)
+}
+
+/// Same as `read_multiple_fits_with_options`, also returning the time spent on
+/// every file in path order.
+///
+/// Files are read in parallel, so the per-file times add up to more than the
+/// wall-clock time of the call. The time to combine the frames is not part of
+/// any file.
+pub fn read_multiple_fits_timed(
+    file_paths: Vec<PathBuf>,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<(DataFrame, Vec<FileTiming>), FitsLoaderError> {
+    let files = file_paths.into_iter().map(|path| (path, None)).collect();
+    let (df, timings, _) = read_files(files, header_items, options)?;
+    Ok((df, timings))
+}
+
+/// Same as `read_multiple_fits_with_options`, also returning the `LoadReport`
+/// of the header items checked before the files are read.
+pub fn read_multiple_fits_with_report(
+    file_paths: Vec<PathBuf>,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<(DataFrame, LoadReport), FitsLoaderError> {
+    let files = file_paths.into_iter().map(|path| (path, None)).collect();
+    let (df, _, report) = read_files(files, header_items, options)?;
+    Ok((df, report))
+}
+
+/// Same as `read_multiple_fits_with_options`, handing the frame of every batch
+/// of `LoaderOptions::chunk_size` files to `sink` instead of combining them,
+/// e.g. to write each batch to its own file, so that a load of any length
+/// holds a single batch in memory. Without `chunk_size` the whole load is one
+/// batch.
+///
+/// Every batch is finished on its own, so the columns computed across frames
+/// only see the frames of their batch: the `BeamReference::FirstFrame` offsets,
+/// the median exposure of `exposure_outlier`, and the `Frame Interval [s]`,
+/// null on the first frame of every batch. A `sink` error stops the load and
+/// is returned.
+pub fn read_multiple_fits_batched(
+    file_paths: Vec<PathBuf>,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+    mut sink: impl FnMut(DataFrame) -> Result<(), FitsLoaderError>,
+) -> Result<LoadReport, FitsLoaderError> {
+    let files = file_paths.into_iter().map(|path| (path, None)).collect();
+    let files = loadable_files(files, options)?;
+    if files.is_empty() && options.allow_empty {
+        return Ok(LoadReport::default());
+    }
+    let report = check_header_items(&files, header_items, options)?;
+    let options = &*with_reference_frames(options)?;
+    let mut batches = 0;
+    read_chunks(&files, header_items, options, |combined, groups| {
+        batches += 1;
+        sink(finish_frame(combined, groups, options)?)
+    })?;
+    if batches == 0 {
+        return Err(FitsLoaderError::NoData);
+    }
+    Ok(report)
+}
+
+/// Warnings found on the first file of a load before the others are read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadReport {
+    /// The file the header items were checked on, the first that opens.
+    pub checked_file: Option<PathBuf>,
+    /// The header items it lacks, see `missing_header_items`. Their columns are
+    /// null in the rows of every file without the card, most likely a typo
+    /// when the first file already lacks it.
+    pub missing_header_items: Vec<String>,
+}
+
+/// Card names of the `header_items` for which `path` holds none of the
+/// candidate cards or configured aliases, in `header_items` order.
+///
+/// Checking a single file of a scan catches typos before the whole directory
+/// is read, with `LoaderOptions::strict_schema` the load fails on them.
+pub fn missing_header_items(
+    path: impl AsRef<Path>,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<Vec<String>, FitsLoaderError> {
+    let header = read_header(path, options.metadata_hdu)?;
+    Ok(header_items
+        .iter()
+        .filter(|key| {
+            !key.is_wildcard() && !key.is_optional() && find_card(&header, key, options).is_none()
+        })
+        .map(|key| key.hdu().to_string())
+        .collect())
+}
+
+/// Reports every absent header item of the first file that opens. With
+/// `LoaderOptions::strict_schema` they are an error instead, listing them all
+/// rather than the first missing key of whichever file fails first.
+fn check_header_items(
+    files: &[(PathBuf, Option<String>)],
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<LoadReport, FitsLoaderError> {
+    if header_items.is_empty() {
+        return Ok(LoadReport::default());
+    }
+    let first = match files.first() {
+        Some((path, _)) if options.fail_fast => Some((
+            path,
+            missing_header_items(path, header_items, options).map_err(|e| file_error(path, e))?,
+        )),
+        _ => files.iter().find_map(|(path, _)| {
+            Some((
+                path,
+                missing_header_items(path, header_items, options).ok()?,
+            ))
+        }),
     };
+    match first {
+        Some((path, missing)) if options.strict_schema && !missing.is_empty() => {
+            Err(FitsLoaderError::MissingHeaderItems {
+                path: display_path(path),
+                items: missing,
+            })
+        }
+        Some((path, missing)) => Ok(LoadReport {
+            checked_file: Some(path.clone()),
+            missing_header_items: missing,
+        }),
+        None => Ok(LoadReport::default()),
+    }
+}
+
+/// The `Experiment Type` cell of a frame. The enum holds every spelling, so
+/// columns of different loads share one dtype.
+fn experiment_type_column(experiment: ExperimentType) -> Result<Column, FitsLoaderError> {
+    let names = [
+        ExperimentType::Xrr,
+        ExperimentType::Xrs,
+        ExperimentType::Other,
+    ]
+    .map(|e| e.name());
+    let dtype = create_enum_dtype(Utf8ViewArray::from_slice_values(names));
+    Ok(Column::new("Experiment Type".into(), [experiment.name()]).cast(&dtype)?)
+}
+
+/// Common dtype of a column held as `left` in one frame and `right` in another,
+/// `None` when they already agree or cannot be promoted.
+///
+/// A card written as `0` in one file and `0.5` in the next is the same number,
+/// as is a pixel of an `I16` image and of an `F32` one written after a firmware
+/// update, so differing numeric dtypes become `Float64`, nested in the same
+/// lists or arrays. Other dtype conflicts are left for the concat to report.
+fn promoted_dtype(left: &DataType, right: &DataType) -> Option<DataType> {
+    fn float_leaf(dtype: &DataType) -> Option<DataType> {
+        match dtype {
+            DataType::List(inner) => Some(DataType::List(Box::new(float_leaf(inner)?))),
+            DataType::Array(inner, size) => {
+                Some(DataType::Array(Box::new(float_leaf(inner)?), *size))
+            }
+            dtype if dtype.is_numeric() => Some(DataType::Float64),
+            _ => None,
+        }
+    }
+    if left == right {
+        return None;
+    }
+    float_leaf(left).filter(|dtype| float_leaf(right).as_ref() == Some(dtype))
+}
 
-    let img_data = match hdul.hdus.get(2) {
-        Some(HDU::Image(hdu)) => process_image(hdu)?,
-        _ => return Err(FitsLoaderError::NoData),
+/// Casts the columns of `frames` whose dtype differs between frames to their
+/// common dtype, see `promoted_dtype`.
+fn promote_mixed_cards(frames: Vec<LazyFrame>) -> Result<Vec<LazyFrame>, FitsLoaderError> {
+    let mut schemas = vec![];
+    for frame in &frames {
+        schemas.push(frame.clone().collect_schema()?);
+    }
+    let mut promoted: HashMap<&PlSmallStr, Option<DataType>> = HashMap::new();
+    for (name, dtype) in schemas.iter().flat_map(|schema| schema.iter()) {
+        if *dtype == DataType::Null {
+            continue;
+        }
+        match promoted.get(name) {
+            None => {
+                promoted.insert(name, Some(dtype.clone()));
+            }
+            Some(Some(common)) if common != dtype => {
+                let common = promoted_dtype(common, dtype);
+                promoted.insert(name, common);
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(frames
+        .into_iter()
+        .zip(&schemas)
+        .map(|(frame, schema)| {
+            let casts: Vec<Expr> = schema
+                .iter()
+                .filter_map(|(name, dtype)| match promoted.get(name) {
+                    Some(Some(common)) if common != dtype => {
+                        Some(col(name.clone()).cast(common.clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if casts.is_empty() {
+                frame
+            } else {
+                frame.with_columns(casts)
+            }
+        })
+        .collect())
+}
+
+/// Runs `load` on every item in parallel, keeping the results in order.
+///
+/// With `LoaderOptions::fail_fast`, no item is started once one has failed and
+/// the error of the first failing item is returned as `FitsLoaderError::File`.
+/// A panic in `load` fails its item with a `FitsError` rather than unwinding
+/// through the thread pool.
+fn load_parallel<I, T>(
+    items: &[I],
+    path: impl Fn(&I) -> &Path + Sync,
+    options: &LoaderOptions,
+    load: impl Fn(&I) -> Result<T, FitsLoaderError> + Sync,
+) -> Result<Vec<T>, FitsLoaderError>
+where
+    I: Sync,
+    T: Send,
+{
+    let load = |item: &I| {
+        panic::catch_unwind(AssertUnwindSafe(|| load(item))).unwrap_or_else(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            Err(FitsLoaderError::FitsError(format!(
+                "Panicked while loading {}: {}",
+                display_path(path(item)),
+                message
+            )))
+        })
     };
+    if !across_files(items.iter().map(&path), options.parallelism) {
+        let mut loaded = Vec::with_capacity(items.len());
+        for item in items {
+            match load(item) {
+                Ok(value) => loaded.push(value),
+                Err(e) if options.fail_fast => return Err(file_error(path(item), e)),
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(loaded);
+    }
+    if !options.fail_fast {
+        return items.par_iter().map(&load).collect();
+    }
+    let failed = AtomicBool::new(false);
+    let results: Vec<Option<Result<T, FitsLoaderError>>> = items
+        .par_iter()
+        .map(|item| {
+            if failed.load(Ordering::Relaxed) {
+                return None;
+            }
+            let result = load(item);
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            Some(result)
+        })
+        .collect();
+    let mut loaded = vec![];
+    for (item, result) in items.iter().zip(results) {
+        match result {
+            Some(Ok(value)) => loaded.push(value),
+            Some(Err(e)) => return Err(file_error(path(item), e)),
+            None => {}
+        }
+    }
+    Ok(loaded)
+}
 
-    let names = process_file_name(file_path);
+/// Average file size from which `Parallelism::Auto` packages few files on the
+/// whole pool, about a 2k x 2k frame of 16-bit pixels.
+const WITHIN_FRAMES_BYTES: u64 = 8 << 20;
 
-    let mut columns = meta;
-    columns.extend(img_data);
-    columns.extend(names);
+/// Whether the files at `paths` are loaded in parallel, see `Parallelism`.
+fn across_files<'a>(paths: impl Iterator<Item = &'a Path>, parallelism: Parallelism) -> bool {
+    match parallelism {
+        Parallelism::AcrossFiles => true,
+        Parallelism::WithinFrames => false,
+        Parallelism::Auto => {
+            let sizes: Vec<u64> = paths
+                .map(|path| fs::metadata(path).map_or(0, |meta| meta.len()))
+                .collect();
+            let mean = sizes.iter().sum::<u64>() / sizes.len().max(1) as u64;
+            sizes.len() >= rayon::current_num_threads() || mean < WITHIN_FRAMES_BYTES
+        }
+    }
+}
 
-    DataFrame::new(columns).map_err(FitsLoaderError::PolarsError)
+fn file_error(path: &Path, source: FitsLoaderError) -> FitsLoaderError {
+    FitsLoaderError::File {
+        path: display_path(path),
+        source: Box::new(source),
+    }
 }
 
+/// Loads and combines `files`, each with the `Source Dir` to report for it.
+///
+/// The header items are checked on the first file before any is loaded.
+fn read_files(
+    files: Vec<(PathBuf, Option<String>)>,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<(DataFrame, Vec<FileTiming>, LoadReport), FitsLoaderError> {
+    let files = loadable_files(files, options)?;
+    if files.is_empty() && options.allow_empty {
+        return Ok((
+            empty_frame(header_items, options)?,
+            vec![],
+            LoadReport::default(),
+        ));
+    }
+    let report = check_header_items(&files, header_items, options)?;
+    let options = &*with_reference_frames(options)?;
+    let mut chunks = vec![];
+    let (groups, timings) = read_chunks(&files, header_items, options, |combined, _| {
+        // Collecting a chunk frees its per-file frames before the next one is read.
+        chunks.push(match options.chunk_size {
+            Some(_) => combined.collect()?.lazy(),
+            None => combined,
+        });
+        Ok(())
+    })?;
+    if chunks.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+    let combined = match chunks.len() {
+        1 => chunks.remove(0),
+        _ => concat_lf_diagonal(promote_mixed_cards(chunks)?, UnionArgs::default())?,
+    };
+    Ok((finish_frame(combined, &groups, options)?, timings, report))
+}
+
+/// `files` without those `LoaderOptions::min_file_size` and `drop_duplicates`
+/// leave out.
+fn loadable_files(
+    files: Vec<(PathBuf, Option<String>)>,
+    options: &LoaderOptions,
+) -> Result<Vec<(PathBuf, Option<String>)>, FitsLoaderError> {
+    let files = match options.min_file_size {
+        Some(min_size) => files
+            .into_iter()
+            .filter(|(path, _)| is_sane_file(path, min_size))
+            .collect(),
+        None => files,
+    };
+    if options.drop_duplicates {
+        drop_duplicate_files(files)
+    } else {
+        Ok(files)
+    }
+}
+
+/// Reads `files` in batches of `LoaderOptions::chunk_size`, handing each batch
+/// combined in path order to `on_chunk` along with the column groups seen so
+/// far. `options` must have its reference frames loaded, see
+/// `with_reference_frames`.
+fn read_chunks(
+    files: &[(PathBuf, Option<String>)],
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+    mut on_chunk: impl FnMut(LazyFrame, &ColumnGroups) -> Result<(), FitsLoaderError>,
+) -> Result<(ColumnGroups, Vec<FileTiming>), FitsLoaderError> {
+    let chunk_size = options.chunk_size.unwrap_or(files.len()).max(1);
+    let mut groups = ColumnGroups::default();
+    // First row of the first file, the reference for the schema and shape checks.
+    let mut first: Option<DataFrame> = None;
+    let mut timings = vec![];
+    for chunk in files.chunks(chunk_size) {
+        let loaded = load_parallel(
+            chunk,
+            |(path, _)| path,
+            options,
+            |(path, source_dir)| {
+                let (df, groups, timing) =
+                    read_fits_grouped(path.clone(), header_items, options, source_dir.clone())?;
+                Ok((path.clone(), df, groups, timing))
+            },
+        )?;
+        let mut dataframes = vec![];
+        for (path, df, file_groups, timing) in loaded {
+            groups.merge(file_groups);
+            dataframes.push((path, df));
+            timings.push(timing);
+        }
+        let Some((_, head)) = dataframes.first() else {
+            continue;
+        };
+        let first = first.get_or_insert_with(|| head.head(Some(1)));
+
+        if options.strict_schema {
+            check_schema(first, &dataframes)?;
+        }
+        if options.image.fixed_size_list {
+            check_image_shapes(first, &dataframes)?;
+        }
+
+        // One diagonal concat in path order: columns missing from a frame are null-filled,
+        // like `combine_dataframes_with_alignment`, and dtype conflicts surface as errors.
+        let frames: Vec<LazyFrame> = dataframes.into_iter().map(|(_, df)| df.lazy()).collect();
+        let combined = concat_lf_diagonal(promote_mixed_cards(frames)?, UnionArgs::default())?;
+        on_chunk(combined, &groups)?;
+    }
+    Ok((groups, timings))
+}
+
+/// Takes the `Beam Offset X/Y [px]` of `lf`, still the plain centroids, relative
+/// to the first frame holding one when `ImageOptions::beam_offset` is
+/// `BeamReference::FirstFrame`. `lf` must be in path order.
+fn relative_beam_offsets(
+    mut lf: LazyFrame,
+    options: &ImageOptions,
+) -> Result<LazyFrame, FitsLoaderError> {
+    let first_frame = options.beam_offset == Some(BeamReference::FirstFrame);
+    if !first_frame || !lf.collect_schema()?.contains("Beam Offset X [px]") {
+        return Ok(lf);
+    }
+    let offsets = ["Beam Offset X [px]", "Beam Offset Y [px]"]
+        .map(|name| (col(name) - col(name).drop_nulls().first()).alias(name));
+    Ok(lf.with_columns(offsets))
+}
+
+/// Adds the calculated domains and user expressions to the combined frame and
+/// projects it onto `LoaderOptions::columns` or the `groups` order.
+fn finish_frame(
+    mut combined: LazyFrame,
+    groups: &ColumnGroups,
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    let exposure = &*exposure_column(&options.unit_overrides);
+    if let Some(min_exposure) = options.min_exposure {
+        if !combined.collect_schema()?.contains(exposure) {
+            return Err(FitsLoaderError::MissingColumn(exposure.to_string()));
+        }
+        combined = combined.filter(closed_shutter(exposure, min_exposure).not());
+    }
+    let mut combined = relative_beam_offsets(combined, &options.image)?;
+    if let Some(calibration) = &options.energy_calibration {
+        let energy = HeaderValue::BeamlineEnergy.name();
+        if combined.collect_schema()?.contains(energy) {
+            combined = combined.with_columns([
+                col(energy).alias("Beamline Energy Raw [eV]"),
+                calibration.expr(col(energy)).alias(energy),
+            ]);
+        }
+    }
+    let mut lz = calculated_domains_with_unit(combined, options.q_unit);
+    let schema = lz.collect_schema()?;
+    if let Some(axis) = groups.sort_column(&schema) {
+        let mut by = vec![axis.as_str()];
+        by.extend(frame_order(&schema));
+        let nulls_last: Vec<bool> = by.iter().map(|name| *name != axis.as_str()).collect();
+        lz = lz.sort(
+            by,
+            SortMultipleOptions::default()
+                .with_maintain_order(true)
+                .with_nulls_last_multi(nulls_last),
+        );
+    }
+    if options.angle_radians {
+        let schema = lz.collect_schema()?;
+        let radians: Vec<Expr> = ["Sample Theta", "CCD Theta"]
+            .into_iter()
+            .filter(|name| schema.contains(&format!("{} [deg]", name)))
+            .map(|name| {
+                (col(format!("{} [deg]", name)) * lit(std::f64::consts::PI / 180.0))
+                    .alias(format!("{} [rad]", name))
+            })
+            .collect();
+        lz = lz.with_columns(radians);
+    }
+    if let Some(fraction) = options.exposure_outlier {
+        if !lz.collect_schema()?.contains(exposure) {
+            return Err(FitsLoaderError::MissingColumn(exposure.to_string()));
+        }
+        let median = col(exposure).median();
+        lz = lz.with_column(
+            col(exposure)
+                .lt(median.clone() * lit(1.0 - fraction))
+                .or(col(exposure).gt(median * lit(1.0 + fraction)))
+                .alias("Exposure Outlier"),
+        );
+    }
+    if options.frame_interval {
+        if !lz.collect_schema()?.contains(exposure) {
+            return Err(FitsLoaderError::MissingColumn(exposure.to_string()));
+        }
+        // Take the differences in time order, then restore the scan order.
+        let row = "Frame Interval Row";
+        let time = col("Observation Time").cast(DataType::Int64);
+        let interval = (time.clone() - time.shift(lit(1))).cast(DataType::Float64) / lit(1e6);
+        lz = lz
+            .with_row_index(row, None)
+            .sort(
+                ["Observation Time"],
+                SortMultipleOptions::default()
+                    .with_maintain_order(true)
+                    .with_nulls_last(true),
+            )
+            .with_column(interval.alias("Frame Interval [s]"))
+            .with_column((col(exposure) / col("Frame Interval [s]")).alias("Duty Cycle"))
+            .sort([row], SortMultipleOptions::default())
+            .drop([row]);
+    }
+    if let Some(normalization) = &options.normalization {
+        let individual = options.normalize_beam_current.is_some()
+            || options.normalize_i0.is_some()
+            || options.filter_correction.is_some();
+        if individual {
+            return Err(FitsLoaderError::InvalidConfig(
+                "normalization replaces normalize_beam_current, normalize_i0 and filter_correction"
+                    .into(),
+            ));
+        }
+        let schema = lz.collect_schema()?;
+        let mut factor = lit(1.0);
+        for step in &normalization.steps {
+            let name = step.column();
+            if !schema.contains(name) {
+                return Err(FitsLoaderError::MissingColumn(name.to_string()));
+            }
+            let value = col(name).cast(DataType::Float64);
+            let valid = value.clone().gt(lit(0.0)).and(value.clone().is_finite());
+            factor = factor * when(valid).then(value).otherwise(lit(NULL));
+        }
+        let normalized: Vec<Expr> = INTEGRATED_COLUMNS
+            .into_iter()
+            .filter(|name| schema.contains(name))
+            .map(|name| {
+                let value = col(name) / col("Normalization Factor");
+                match normalization.output {
+                    NormalizationOutput::Paired => {
+                        value.alias(NormalizationOutput::intensity_name(name))
+                    }
+                    NormalizationOutput::Replace => when(col("Normalization Factor").is_not_null())
+                        .then(value)
+                        .otherwise(col(name))
+                        .alias(name),
+                }
+            })
+            .collect();
+        lz = lz
+            .with_column(factor.alias("Normalization Factor"))
+            .with_columns(normalized);
+    }
+    if !options.expressions.is_empty() {
+        lz = lz.with_columns(&options.expressions);
+    }
+    let schema = lz.collect_schema()?;
+    let selection = match &options.columns {
+        Some(columns) => {
+            if let Some(missing) = columns.iter().find(|c| !schema.contains(c)) {
+                return Err(FitsLoaderError::MissingColumn(missing.clone()));
+            }
+            columns.iter().map(|c| col(c.as_str())).collect()
+        }
+        None => groups.order(&schema),
+    };
+    Ok(lz.select(selection).collect()?)
+}
+
+/// Loads every FITS file directly inside `dir` as one scan.
+///
+/// Rows are ordered by frame number, or by the scanned motor when the files
+/// carry a `SCANAXIS` card, such as `Sample Theta` or `Beamline Energy`. Each
+/// file's card is kept as `Scan Axis`, and the motor column is used when all
+/// files name the same loaded one, with ties kept in frame order. A scan point
+/// index card (`Point Index`, `SCANPT` or `POINTNUM`) is kept as `Point Index`
+/// and orders the frames ahead of the frame number parsed from the file name.
+///
+/// Card names that are only known at runtime convert into header items with
+/// `HeaderValue::from`, e.g. `names.iter().map(|n| n.as_str().into()).collect()`.
 pub fn read_experiment(
     dir: &str,
-    header_items: &Vec<HeaderValue>,
+    header_items: &[HeaderValue],
 ) -> Result<DataFrame, FitsLoaderError> {
+    read_experiment_with_options(dir, header_items, &LoaderOptions::default())
+}
+
+/// Same as `read_experiment`, with explicit `LoaderOptions`.
+pub fn read_experiment_with_options(
+    dir: &str,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    read_multiple_fits_with_options(fits_files(dir)?, header_items, options)
+}
+
+/// Same as `read_experiment_with_options`, also returning the `LoadReport` of
+/// the header items checked on the first file.
+pub fn read_experiment_with_report(
+    dir: &str,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<(DataFrame, LoadReport), FitsLoaderError> {
+    read_multiple_fits_with_report(fits_files(dir)?, header_items, options)
+}
+
+/// Every FITS file directly inside `dir`, in path order.
+fn fits_files(dir: &str) -> Result<Vec<PathBuf>, FitsLoaderError> {
     let dir_path = std::path::PathBuf::from(dir);
 
     if !dir_path.exists() {
         return Err(FitsLoaderError::NoData);
     }
 
-    let entries: Vec<_> = fs::read_dir(dir)
+    let entries: Vec<PathBuf> = fs::read_dir(dir)
         .map_err(FitsLoaderError::IoError)?
         .par_bridge()
         .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.path().extension().and_then(|ext| ext.to_str()) == Some("fits"))
+        .filter(|entry| is_fits_file(&entry.path()))
+        .map(|entry| entry.path())
+        .collect();
+    let mut entries = entries;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Reads a scan split across several acquisition directories.
+///
+/// The FITS files of all directories are loaded as one scan, the same way
+/// `read_experiment` loads a single directory, so the schema alignment and
+/// column order are those of `read_multiple_fits_with_options`. The directory
+/// each row came from is added as `Source Dir`, next to `Source Path`.
+pub fn read_experiment_dirs(
+    dirs: &[&str],
+    header_items: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    read_experiment_dirs_with_options(dirs, header_items, &LoaderOptions::default())
+}
+
+/// Same as `read_experiment_dirs`, with explicit `LoaderOptions`.
+pub fn read_experiment_dirs_with_options(
+    dirs: &[&str],
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    let mut files = vec![];
+    for dir in dirs {
+        let source = display_path(Path::new(dir));
+        files.extend(
+            fits_files(dir)?
+                .into_iter()
+                .map(|path| (path, Some(source.clone()))),
+        );
+    }
+    Ok(read_files(files, header_items, options)?.0)
+}
+
+/// Same as `read_experiment`, with the header items and options taken from a
+/// per-instrument `InstrumentConfig`.
+pub fn read_experiment_with_config(
+    dir: &str,
+    config: &InstrumentConfig,
+) -> Result<DataFrame, FitsLoaderError> {
+    read_experiment_with_options(dir, &config.header_items(), &config.options())
+}
+
+/// Same as `read_experiment`, with the options of a `DetectorPreset`. Load with
+/// `read_experiment_with_options` and `DetectorPreset::options` to override
+/// single fields of the preset.
+pub fn read_experiment_with_preset(
+    dir: &str,
+    header_items: &[HeaderValue],
+    preset: DetectorPreset,
+) -> Result<DataFrame, FitsLoaderError> {
+    read_experiment_with_options(dir, header_items, &preset.options())
+}
+
+/// Same as `read_experiment`, with the header items of `experiment`, e.g. the
+/// XRR angles, energy and exposure for `ExperimentType::Xrr`, followed by the
+/// `extra` items not already among them, and the options of
+/// `ExperimentType::default_options`. `ExperimentType::Other` without `extra`
+/// loads every card, like an empty `header_items`.
+pub fn read_experiment_typed(
+    dir: &str,
+    experiment: ExperimentType,
+    extra: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    let header_items = typed_header_items(experiment, extra);
+    read_experiment_with_options(dir, &header_items, &experiment.default_options())
+}
+
+/// Same as `read_experiment_typed` for the single file at `file_path`.
+pub fn read_fits_typed(
+    file_path: std::path::PathBuf,
+    experiment: ExperimentType,
+    extra: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    let header_items = typed_header_items(experiment, extra);
+    read_fits_with_options(file_path, &header_items, &experiment.default_options())
+}
+
+/// Same as `read_experiment_typed` for the files at `file_paths`, see
+/// `read_multiple_fits`.
+pub fn read_multiple_fits_typed(
+    file_paths: Vec<PathBuf>,
+    experiment: ExperimentType,
+    extra: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    let header_items = typed_header_items(experiment, extra);
+    read_multiple_fits_with_options(file_paths, &header_items, &experiment.default_options())
+}
+
+/// Same as `read_experiment_typed` for the files of `dir` matching `patterns`,
+/// see `read_experiment_pattern`.
+pub fn read_experiment_pattern_typed(
+    dir: &str,
+    patterns: &[&str],
+    experiment: ExperimentType,
+    extra: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    let header_items = typed_header_items(experiment, extra);
+    read_experiment_pattern_with_options(
+        dir,
+        patterns,
+        &header_items,
+        &experiment.default_options(),
+    )
+}
+
+/// `ExperimentType::get_keys` followed by the keys of `extra` it lacks.
+fn typed_header_items(experiment: ExperimentType, extra: &[HeaderValue]) -> Vec<HeaderValue> {
+    let mut header_items = experiment.get_keys();
+    for key in extra {
+        if !header_items.iter().any(|item| item.hdu() == key.hdu()) {
+            header_items.push(key.clone());
+        }
+    }
+    header_items
+}
+
+/// Reads every FITS file in `dir` whose name matches at least one of `patterns`.
+///
+/// Patterns are matched against the file name only and support `*`, `?`, `[...]`
+/// and brace expansion (`{Y6,PM6}_refl_*.fits`). Files matched by more than one
+/// pattern are only loaded once.
+pub fn read_experiment_pattern(
+    dir: &str,
+    patterns: &[&str],
+    header_items: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    read_experiment_pattern_with_options(dir, patterns, header_items, &LoaderOptions::default())
+}
+
+/// Same as `read_experiment_pattern`, with explicit `LoaderOptions`.
+pub fn read_experiment_pattern_with_options(
+    dir: &str,
+    patterns: &[&str],
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    let entries = list_matching(dir, patterns)?;
+    if entries.is_empty() && !options.allow_empty {
+        return Err(FitsLoaderError::NoData);
+    }
+
+    read_multiple_fits_with_options(entries, header_items, options)
+}
+
+/// Lists the FITS files in `dir` that `read_experiment_pattern` would load, in
+/// path order, without reading them.
+///
+/// An empty list means no file matched; a missing `dir` is `NoData` like in
+/// the loaders.
+pub fn list_matching(dir: &str, patterns: &[&str]) -> Result<Vec<PathBuf>, FitsLoaderError> {
+    let dir_path = std::path::PathBuf::from(dir);
+
+    if !dir_path.exists() {
+        return Err(FitsLoaderError::NoData);
+    }
+
+    let expanded: Vec<String> = patterns.iter().flat_map(|p| expand_braces(p)).collect();
+
+    let entries: BTreeSet<PathBuf> = fs::read_dir(dir)
+        .map_err(FitsLoaderError::IoError)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_fits_file(path))
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| expanded.iter().any(|p| glob_match(p, &name)))
+        })
         .collect();
+    Ok(entries.into_iter().collect())
+}
+
+// ================== Reference Frames ==================
 
-    let dataframes: Result<Vec<DataFrame>, FitsLoaderError> = entries
+/// `options` with the `dark_frame` and `flat_frame` files read into the shared
+/// `ImageOptions::dark` and `flat`, borrowed as is without either.
+///
+/// Called once per load before any file is read, so the workers share one
+/// buffer per reference and the two references are checked against each other
+/// only here.
+fn with_reference_frames(
+    options: &LoaderOptions,
+) -> Result<Cow<'_, LoaderOptions>, FitsLoaderError> {
+    if options.dark_frame.is_none() && options.flat_frame.is_none() {
+        return Ok(Cow::Borrowed(options));
+    }
+    let dark = match &options.dark_frame {
+        Some(path) => Some(reference_frame(path, options)?),
+        None => None,
+    };
+    let flat = match &options.flat_frame {
+        Some(path) => {
+            let mut flat = reference_frame(path, options)?;
+            if let Some(dark) = &dark {
+                if dark.dim() != flat.dim() {
+                    return Err(FitsLoaderError::ImageShapeMismatch {
+                        path: path.display().to_string(),
+                        expected: dark.dim(),
+                        found: flat.dim(),
+                    });
+                }
+                flat -= dark;
+            }
+            let finite: Vec<f64> = flat.iter().copied().filter(|v| v.is_finite()).collect();
+            let mean = finite.iter().sum::<f64>() / finite.len() as f64;
+            if !(mean.is_finite() && mean > 0.0) {
+                return Err(FitsLoaderError::InvalidConfig(format!(
+                    "flat field {} has no positive mean",
+                    path.display()
+                )));
+            }
+            flat.mapv_inplace(|v| if v > 0.0 { v / mean } else { f64::NAN });
+            Some(flat)
+        }
+        None => None,
+    };
+    let mut options = options.clone();
+    options.image.dark = dark.map(Arc::new).or(options.image.dark);
+    options.image.flat = flat.map(Arc::new).or(options.image.flat);
+    Ok(Cow::Owned(options))
+}
+
+/// Pixels of the reference frame at `path` as the frames see them: deinterlaced
+/// and cut to the science region with its overscan bias subtracted.
+fn reference_frame(path: &Path, options: &LoaderOptions) -> Result<Array2<f64>, FitsLoaderError> {
+    let fits = CcdFits::new(path)?;
+    let image = fits.image_hdu(&options.image_hdu)?;
+    let mut pixels = frame_pixels(image, None).map_err(|e| e.with_path(path))?;
+    if let Some(readout) = &options.image.deinterlace {
+        pixels = deinterlace_rows(&pixels.view(), readout)?;
+    }
+    if let Some(overscan) = &options.image.overscan {
+        let (bias, science) = split_overscan(&pixels.view(), overscan)?;
+        pixels = science - bias.unwrap_or(0.0);
+    }
+    Ok(pixels)
+}
+
+// ================== Duplicate Files ==================
+
+/// Groups of files among `paths` with byte-identical data units, from copy
+/// mistakes that would count a frame twice.
+///
+/// Only the data of every HDU is compared, so copies whose headers differ, e.g.
+/// in a rewritten `DATE`, are still found. Files are told apart by the length
+/// and a 64-bit hash of their data, hashed in parallel; a hash collision between
+/// different data is possible in principle but vanishingly unlikely. Each group
+/// holds at least two files in `paths` order, the groups are ordered by their
+/// first file, and files without a duplicate are left out.
+pub fn find_duplicate_files(paths: &[PathBuf]) -> Result<Vec<Vec<PathBuf>>, FitsLoaderError> {
+    let keys: Vec<(usize, u64)> = paths
         .par_iter()
-        .map(|entry| read_fits(entry.path(), &header_items))
+        .map(|path| {
+            let units = data_units(path).map_err(|e| e.with_path(path))?;
+            let bytes = fs::read(path)?;
+            let mut hasher = DefaultHasher::new();
+            let mut len = 0;
+            for &(start, unit_len) in &units {
+                let data = bytes.get(start..start + unit_len).ok_or_else(|| {
+                    FitsLoaderError::FitsError(format!("{} is truncated", display_path(path)))
+                })?;
+                hasher.write(data);
+                len += unit_len;
+            }
+            Ok((len, hasher.finish()))
+        })
+        .collect::<Result<_, FitsLoaderError>>()?;
+
+    let mut index: HashMap<(usize, u64), usize> = HashMap::new();
+    let mut groups: Vec<Vec<PathBuf>> = vec![];
+    for (key, path) in keys.into_iter().zip(paths) {
+        let i = *index.entry(key).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[i].push(path.clone());
+    }
+    groups.retain(|group| group.len() > 1);
+    Ok(groups)
+}
+
+/// `files` without the later copies of every `find_duplicate_files` group.
+fn drop_duplicate_files(
+    files: Vec<(PathBuf, Option<String>)>,
+) -> Result<Vec<(PathBuf, Option<String>)>, FitsLoaderError> {
+    let paths: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+    let copies: BTreeSet<PathBuf> = find_duplicate_files(&paths)?
+        .into_iter()
+        .flat_map(|group| group.into_iter().skip(1))
+        .collect();
+    Ok(files
+        .into_iter()
+        .filter(|(path, _)| !copies.contains(path))
+        .collect())
+}
+
+// ================== File Sanity ==================
+
+/// The bytes every FITS file starts with, the `SIMPLE` keyword and value indicator.
+const FITS_MAGIC: &[u8] = b"SIMPLE  =";
+
+/// Whether `path` is at least `min_size` bytes long and starts with
+/// `FITS_MAGIC`; unreadable files are not.
+fn is_sane_file(path: &Path, min_size: u64) -> bool {
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut magic = [0u8; FITS_MAGIC.len()];
+    file.metadata().is_ok_and(|meta| meta.len() >= min_size)
+        && std::io::Read::read_exact(&mut file, &mut magic).is_ok()
+        && magic == FITS_MAGIC
+}
+
+/// The files among `paths` that `LoaderOptions::min_file_size` skips: shorter
+/// than `min_size` bytes, without the FITS magic, or unreadable, in `paths` order.
+pub fn find_invalid_files(paths: &[PathBuf], min_size: u64) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .filter(|path| !is_sane_file(path, min_size))
+        .cloned()
+        .collect()
+}
+
+// ================== Shutter-Closed Frames ==================
+
+/// Name of the `EXPOSURE` column of a load with `unit_overrides`, `EXPOSURE
+/// [s]` unless they give it another unit.
+fn exposure_column(unit_overrides: &HashMap<String, String>) -> String {
+    let exposure = HeaderValue::Exposure;
+    unit_column_name(exposure.hdu(), Some(exposure.unit()), unit_overrides)
+}
+
+/// Whether a row's `exposure` is at or below `min_exposure`, false without an
+/// exposure.
+fn closed_shutter(exposure: &str, min_exposure: f64) -> Expr {
+    col(exposure).lt_eq(lit(min_exposure)).fill_null(lit(false))
+}
+
+/// The rows of `df` that `LoaderOptions::min_exposure` drops, those exposed
+/// for at most `min_exposure` seconds, in row order. `df` is a load without the
+/// option and with the `unit_overrides` of the load, and must have its exposure
+/// column, `EXPOSURE [s]` by default.
+pub fn find_closed_shutter_frames(
+    df: &DataFrame,
+    min_exposure: f64,
+    unit_overrides: &HashMap<String, String>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let exposure = exposure_column(unit_overrides);
+    if !df.schema().contains(&exposure) {
+        return Err(FitsLoaderError::MissingColumn(exposure));
+    }
+    Ok(df
+        .clone()
+        .lazy()
+        .filter(closed_shutter(&exposure, min_exposure))
+        .collect()?)
+}
+
+// ================== Metadata ==================
+
+/// Header metadata of every FITS file directly inside `dir`, without decoding
+/// any image.
+pub fn read_experiment_metadata(
+    dir: &str,
+    header_items: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    read_experiment_metadata_with_options(dir, header_items, &LoaderOptions::default())
+}
+
+/// Same as `read_experiment_metadata`, with explicit `LoaderOptions`.
+///
+/// The rows are those of `read_experiment_with_options` without the image
+/// columns: the header items, `Comments`/`History` when enabled, the calculated
+/// domains, user expressions and file columns. The image options are ignored.
+///
+/// With `LoaderOptions::metadata_cache` set, headers of files that have not
+/// changed since the previous load are taken from the cache instead of parsing
+/// the files, and the cache is updated with the files that were parsed.
+pub fn read_experiment_metadata_with_options(
+    dir: &str,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    let files = fits_files(dir)?
+        .into_iter()
+        .map(|path| (path, None))
+        .collect();
+    let files = loadable_files(files, options)?;
+    if files.is_empty() && options.allow_empty {
+        return empty_frame(header_items, options);
+    }
+    check_header_items(&files, header_items, options)?;
+    let mut cache = options.metadata_cache.as_deref().map(MetadataCache::load);
+    let entries = load_parallel(
+        &files,
+        |(path, _)| path,
+        options,
+        |(path, _)| {
+            let hdu = options.metadata_hdu;
+            if let Some(entry) = cache.as_ref().and_then(|cache| cache.get(path, hdu)) {
+                return Ok((entry.clone(), false));
+            }
+            if options.verify_checksums {
+                verify_checksums(path)?;
+            }
+            Ok((CacheEntry::new(path, hdu, &read_header(path, hdu)?)?, true))
+        },
+    )?;
+
+    let mut groups = ColumnGroups::default();
+    let mut frames = vec![];
+    let mut updated = false;
+    for ((path, _), (entry, parsed)) in files.iter().zip(entries) {
+        let header = entry.header();
+        let mut meta = process_header_metadata(&header, header_items, options)?;
+        if options.commentary {
+            meta.extend(process_commentary(&header));
+        }
+        if options.header_json {
+            meta.push(header_json(&header));
+        }
+        let scan_axis = scan_axis(&header);
+        meta.extend(point_index(&header).map(|index| Column::new("Point Index".into(), [index])));
+        meta.extend(
+            scan_axis
+                .iter()
+                .map(|axis| Column::new("Scan Axis".into(), [axis.as_str()])),
+        );
+        if options.frame_interval {
+            meta.push(observation_time_column(&header)?);
+        }
+        let file = file_columns(path, options, None)?;
+        let names = |columns: &[Column]| columns.iter().map(|c| c.name().clone()).collect();
+        groups.merge(ColumnGroups {
+            metadata: names(&meta),
+            image: vec![],
+            file: names(&file),
+            scan_axes: scan_axis.into_iter().collect(),
+        });
+        meta.extend(file);
+        frames.push(DataFrame::new(meta)?.lazy());
+        if let Some(cache) = cache.as_mut().filter(|_| parsed) {
+            cache.insert(path, entry);
+            updated = true;
+        }
+    }
+    if frames.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+    if let (Some(cache), Some(dir), true) = (&cache, &options.metadata_cache, updated) {
+        cache.save(dir)?;
+    }
+    let combined = concat_lf_diagonal(promote_mixed_cards(frames)?, UnionArgs::default())?;
+    finish_frame(combined, &groups, options)
+}
+
+// ================== Image Statistics ==================
+
+/// Appends the image statistics selected by `LoaderOptions::image` to `df`, e.g.
+/// a frame from `read_experiment_metadata`, reading only the files named in its
+/// `path_col` column, such as `Source Path`.
+///
+/// Each file is read once however many rows name it. The statistics of a cube
+/// slice are matched by `Frame` when `df` has that column, otherwise every row
+/// of a file gets the statistics of its first kept slice. The image itself is
+/// never added, and the beam current, `I0` and filter normalizations are not
+/// applied. Statistics that `df` already holds are replaced. Rows with a null
+/// path get null statistics.
+pub fn augment_with_image_stats(
+    df: &DataFrame,
+    path_col: &str,
+    options: &LoaderOptions,
+) -> Result<DataFrame, FitsLoaderError> {
+    let paths = df.column(path_col)?.str()?;
+    let unique: BTreeSet<&str> = paths.into_iter().flatten().collect();
+    let unique: Vec<PathBuf> = unique.into_iter().map(PathBuf::from).collect();
+    let by_frame = df.schema().contains("Frame");
+    let options = &*with_reference_frames(options)?;
+    let image_options = ImageOptions {
+        drop_image: true,
+        ..options.image.clone()
+    };
+
+    let stats = load_parallel(
+        &unique,
+        |path| path,
+        options,
+        |path| {
+            let fits = CcdFits::new(path)?;
+            let image = fits.image_hdu(&options.image_hdu)?;
+            let mut frames = selected_frames(image, options, path)?;
+            if !by_frame {
+                frames.truncate(1);
+            }
+            let mut stats = vec![];
+            for frame in frames {
+                let mut columns = vec![Column::new(path_col.into(), [path.to_string_lossy()])];
+                columns.extend(
+                    process_frame(image, frame, &image_options).map_err(|e| e.with_path(path))?,
+                );
+                if by_frame {
+                    columns.push(Column::new("Frame".into(), [frame.map(|f| f as u32)]));
+                }
+                stats.push(DataFrame::new(columns)?.lazy());
+            }
+            Ok(stats)
+        },
+    )?;
+    let frames: Vec<LazyFrame> = stats.into_iter().flatten().collect();
+    if frames.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+    let stats = concat_lf_diagonal(frames, UnionArgs::default())?;
+    let stats = relative_beam_offsets(stats, &options.image)?.collect()?;
+
+    let mut keys = vec![col(path_col)];
+    if by_frame {
+        keys.push(col("Frame"));
+    }
+    let replaced: Vec<String> = stats
+        .get_column_names()
+        .into_iter()
+        .filter(|name| name.as_str() != path_col && name.as_str() != "Frame")
+        .filter(|name| df.schema().contains(name))
+        .map(|name| name.to_string())
         .collect();
+    Ok(df
+        .clone()
+        .lazy()
+        .drop(replaced)
+        .join(
+            stats.lazy(),
+            keys.clone(),
+            keys,
+            JoinArgs {
+                join_nulls: true,
+                ..JoinArgs::new(JoinType::Left)
+            },
+        )
+        .collect()?)
+}
+
+// ================== Image Handles ==================
+
+/// `Image Handle` of a frame, see `ImageOptions::image_handle`.
+fn image_handle(
+    path: &Path,
+    image: &ImageHDU,
+    hdu: usize,
+    frame: Option<usize>,
+) -> Result<Column, FitsLoaderError> {
+    let shape = image.data.get_shape();
+    let fields = [
+        Column::new(
+            "Source Path".into(),
+            [display_path(&fs::canonicalize(path)?)],
+        ),
+        Column::new("HDU".into(), [hdu as u32]),
+        Column::new("Frame".into(), [frame.map(|frame| frame as u32)]),
+        // astrors gives `[NAXIS1, NAXIS2]`, see `crate::io::frame_pixels`.
+        Column::new("Rows".into(), [shape[1] as u32]),
+        Column::new("Cols".into(), [shape[0] as u32]),
+    ];
+    Ok(
+        StructChunked::from_columns("Image Handle".into(), 1, &fields)?
+            .into_series()
+            .into(),
+    )
+}
+
+/// Reads the image referenced by the `Image Handle` of row `row` of `df`, see
+/// `ImageOptions::image_handle`.
+///
+/// The pixels are those of `crate::io::frame_pixels`, with `BZERO` applied but
+/// none of the corrections of `ImageOptions`. A file whose image no longer has
+/// the shape it had at load time fails with `ImageShapeMismatch`.
+pub fn materialize_image(df: &DataFrame, row: usize) -> Result<Array2<f64>, FitsLoaderError> {
+    if row >= df.height() {
+        return Err(FitsLoaderError::Other(format!(
+            "Row {} out of range for {} rows",
+            row,
+            df.height()
+        )));
+    }
+    let handle = df
+        .column("Image Handle")
+        .map_err(|_| FitsLoaderError::MissingColumn("Image Handle".into()))?
+        .as_materialized_series()
+        .struct_()?
+        .clone();
+    let field = |name: &str| handle.field_by_name(name);
+    let missing = || FitsLoaderError::Other(format!("No image handle in row {}", row));
+    let path = field("Source Path")?
+        .str()?
+        .get(row)
+        .ok_or_else(missing)?
+        .to_string();
+    let hdu = field("HDU")?.u32()?.get(row).ok_or_else(missing)? as usize;
+    let frame = field("Frame")?.u32()?.get(row).map(|frame| frame as usize);
+    let rows = field("Rows")?.u32()?.get(row).ok_or_else(missing)? as usize;
+    let cols = field("Cols")?.u32()?.get(row).ok_or_else(missing)? as usize;
+
+    let fits = CcdFits::new(&path)?;
+    let image = fits.image_hdu(&ImageHduSelection::Index(hdu))?;
+    let pixels = frame_pixels(image, frame).map_err(|e| e.with_path(Path::new(&path)))?;
+    if pixels.dim() != (rows, cols) {
+        return Err(FitsLoaderError::ImageShapeMismatch {
+            path,
+            expected: (rows, cols),
+            found: pixels.dim(),
+        });
+    }
+    Ok(pixels)
+}
+
+// ================== Lazy Scans ==================
+
+/// Lazy counterpart of `read_experiment`, see `scan_experiment_with_options`.
+pub fn scan_experiment(
+    dir: &str,
+    header_items: &[HeaderValue],
+) -> Result<LazyFrame, FitsLoaderError> {
+    scan_experiment_with_options(dir, header_items, &LoaderOptions::default())
+}
+
+/// Same as `scan_experiment`, with explicit `LoaderOptions`.
+///
+/// The rows and columns are those of `read_experiment_with_options`. Creating
+/// the scan loads the metadata and image statistics of every file, decoding one
+/// image at a time and dropping it as `ImageOptions::drop_image` does, so a
+/// query that does not project `Raw`, e.g. `.select(stats).collect()`, is
+/// answered from those columns without holding any image in memory. Only a
+/// query that needs `Raw` reads the images again, with the pixel corrections
+/// but none of the statistics, which it takes from the scan.
+///
+/// The dtype of `Raw` in the schema is that of the first file. Collecting `Raw`
+/// fails when the images of the experiment end up with another dtype, e.g.
+/// after promoting frames of mixed dtypes, load those with `read_experiment`.
+pub fn scan_experiment_with_options(
+    dir: &str,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+) -> Result<LazyFrame, FitsLoaderError> {
+    let stats_options = LoaderOptions {
+        image: ImageOptions {
+            drop_image: true,
+            ..options.image.clone()
+        },
+        ..options.clone()
+    };
+    let stats = read_experiment_with_options(dir, header_items, &stats_options)?;
+    let mut schema = stats.schema();
+    if !options.image.drop_image && !options.image.image_handle {
+        let first = fits_files(dir)?
+            .into_iter()
+            .next()
+            .ok_or(FitsLoaderError::NoData)?;
+        let frame = read_fits_with_options(first, header_items, options)?;
+        // `Raw` leads the image columns, which follow the calculated domains.
+        let next = frame
+            .get_column_index("Raw")
+            .and_then(|index| frame.get_column_names().get(index + 1).copied());
+        let index = next
+            .and_then(|name| schema.index_of(name))
+            .unwrap_or(schema.len());
+        schema.insert_at_index(index, "Raw".into(), frame.column("Raw")?.dtype().clone())?;
+    }
+    let scan = ExperimentScan {
+        dir: dir.to_string(),
+        header_items: header_items.to_vec(),
+        options: image_only_options(options),
+        schema: Arc::new(schema),
+        stats,
+    };
+    // Without any projection, the optimizer of this polars version panics on an
+    // anonymous scan, so every column is projected explicitly.
+    Ok(LazyFrame::anonymous_scan(
+        Arc::new(scan),
+        ScanArgsAnonymous {
+            name: "FITS EXPERIMENT SCAN",
+            ..Default::default()
+        },
+    )?
+    .select([all()]))
+}
+
+/// `options` for loading just the `Raw` images of a scan: the same files, rows
+/// and pixel corrections, without the statistics, computed columns and
+/// projection, which the scan already holds.
+fn image_only_options(options: &LoaderOptions) -> LoaderOptions {
+    let image = ImageOptions {
+        aperture_radius: None,
+        background_annulus: None,
+        snr: false,
+        total_counts: false,
+        hdu_info: false,
+        thumbnail_size: None,
+        #[cfg(feature = "png")]
+        png_thumbnail: None,
+        peak_fit: None,
+        fwhm: None,
+        peak_detection: None,
+        quality: None,
+        integration: None,
+        roi_ratio: None,
+        specular_bands: None,
+        named_rois: vec![],
+        radial_profile: None,
+        beam_offset: None,
+        ..options.image.clone()
+    };
+    LoaderOptions {
+        image,
+        q_map: None,
+        normalization: None,
+        expressions: vec![],
+        columns: None,
+        ..options.clone()
+    }
+}
+
+/// Source of `scan_experiment_with_options`, holding the image-free columns.
+struct ExperimentScan {
+    dir: String,
+    header_items: Vec<HeaderValue>,
+    /// Options of the load reading `Raw`, see `image_only_options`.
+    options: LoaderOptions,
+    schema: SchemaRef,
+    stats: DataFrame,
+}
+
+impl AnonymousScan for ExperimentScan {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn scan(&self, scan_opts: AnonymousScanArgs) -> PolarsResult<DataFrame> {
+        let names: Vec<PlSmallStr> = match &scan_opts.with_columns {
+            Some(columns) => columns.to_vec(),
+            None => self.schema.iter_names().cloned().collect(),
+        };
+        if !names.iter().any(|name| name == "Raw") {
+            return self.stats.select(names);
+        }
+        let images = read_experiment_with_options(&self.dir, &self.header_items, &self.options)
+            .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+        let raw = images.column("Raw")?;
+        let (expected, found) = (self.schema.get("Raw"), raw.dtype());
+        if expected != Some(found) {
+            return Err(PolarsError::SchemaMismatch(
+                format!("Raw is {} where the scan expected {:?}", found, expected).into(),
+            ));
+        }
+        // Both loads keep the rows of the same files in the same order.
+        if raw.len() != self.stats.height() {
+            return Err(PolarsError::ShapeMismatch(
+                "the directory changed since the scan was created".into(),
+            ));
+        }
+        let mut df = self.stats.clone();
+        df.with_column(raw.clone())?;
+        df.select(names)
+    }
+
+    fn schema(&self, _infer_schema_length: Option<usize>) -> PolarsResult<SchemaRef> {
+        Ok(self.schema.clone())
+    }
+
+    fn allows_projection_pushdown(&self) -> bool {
+        true
+    }
+}
+
+// ================== Live Acquisition ==================
+
+/// Interval at which `watch_experiment` scans the directory between file
+/// system events.
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Calls `callback` with a single-row frame for every FITS file that appears in
+/// `dir` after the call, until the callback returns `ControlFlow::Break`.
+///
+/// Files present when the watch starts are not reported, load them with
+/// `read_experiment` first. A file that never loads is handed over as its
+/// error, see `WatchOptions::give_up_after`.
+pub fn watch_experiment<F>(
+    dir: &str,
+    header_items: &[HeaderValue],
+    callback: F,
+) -> Result<(), FitsLoaderError>
+where
+    F: FnMut(Result<DataFrame, FitsLoaderError>) -> ControlFlow<()>,
+{
+    watch_experiment_with_options(
+        dir,
+        header_items,
+        &LoaderOptions::default(),
+        &WatchOptions::default(),
+        callback,
+    )
+}
 
-    let combined_df = dataframes?
-        .into_par_iter()
-        .reduce_with(|acc, df| acc.vstack(&df).unwrap_or(DataFrame::empty()))
-        .ok_or(FitsLoaderError::NoData)?;
+/// Same as `watch_experiment`, with explicit `LoaderOptions` and `WatchOptions`.
+///
+/// The directory is scanned whenever a file system event arrives and at every
+/// `WatchOptions::interval` in between, so the watch also works on the network
+/// shares acquisition machines write to, where events from other machines do
+/// not arrive. A new file that does not load yet, e.g. because the detector is
+/// still writing its data unit, is retried on every scan until it does, so each
+/// file is reported once and complete. A file that fails without growing for
+/// `WatchOptions::give_up_after` is reported once as its error instead. Frames
+/// come in path order within a scan.
+pub fn watch_experiment_with_options<F>(
+    dir: &str,
+    header_items: &[HeaderValue],
+    options: &LoaderOptions,
+    watch: &WatchOptions,
+    mut callback: F,
+) -> Result<(), FitsLoaderError>
+where
+    F: FnMut(Result<DataFrame, FitsLoaderError>) -> ControlFlow<()>,
+{
+    let mut seen: BTreeSet<PathBuf> = fits_files(dir)?.into_iter().collect();
+    // Failing files by the size they last had and since when they have it.
+    let mut pending: HashMap<PathBuf, (u64, Instant)> = HashMap::new();
+    let (events, receiver) = std::sync::mpsc::channel();
+    // Without a subscription, e.g. past the inotify watch limit, only poll.
+    let _watcher = watch
+        .notify
+        .then(|| {
+            let mut watcher = notify::recommended_watcher(events).ok()?;
+            watcher
+                .watch(Path::new(dir), notify::RecursiveMode::NonRecursive)
+                .ok()?;
+            Some(watcher)
+        })
+        .flatten();
+    loop {
+        match receiver.recv_timeout(watch.interval) {
+            // One scan covers a burst of events, e.g. the writes of one file.
+            Ok(_) => while receiver.try_recv().is_ok() {},
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(watch.interval)
+            }
+        }
+        for path in fits_files(dir)? {
+            if seen.contains(&path) {
+                continue;
+            }
+            let result = read_multiple_fits_with_options(vec![path.clone()], header_items, options);
+            let result = match result {
+                Ok(df) => Ok(df),
+                Err(e) => {
+                    // Partially written files fail to load, they stay unseen until
+                    // they parse or stop growing for `give_up_after`.
+                    let size = fs::metadata(&path).map_or(0, |meta| meta.len());
+                    let (last_size, since) = pending
+                        .entry(path.clone())
+                        .or_insert((size, Instant::now()));
+                    if *last_size != size {
+                        *last_size = size;
+                        *since = Instant::now();
+                    }
+                    if since.elapsed() < watch.give_up_after {
+                        continue;
+                    }
+                    Err(e)
+                }
+            };
+            pending.remove(&path);
+            seen.insert(path);
+            if callback(result).is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Left-joins a companion scan log (CSV) onto a loaded experiment.
+///
+/// Values that are not stored in the FITS headers, e.g. EPICS channels logged by
+/// the beamline, can be merged by a key shared by both tables such as
+/// `"Frame Number"`. The key column of the log is cast to the dtype of the frame
+/// before joining.
+pub fn join_scan_log(
+    df: DataFrame,
+    csv_path: impl AsRef<Path>,
+    on: &str,
+) -> Result<DataFrame, FitsLoaderError> {
+    let key_dtype = df
+        .column(on)
+        .map_err(|_| FitsLoaderError::MissingColumn(on.to_string()))?
+        .dtype()
+        .clone();
 
-    Ok(add_calculated_domains(combined_df.lazy()))
+    let log = CsvReadOptions::default()
+        .with_has_header(true)
+        .try_into_reader_with_file_path(Some(csv_path.as_ref().to_path_buf()))?
+        .finish()?;
+    if log.column(on).is_err() {
+        return Err(FitsLoaderError::MissingColumn(format!(
+            "{} in {}",
+            on,
+            csv_path.as_ref().display()
+        )));
+    }
+
+    let joined = df
+        .lazy()
+        .join(
+            log.lazy().with_column(col(on).cast(key_dtype)),
+            [col(on)],
+            [col(on)],
+            JoinArgs {
+                join_nulls: true,
+                ..JoinArgs::new(JoinType::Left)
+            },
+        )
+        .collect()?;
+    Ok(joined)
 }
 
 pub fn _load() {
@@ -82,3 +2420,673 @@ pub fn _load() {
     // let data = read_experiment(test_path.into(), &ExperimentType::Xrr.get_keys()).unwrap();
     // println!("{:?}", data);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::image_from_column;
+    use crate::options::{ApertureCenter, I0Normalization, StringPadding};
+    use crate::test_util::{be_bytes, test_dir, u16_image, write_fits, Extension};
+
+    #[test]
+    fn braces_expand_nested_and_leave_unbalanced_groups() {
+        assert_eq!(
+            expand_braces("{Y6,PM6}_{refl,bg}_*.fits"),
+            [
+                "Y6_refl_*.fits",
+                "Y6_bg_*.fits",
+                "PM6_refl_*.fits",
+                "PM6_bg_*.fits"
+            ]
+        );
+        assert_eq!(expand_braces("a{b,c{d,e}}f"), ["abf", "acdf", "acef"]);
+        assert_eq!(expand_braces("a{,b}"), ["a", "ab"]);
+        assert_eq!(expand_braces("a{b,c"), ["a{b,c"]);
+        assert_eq!(expand_braces("a}b{c}d{e"), ["a}bcd{e"]);
+    }
+
+    #[test]
+    fn globs_match_classes_and_backtrack_over_stars() {
+        let cases = [
+            ("a*b*c", "aXbYbc", true),
+            ("a*b*c", "aXbYbd", false),
+            ("*.fits", "scan.fits", true),
+            ("*", "", true),
+            ("", "a", false),
+            ("film_?.fits", "film_1.fits", true),
+            ("film_?.fits", "film_12.fits", false),
+            ("[a-c]x", "bx", true),
+            ("[!a-c]x", "dx", true),
+            ("[!a-c]x", "bx", false),
+            ("[^a-c]x", "bx", false),
+            ("[]a]", "]", true),
+            // A class that is never closed is a literal `[`.
+            ("[ab", "[ab", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, text),
+                expected,
+                "{} on {}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn uppercase_extensions_are_fits_files() {
+        let dir = test_dir("uppercase_extension");
+        for name in ["a_00001.FITS", "b_00002.Fits", "c_00003.fits", "notes.txt"] {
+            write_fits(
+                &dir.join(name),
+                &[("EXPOSURE", "1.0")],
+                Some(u16_image(2, 2, &[1, 2, 3, 4])),
+            );
+        }
+        let df = read_experiment(dir.to_str().unwrap(), &[HeaderValue::Exposure]).unwrap();
+        assert_eq!(df.height(), 3);
+        let upper = dir.join("a_00001.FITS");
+        assert!(CcdFits::new(&upper).is_ok());
+        assert_eq!(
+            read_fits(upper, &[HeaderValue::Exposure]).unwrap().height(),
+            1
+        );
+    }
+
+    #[test]
+    fn missing_float_column_is_null_filled_as_float() {
+        let with = df!("Sample Theta [deg]" => [1.0, 2.0], "Frame Number" => [1u32, 2]).unwrap();
+        let without = df!("Frame Number" => [3u32]).unwrap();
+        let combined = combine_dataframes_with_alignment(with, without).unwrap();
+        let theta = combined.column("Sample Theta [deg]").unwrap();
+        assert_eq!(theta.dtype(), &DataType::Float64);
+        assert_eq!(
+            theta.f64().unwrap().into_iter().collect::<Vec<_>>(),
+            [Some(1.0), Some(2.0), None]
+        );
+
+        // The same with the frame lacking the column first.
+        let without = df!("Frame Number" => [3u32]).unwrap();
+        let with = df!("Frame Number" => [1u32], "Sample Theta [deg]" => [1.0]).unwrap();
+        let combined = combine_dataframes_with_alignment(without, with).unwrap();
+        assert_eq!(
+            combined.column("Sample Theta [deg]").unwrap().dtype(),
+            &DataType::Float64
+        );
+        assert_eq!(
+            combined.get_column_names(),
+            ["Frame Number", "Sample Theta [deg]"]
+        );
+    }
+
+    #[test]
+    fn null_column_takes_the_dtype_of_its_counterpart() {
+        let unknown =
+            DataFrame::new(vec![Column::full_null("Note".into(), 1, &DataType::Null)]).unwrap();
+        let text = df!("Note" => ["dark"]).unwrap();
+        let combined = combine_dataframes_with_alignment(unknown, text).unwrap();
+        assert_eq!(combined.column("Note").unwrap().dtype(), &DataType::String);
+    }
+
+    #[test]
+    fn float_cards_missing_from_a_file_load_as_null_floats() {
+        let dir = test_dir("missing_float_card");
+        write_fits(
+            &dir.join("film_00001.fits"),
+            &[("EXPOSURE", "1.0")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        write_fits(
+            &dir.join("film_00002.fits"),
+            &[("EXPOSURE", "1.0"), ("TEMP", "21.5"), ("TEMP_CCD", "-40.5")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        // The first file gets a `Null` column for `TEMP` and none for the wildcard.
+        let items = [HeaderValue::from("TEMP"), HeaderValue::from("TEMP_*")];
+        let df = read_experiment(dir.to_str().unwrap(), &items).unwrap();
+        for (name, value) in [("TEMP", 21.5), ("TEMP_CCD", -40.5)] {
+            let column = df.column(name).unwrap();
+            assert_eq!(column.dtype(), &DataType::Float64, "{}", name);
+            let values: Vec<Option<f64>> = column.f64().unwrap().into_iter().collect();
+            assert_eq!(values, [None, Some(value)], "{}", name);
+        }
+    }
+
+    #[test]
+    fn numeric_and_string_header_items_load_together() {
+        let dir = test_dir("mixed_header_items");
+        let cards = [
+            ("EXPOSURE", "0.5"),
+            ("Proposal", "'P12345'"),
+            ("SHUTTER", "T"),
+        ];
+        write_fits(
+            &dir.join("film_00001.fits"),
+            &cards,
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        let items: Vec<HeaderValue> = ["EXPOSURE", "Proposal", "SHUTTER"]
+            .into_iter()
+            .map(HeaderValue::from)
+            .collect();
+        let df = read_experiment(dir.to_str().unwrap(), &items).unwrap();
+        assert_eq!(
+            df.column("EXPOSURE [s]").unwrap().f64().unwrap().get(0),
+            Some(0.5)
+        );
+        assert_eq!(
+            df.column("Proposal").unwrap().str().unwrap().get(0),
+            Some("P12345")
+        );
+        assert_eq!(
+            df.column("SHUTTER").unwrap().bool().unwrap().get(0),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn large_integer_cards_load_as_exact_int64() {
+        let dir = test_dir("large_integer_card");
+        write_fits(
+            &dir.join("film_00001.fits"),
+            &[("FRAMECNT", "9007199254740993")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        let df = read_experiment(dir.to_str().unwrap(), &[HeaderValue::from("FRAMECNT")]).unwrap();
+        let count = df.column("FRAMECNT").unwrap();
+        assert_eq!(count.dtype(), &DataType::Int64);
+        // 2^53 + 1, which a `Float64` column would round to 2^53.
+        assert_eq!(count.i64().unwrap().get(0), Some(9_007_199_254_740_993));
+    }
+
+    #[test]
+    fn mixed_integer_and_float_frames_promote_raw_to_float() {
+        let dir = test_dir("mixed_image_dtypes");
+        write_fits(
+            &dir.join("film_00001.fits"),
+            &[("EXPOSURE", "1.0")],
+            Some(u16_image(2, 2, &[1, 2, 3, 40000])),
+        );
+        let float = Extension {
+            bitpix: -32,
+            axes: vec![2, 2],
+            cards: vec![],
+            data: be_bytes(&[0.5f32, 1.5, 2.5, 3.5], f32::to_be_bytes),
+        };
+        write_fits(
+            &dir.join("film_00002.fits"),
+            &[("EXPOSURE", "1.0")],
+            Some(float),
+        );
+        let df = read_experiment(dir.to_str().unwrap(), &[HeaderValue::Exposure]).unwrap();
+        let raw = df.column("Raw").unwrap();
+        let pixels = |row: usize| -> Vec<f64> {
+            let cell = raw.as_materialized_series().slice(row as i64, 1);
+            let values = cell.explode().unwrap().explode().unwrap();
+            values.f64().unwrap().into_no_null_iter().collect()
+        };
+        assert_eq!(pixels(0), [1.0, 2.0, 3.0, 40000.0]);
+        assert_eq!(pixels(1), [0.5, 1.5, 2.5, 3.5]);
+        let float_frame =
+            DataType::Array(Box::new(DataType::Array(Box::new(DataType::Float64), 2)), 2);
+        assert_eq!(raw.dtype(), &float_frame);
+    }
+
+    #[test]
+    fn header_only_files_are_indexed_with_a_null_image() {
+        let dir = test_dir("header_only_file");
+        let log = dir.join("log_00001.fits");
+        write_fits(&log, &[("EXPOSURE", "2.0")], None);
+        write_fits(
+            &dir.join("film_00002.fits"),
+            &[("EXPOSURE", "1.0")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        assert!(matches!(
+            read_fits(log.clone(), &[HeaderValue::Exposure]),
+            Err(FitsLoaderError::NoData)
+        ));
+
+        let options = LoaderOptions {
+            header_only_files: true,
+            ..Default::default()
+        };
+        let items = [HeaderValue::Exposure];
+        let df = read_fits_with_options(log, &items, &options).unwrap();
+        assert_eq!(df.height(), 1);
+        assert_eq!(df.column("Raw").unwrap().null_count(), 1);
+
+        let df = read_experiment_with_options(dir.to_str().unwrap(), &items, &options).unwrap();
+        assert_eq!(df.height(), 2);
+        let exposure: Vec<_> = df
+            .column("EXPOSURE [s]")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        let raw = df.column("Raw").unwrap();
+        let log_row = exposure.iter().position(|&e| e == Some(2.0)).unwrap();
+        assert!(raw.get(log_row).unwrap().is_null());
+        assert!(!raw.get(1 - log_row).unwrap().is_null());
+    }
+
+    #[test]
+    fn padded_string_cards_are_trimmed_unless_preserved() {
+        let dir = test_dir("padded_card");
+        let path = dir.join("film_00001.fits");
+        write_fits(
+            &path,
+            &[("OBJECT", "'  sample A    '")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        let object = |padding: StringPadding| {
+            let options = LoaderOptions {
+                string_padding: padding,
+                ..Default::default()
+            };
+            let df = read_fits_with_options(path.clone(), &[HeaderValue::from("OBJECT")], &options)
+                .unwrap();
+            let value = df.column("OBJECT").unwrap().str().unwrap().get(0);
+            value.unwrap().to_string()
+        };
+        assert_eq!(object(StringPadding::default()), "sample A");
+        assert_eq!(object(StringPadding::Trim), "sample A");
+        assert_eq!(object(StringPadding::TrimEnd), "  sample A");
+        assert_eq!(object(StringPadding::Preserve), "  sample A    ");
+    }
+
+    #[test]
+    fn empty_loads_give_custom_items_the_string_dtype_of_their_cards() {
+        let dir = test_dir("empty_custom");
+        let items = [HeaderValue::from("OBJECT")];
+        let options = LoaderOptions {
+            allow_empty: true,
+            ..Default::default()
+        };
+        let empty = read_experiment_with_options(dir.to_str().unwrap(), &items, &options).unwrap();
+        assert_eq!(empty.height(), 0);
+        write_fits(
+            &dir.join("film_00001.fits"),
+            &[("OBJECT", "'sample A'")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        let full = read_experiment_with_options(dir.to_str().unwrap(), &items, &options).unwrap();
+        let dtype = |df: &DataFrame| df.column("OBJECT").unwrap().dtype().clone();
+        assert_eq!(dtype(&empty), DataType::String);
+        assert_eq!(dtype(&empty), dtype(&full));
+    }
+
+    #[test]
+    fn naxis_cards_disagreeing_with_the_data_unit_are_an_error() {
+        let dir = test_dir("naxis_mismatch");
+        let path = dir.join("film_00001.fits");
+        // Two rows of 1440 16-bit pixels fill two blocks, NAXIS2 claims one.
+        let mut image = u16_image(2, 1440, &[7; 2880]);
+        image.axes = vec![1440, 1];
+        write_fits(&path, &[("EXPOSURE", "1.0")], Some(image));
+        match read_fits(path.clone(), &[HeaderValue::Exposure]) {
+            Err(FitsLoaderError::NaxisMismatch {
+                path: p,
+                header,
+                expected_bytes,
+                found_bytes,
+            }) => {
+                assert_eq!(p, path.display().to_string());
+                assert_eq!(header, [1440, 1]);
+                assert_eq!((expected_bytes, found_bytes), (2880, 5760));
+            }
+            other => panic!(
+                "expected NaxisMismatch, got {:?}",
+                other.map(|df| df.shape())
+            ),
+        }
+
+        // Claiming more rows than the file holds reads as a truncated file.
+        let mut image = u16_image(2, 1440, &[7; 2880]);
+        image.axes = vec![1440, 3];
+        write_fits(&path, &[("EXPOSURE", "1.0")], Some(image));
+        let err = read_fits(path, &[HeaderValue::Exposure]).unwrap_err();
+        assert!(err.to_string().contains("truncated data unit"), "{}", err);
+    }
+
+    #[test]
+    fn image_normalization_scales_only_the_stored_pixels() {
+        let dir = test_dir("image_normalization");
+        let path = dir.join("film_00001.fits");
+        let mut values = [10u16; 25];
+        values[12] = 200;
+        write_fits(
+            &path,
+            &[("EXPOSURE", "1.0"), ("DIODE", "4.0")],
+            Some(u16_image(5, 5, &values)),
+        );
+        let mut options = LoaderOptions::default();
+        options.image.aperture_center = ApertureCenter::Fixed((2.0, 2.0));
+        options.image.aperture_radius = Some(1.0);
+        options.image.background_annulus = Some((1.5, 3.0));
+        options.image.snr = true;
+        let load = |options: &LoaderOptions| {
+            let df = read_fits_with_options(path.clone(), &[], options).unwrap();
+            let value = |name: &str| df.column(name).unwrap().f64().unwrap().get(0).unwrap();
+            let raw = image_from_column(df.column("Raw").unwrap(), 0).unwrap();
+            (
+                value("Net Counts"),
+                value("Net Counts Error"),
+                value("SNR"),
+                raw,
+            )
+        };
+        let (net, error, snr, raw) = load(&options);
+
+        for image in [false, true] {
+            options.normalize_i0 = Some(I0Normalization {
+                card: Some("DIODE".into()),
+                image,
+            });
+            let normalized = load(&options);
+            assert_eq!(normalized.0, net / 4.0);
+            // The Poisson error of the counts, divided like the counts.
+            assert_eq!(normalized.1, error / 4.0);
+            assert_eq!(normalized.2, snr);
+            let scale = if image { 4.0 } else { 1.0 };
+            assert_eq!(normalized.3, &raw / scale);
+        }
+    }
+
+    #[test]
+    fn typed_loaders_agree() {
+        let dir = test_dir("typed_loaders");
+        let paths: Vec<PathBuf> = ["dark_00001.fits", "scan_00002.fits", "scan_00003.fits"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        for path in &paths {
+            write_fits(
+                path,
+                &[("EXPOSURE", "1.0"), ("Proposal", "'P1'")],
+                Some(u16_image(2, 2, &[1, 2, 3, 4])),
+            );
+        }
+        let extra = [HeaderValue::from("Proposal")];
+        let d = dir.to_str().unwrap();
+        let all = read_experiment_typed(d, ExperimentType::Other, &extra).unwrap();
+        let listed = read_multiple_fits_typed(paths, ExperimentType::Other, &extra).unwrap();
+        let diff = crate::diff::diff_dataframes(&all, &listed).unwrap();
+        assert!(diff.is_empty(), "{:?}", diff);
+        let scans =
+            read_experiment_pattern_typed(d, &["scan_*"], ExperimentType::Other, &extra).unwrap();
+        assert_eq!(scans.height(), 2);
+        assert_eq!(scans.schema(), all.schema());
+        assert!(scans.column("Proposal").is_ok());
+    }
+
+    #[test]
+    fn missing_header_items_are_reported_unless_strict() {
+        let dir = test_dir("missing_header_items");
+        let first = dir.join("film_00001.fits");
+        write_fits(
+            &first,
+            &[("EXPOSURE", "1.0")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        write_fits(
+            &dir.join("film_00002.fits"),
+            &[("EXPOSURE", "1.0"), ("Proposal", "'P1'")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        let items = [
+            HeaderValue::SampleTheta,
+            HeaderValue::from("Proposal"),
+            HeaderValue::from("TYPO"),
+        ];
+        let d = dir.to_str().unwrap();
+        let (df, report) = read_experiment_with_report(d, &items, &Default::default()).unwrap();
+        assert_eq!(report.checked_file, Some(first.clone()));
+        assert_eq!(
+            report.missing_header_items,
+            ["Sample Theta", "Proposal", "TYPO"]
+        );
+        let theta = df.column("Sample Theta [deg]").unwrap();
+        assert_eq!((theta.dtype(), theta.null_count()), (&DataType::Float64, 2));
+        let proposal = df.column("Proposal").unwrap().str().unwrap();
+        assert_eq!(proposal.into_iter().collect::<Vec<_>>(), [None, Some("P1")]);
+        assert_eq!(df.column("TYPO").unwrap().null_count(), 2);
+
+        let strict = LoaderOptions {
+            strict_schema: true,
+            ..Default::default()
+        };
+        match read_experiment_with_options(d, &items, &strict) {
+            Err(FitsLoaderError::MissingHeaderItems { path, items }) => {
+                assert_eq!(path, display_path(&first));
+                assert_eq!(items, report.missing_header_items);
+            }
+            other => panic!(
+                "expected MissingHeaderItems, got {:?}",
+                other.map(|df| df.shape())
+            ),
+        }
+    }
+
+    #[test]
+    fn batches_go_to_the_sink_in_path_order() {
+        let dir = test_dir("batched_load");
+        let paths: Vec<PathBuf> = (1..=5)
+            .map(|i| dir.join(format!("film_{:05}.fits", i)))
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            let exposure = format!("{}.0", i + 1);
+            write_fits(
+                path,
+                &[("EXPOSURE", exposure.as_str())],
+                Some(u16_image(2, 2, &[1, 2, 3, i as u16])),
+            );
+        }
+        let options = LoaderOptions {
+            chunk_size: Some(2),
+            ..Default::default()
+        };
+        let items = [HeaderValue::Exposure];
+        let mut batches = vec![];
+        read_multiple_fits_batched(paths.clone(), &items, &options, |df| {
+            batches.push(df);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            batches.iter().map(DataFrame::height).collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+        let mut stacked = batches[0].clone();
+        for batch in &batches[1..] {
+            stacked.vstack_mut(batch).unwrap();
+        }
+        let whole = read_multiple_fits_with_options(paths.clone(), &items, &options).unwrap();
+        let diff = crate::diff::diff_dataframes(&whole, &stacked).unwrap();
+        assert!(diff.is_empty(), "{:?}", diff);
+
+        let err = read_multiple_fits_batched(paths, &items, &options, |_| {
+            Err(FitsLoaderError::InvalidConfig("disk full".into()))
+        });
+        assert!(matches!(err, Err(FitsLoaderError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn watch_reports_new_files_once_complete_and_gives_up_on_broken_ones() {
+        let dir = test_dir("watch_experiment");
+        write_fits(
+            &dir.join("film_00001.fits"),
+            &[("EXPOSURE", "1.0")],
+            Some(u16_image(2, 2, &[1, 2, 3, 4])),
+        );
+        let writer_dir = dir.clone();
+        let writer = std::thread::spawn(move || {
+            let staging = writer_dir.join("staging.tmp");
+            write_fits(
+                &staging,
+                &[("EXPOSURE", "2.0")],
+                Some(u16_image(2, 2, &[1, 2, 3, 4])),
+            );
+            let bytes = fs::read(&staging).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            // The frame arrives in two writes, the first ending in its header.
+            let frame = writer_dir.join("film_00002.fits");
+            fs::write(&frame, &bytes[..bytes.len() - 2880]).unwrap();
+            // A file cut off inside its header, which never loads.
+            fs::write(writer_dir.join("film_00003.fits"), &bytes[..2000]).unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            fs::write(&frame, &bytes).unwrap();
+        });
+        let watch = WatchOptions {
+            interval: Duration::from_millis(20),
+            give_up_after: Duration::from_millis(400),
+            ..Default::default()
+        };
+        let mut reported = vec![];
+        let d = dir.to_str().unwrap();
+        let items = [HeaderValue::Exposure];
+        watch_experiment_with_options(d, &items, &Default::default(), &watch, |result| {
+            reported.push(result.map(|df| {
+                let exposure = df.column("EXPOSURE [s]").unwrap().f64().unwrap().get(0);
+                exposure.unwrap()
+            }));
+            if reported.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+        writer.join().unwrap();
+        // The frame shows up complete, before the broken file is given up on.
+        assert_eq!(reported[0].as_ref().unwrap(), &2.0);
+        assert!(reported[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("film_00003"));
+    }
+
+    #[test]
+    fn exposure_options_follow_the_exposure_unit_override() {
+        let dir = test_dir("exposure_unit_override");
+        for (i, exposure) in ["0.0", "100.0", "100.0", "50.0"].into_iter().enumerate() {
+            write_fits(
+                &dir.join(format!("film_{:05}.fits", i + 1)),
+                &[("EXPOSURE", exposure)],
+                Some(u16_image(2, 2, &[1, 2, 3, 4])),
+            );
+        }
+        let unit_overrides = HashMap::from([("EXPOSURE".to_string(), "ms".to_string())]);
+        let options = LoaderOptions {
+            unit_overrides: unit_overrides.clone(),
+            ..Default::default()
+        };
+        let items = [HeaderValue::Exposure];
+        let d = dir.to_str().unwrap();
+        let all = read_experiment_with_options(d, &items, &options).unwrap();
+        let closed = find_closed_shutter_frames(&all, 0.0, &unit_overrides).unwrap();
+        assert_eq!(
+            closed.column("File Stem").unwrap().str().unwrap().get(0),
+            Some("film_00001")
+        );
+
+        let options = LoaderOptions {
+            min_exposure: Some(0.0),
+            exposure_outlier: Some(0.1),
+            ..options
+        };
+        let df = read_experiment_with_options(d, &items, &options).unwrap();
+        let outliers = df.column("Exposure Outlier").unwrap().bool().unwrap();
+        assert_eq!(
+            outliers.into_iter().collect::<Vec<_>>(),
+            [Some(false), Some(false), Some(true)]
+        );
+        assert!(matches!(
+            find_closed_shutter_frames(&all, 0.0, &HashMap::new()),
+            Err(FitsLoaderError::MissingColumn(name)) if name == "EXPOSURE [s]"
+        ));
+    }
+
+    #[test]
+    fn metadata_cache_reparses_only_changed_files() {
+        let dir = test_dir("metadata_cache");
+        let cache = dir.join("cache");
+        let write = |name: &str, exposure: &str| {
+            let path = dir.join(name);
+            write_fits(
+                &path,
+                &[("EXPOSURE", exposure)],
+                Some(u16_image(2, 2, &[1, 2, 3, 4])),
+            );
+            path
+        };
+        let (first, second) = (
+            write("film_00001.fits", "0.5"),
+            write("film_00002.fits", "1.0"),
+        );
+        let options = LoaderOptions {
+            metadata_cache: Some(cache.clone()),
+            ..Default::default()
+        };
+        let exposures = || {
+            let df = read_experiment_metadata_with_options(
+                dir.to_str().unwrap(),
+                &[HeaderValue::Exposure],
+                &options,
+            )
+            .unwrap();
+            let exposure = df.column("EXPOSURE [s]").unwrap().f64().unwrap().clone();
+            exposure.into_no_null_iter().collect::<Vec<_>>()
+        };
+        assert_eq!(exposures(), [0.5, 1.0]);
+        assert!(cache.join(crate::cache::CACHE_FILE).exists());
+        assert_eq!(exposures(), [0.5, 1.0]);
+
+        // Rewritten with the modification time restored, the first file looks
+        // unchanged and keeps its cached card. The second is parsed again.
+        let set_modified = |path: &Path, time| {
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(time).unwrap();
+        };
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        let unchanged = modified(&first);
+        write("film_00001.fits", "9.0");
+        set_modified(&first, unchanged);
+        let changed = modified(&second) + std::time::Duration::from_secs(1);
+        write("film_00002.fits", "2.0");
+        set_modified(&second, changed);
+        assert_eq!(exposures(), [0.5, 2.0]);
+
+        crate::cache::clear_metadata_cache(&cache).unwrap();
+        assert!(!cache.join(crate::cache::CACHE_FILE).exists());
+        assert_eq!(exposures(), [9.0, 2.0]);
+    }
+
+    #[test]
+    fn scan_stats_projection_matches_the_eager_load() {
+        let dir = test_dir("scan_stats");
+        for (i, exposure) in ["0.5", "1.0"].into_iter().enumerate() {
+            write_fits(
+                &dir.join(format!("film_{:05}.fits", i + 1)),
+                &[("EXPOSURE", exposure)],
+                Some(u16_image(2, 2, &[1, 2, 3, 4 + i as u16])),
+            );
+        }
+        let mut options = LoaderOptions::default();
+        options.image.total_counts = true;
+        let (path, items) = (dir.to_str().unwrap(), [HeaderValue::Exposure]);
+        let eager = read_experiment_with_options(path, &items, &options).unwrap();
+        let scan = scan_experiment_with_options(path, &items, &options).unwrap();
+        assert!(scan.clone().collect().unwrap().equals_missing(&eager));
+
+        // The statistics come from the scan, only `Raw` reads the files again.
+        fs::remove_dir_all(&dir).unwrap();
+        let stats = ["EXPOSURE [s]", "Total Counts"];
+        let projected = scan.clone().select(stats.map(col)).collect().unwrap();
+        assert!(projected.equals_missing(&eager.select(stats).unwrap()));
+        assert!(scan.select([col("Raw")]).collect().is_err());
+    }
+}
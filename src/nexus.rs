@@ -0,0 +1,227 @@
+use std::path::Path;
+
+use hdf5_metno::types::VarLenUnicode;
+use hdf5_metno::{File, Group, Location};
+use ndarray::{Array1, Array3};
+use polars::prelude::*;
+
+use crate::enums::HeaderValue;
+use crate::errors::FitsLoaderError;
+use crate::io::{image_from_column, split_unit};
+
+// ================== NeXus Export ==================
+// Writes a loaded experiment into a minimal NXentry/NXdata layout:
+//
+// entry (NXentry)
+// ├── data (NXdata)            signal `data`, the image stack, axis `Q` if present
+// ├── sample (NXsample)        rotation_angle
+// ├── instrument (NXinstrument)
+// │   ├── detector (NXdetector)           polar_angle, count_time, x/y_pixel_size, distance
+// │   ├── monochromator (NXmonochromator) energy
+// │   ├── source (NXsource)               current
+// │   ├── beam (NXbeam)                   incident_polarization
+// │   └── exit_slit (NXslit)              x_gap
+// └── pyref (NXcollection)     every other numeric column under its DataFrame name
+
+/// Standard NeXus location of the recognized header values, as
+/// `(header value, group path, NX class of the last group, field)`.
+fn nexus_fields() -> Vec<(HeaderValue, &'static str, &'static str, &'static str)> {
+    vec![
+        (
+            HeaderValue::SampleTheta,
+            "sample",
+            "NXsample",
+            "rotation_angle",
+        ),
+        (
+            HeaderValue::CCDTheta,
+            "instrument/detector",
+            "NXdetector",
+            "polar_angle",
+        ),
+        (
+            HeaderValue::Exposure,
+            "instrument/detector",
+            "NXdetector",
+            "count_time",
+        ),
+        (
+            HeaderValue::PixelSize,
+            "instrument/detector",
+            "NXdetector",
+            "x_pixel_size",
+        ),
+        (
+            HeaderValue::PixelSize,
+            "instrument/detector",
+            "NXdetector",
+            "y_pixel_size",
+        ),
+        (
+            HeaderValue::SampleDetectorDistance,
+            "instrument/detector",
+            "NXdetector",
+            "distance",
+        ),
+        (
+            HeaderValue::BeamlineEnergy,
+            "instrument/monochromator",
+            "NXmonochromator",
+            "energy",
+        ),
+        (
+            HeaderValue::BeamCurrent,
+            "instrument/source",
+            "NXsource",
+            "current",
+        ),
+        (
+            HeaderValue::EPUPolarization,
+            "instrument/beam",
+            "NXbeam",
+            "incident_polarization",
+        ),
+        (
+            HeaderValue::HorizontalExitSlitSize,
+            "instrument/exit_slit",
+            "NXslit",
+            "x_gap",
+        ),
+    ]
+}
+
+fn string(value: &str) -> Result<VarLenUnicode, FitsLoaderError> {
+    value
+        .parse()
+        .map_err(|e: hdf5_metno::types::StringError| FitsLoaderError::Other(e.to_string()))
+}
+
+fn set_attr(location: &Location, name: &str, value: &str) -> Result<(), FitsLoaderError> {
+    location
+        .new_attr::<VarLenUnicode>()
+        .create(name)?
+        .write_scalar(&string(value)?)?;
+    Ok(())
+}
+
+/// Opens `path` below `parent`, creating every missing group with `NX_class`
+/// set, intermediate groups being `NXinstrument`.
+fn nx_group(parent: &Group, path: &str, class: &str) -> Result<Group, FitsLoaderError> {
+    let mut group = parent.clone();
+    let parts: Vec<&str> = path.split('/').collect();
+    for (i, part) in parts.iter().enumerate() {
+        group = if group.link_exists(part) {
+            group.group(part)?
+        } else {
+            let created = group.create_group(part)?;
+            let class = if i + 1 == parts.len() {
+                class
+            } else {
+                "NXinstrument"
+            };
+            set_attr(&created, "NX_class", class)?;
+            created
+        };
+    }
+    Ok(group)
+}
+
+fn write_field(
+    group: &Group,
+    name: &str,
+    values: &Array1<f64>,
+    unit: Option<&str>,
+) -> Result<(), FitsLoaderError> {
+    let dataset = group.new_dataset_builder().with_data(values).create(name)?;
+    if let Some(unit) = unit {
+        set_attr(&dataset, "units", unit)?;
+    }
+    Ok(())
+}
+
+fn float_values(column: &Column) -> Result<Array1<f64>, FitsLoaderError> {
+    let values = column.cast(&DataType::Float64)?;
+    Ok(values
+        .f64()?
+        .into_iter()
+        .map(|v| v.unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// Writes the image stack in `image_col` and the metadata columns of `df` to a
+/// NeXus (HDF5) file, see the module comment for the layout.
+///
+/// Recognized header values are stored in their standard NeXus fields with the
+/// unit taken from the column name, every other numeric column is kept in the
+/// `pyref` collection. All images must share the same dimensions.
+pub fn write_nexus(
+    df: &DataFrame,
+    image_col: &str,
+    path: impl AsRef<Path>,
+) -> Result<(), FitsLoaderError> {
+    let path = path.as_ref();
+    let images = df.column(image_col)?;
+    let mut stack: Option<Array3<f64>> = None;
+    for row in 0..df.height() {
+        let image = image_from_column(images, row)?;
+        let stack = stack.get_or_insert_with(|| {
+            let (rows, cols) = image.dim();
+            Array3::zeros((df.height(), rows, cols))
+        });
+        let expected = (stack.dim().1, stack.dim().2);
+        if image.dim() != expected {
+            return Err(FitsLoaderError::ImageShapeMismatch {
+                path: path.display().to_string(),
+                expected,
+                found: image.dim(),
+            });
+        }
+        stack.index_axis_mut(ndarray::Axis(0), row).assign(&image);
+    }
+    let stack = stack.ok_or(FitsLoaderError::NoData)?;
+
+    let file = File::create(path)?;
+    let entry = nx_group(&file, "entry", "NXentry")?;
+    let data = nx_group(&entry, "data", "NXdata")?;
+    let signal = data
+        .new_dataset_builder()
+        .with_data(&stack)
+        .create("data")?;
+    set_attr(&signal, "units", "counts")?;
+    set_attr(&data, "signal", "data")?;
+
+    let mut written = vec![image_col.to_string()];
+    if let Ok(q) = df.column("Q [Å⁻¹]") {
+        write_field(&data, "Q", &float_values(q)?, Some("1/angstrom"))?;
+        set_attr(&data, "axes", "Q")?;
+        written.push(q.name().to_string());
+    }
+
+    for (key, group, class, field) in nexus_fields() {
+        let column = df
+            .get_columns()
+            .iter()
+            .find(|c| split_unit(c.name()).0 == key.hdu());
+        if let Some(column) = column {
+            let group = nx_group(&entry, group, class)?;
+            let unit = split_unit(column.name()).1;
+            write_field(&group, field, &float_values(column)?, unit)?;
+            written.push(column.name().to_string());
+        }
+    }
+
+    let extra = nx_group(&entry, "pyref", "NXcollection")?;
+    for column in df.get_columns() {
+        let name = column.name().as_str();
+        if written.iter().any(|w| w == name) || !column.dtype().is_numeric() {
+            continue;
+        }
+        write_field(
+            &extra,
+            &name.replace('/', "_"),
+            &float_values(column)?,
+            split_unit(name).1,
+        )?;
+    }
+    Ok(())
+}
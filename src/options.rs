@@ -0,0 +1,1111 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ndarray::Array2;
+use polars::prelude::{lit, DataType, Expr};
+
+use crate::enums::{ExperimentType, HeaderValue};
+use crate::errors::FitsLoaderError;
+use crate::image::{NamedRoi, Roi};
+
+/// Options controlling how FITS files are turned into DataFrame rows.
+///
+/// The plain `read_*` loaders use `LoaderOptions::default()`, the `*_with_options`
+/// variants accept a customised set, e.g.
+///
+/// ```rust
+/// use pyref_core::options::LoaderOptions;
+///
+/// let mut options = LoaderOptions::default();
+/// options
+///     .unit_overrides
+///     .insert("Higher Order Suppressor".into(), "counts".into());
+/// ```
+#[derive(Clone, Debug)]
+pub struct LoaderOptions {
+    /// Keyword to unit overrides, consulted before the built-in `HeaderValue::unit()`
+    /// table. Keys without a built-in unit (e.g. when loading every card) also pick
+    /// up their unit from here.
+    pub unit_overrides: HashMap<String, String>,
+    /// Keywords whose card holds a delimited list (e.g. several ROI definitions),
+    /// mapped to the delimiter. These are emitted as list columns instead of a
+    /// single value; other strings are never split.
+    pub list_cards: HashMap<String, char>,
+    /// Additional card names for a keyword, tried after the built-in
+    /// `HeaderValue::candidates`, e.g. `"EXPOSURE" => ["EXP_TIME"]`. The column
+    /// keeps the keyword's name whichever card it was read from.
+    pub card_aliases: HashMap<String, Vec<String>>,
+    /// What happens to the blanks around string card values, which FITS pads to
+    /// a fixed width. Trimmed on both sides by default, so values join and
+    /// compare as typed.
+    pub string_padding: StringPadding,
+    /// Emit the `COMMENT` and `HISTORY` cards of the primary header as the
+    /// newline-joined `Comments` and `History` columns, see
+    /// `crate::io::process_commentary`.
+    pub commentary: bool,
+    /// Emit every keyword card of the header, the one picked by `metadata_hdu`,
+    /// as a JSON object of keyword to value in the `Header JSON` column, for
+    /// archiving the complete metadata of a frame. It repeats the whole header
+    /// on every row, so frames grow accordingly.
+    pub header_json: bool,
+    /// Error instead of null-filling when a file introduces columns that the
+    /// first file (in path order) does not have, e.g. from a typo'd header key.
+    /// Header items missing from a file are an error as well, instead of null
+    /// in its row and, when the first file lacks them, listed in the
+    /// `crate::loader::LoadReport`.
+    pub strict_schema: bool,
+    /// Verify the `CHECKSUM`/`DATASUM` cards of each file before parsing it. This
+    /// reads every file twice, so it is off by default.
+    pub verify_checksums: bool,
+    /// Stop at the first file that fails to load and return its error wrapped in
+    /// `FitsLoaderError::File`, which names the file. No further file is started
+    /// once one has failed, and the reported failure is the first in path order
+    /// among the files read, so a CI check of a dataset fails the same way on
+    /// every run. The header items are then checked on the first file even when
+    /// it cannot be opened, instead of on the first file that opens.
+    pub fail_fast: bool,
+    /// How multi-file loads spread over the thread pool, see `Parallelism`.
+    pub parallelism: Parallelism,
+    /// Load only the first file, in path order, of every group of files with
+    /// byte-identical data, see `crate::loader::find_duplicate_files`, which
+    /// also reports the groups. Every file is read once more for hashing.
+    pub drop_duplicates: bool,
+    /// Skip files smaller than this many bytes, or not starting with the FITS
+    /// magic `SIMPLE  =`, before parsing them, e.g. zero-byte or partly copied
+    /// files left in a directory, instead of failing on them. The skipped
+    /// files are listed by `crate::loader::find_invalid_files`. A FITS file is
+    /// at least one 2880-byte block long.
+    pub min_file_size: Option<u64>,
+    /// Read multi-file loads in batches of this many files. Each batch is read in
+    /// parallel and combined before the next one starts, which bounds the memory
+    /// held by per-file frames and the thread pool for long path lists. The
+    /// result is the same as a single batch, the default. To also bound the
+    /// memory of the result, hand each batch to a sink with
+    /// `crate::loader::read_multiple_fits_batched`.
+    pub chunk_size: Option<usize>,
+    /// Index of the HDU whose header the metadata columns, `Comments`/`History`
+    /// and the beam current for normalization are read from, the primary HDU
+    /// (`0`) by default. This is independent of `image_hdu`; an index past the
+    /// last HDU of a file is an `HduOutOfRange` error. The q-map geometry is
+    /// always read from the primary header.
+    pub metadata_hdu: usize,
+    /// Directory of the on-disk metadata cache used by
+    /// `read_experiment_metadata_with_options`, off by default. The headers of
+    /// every loaded file are kept in `metadata.json` inside it, keyed by path and
+    /// reused while the file's modification time and size are unchanged. Delete
+    /// the file, or call `crate::cache::clear_metadata_cache`, to clear it.
+    pub metadata_cache: Option<PathBuf>,
+    /// Which HDU holds the science image.
+    pub image_hdu: ImageHduSelection,
+    /// Build the frame from several named image extensions instead of the one
+    /// picked by `image_hdu`, e.g. `[("ODD", Sum), ("EVEN", Sum)]` for a detector
+    /// writing interlaced fields. The first extension starts the frame and every
+    /// following one is combined into it with its `ExtensionOp`, the op of the
+    /// first entry is not used. The extensions must be 2D images of the same
+    /// shape; the frame is stored as `Float64` with `BZERO` applied, and `Image
+    /// HDU` and image handles name the first extension.
+    pub combine_extensions: Vec<(String, ExtensionOp)>,
+    /// Load files without any image, e.g. calibration or log files whose only
+    /// HDU is a header-only primary, as one row of their metadata and file
+    /// columns with a null `Raw`, instead of failing with `NoData`. Combined
+    /// with data files, their statistics columns come out null as well, so
+    /// header-only files can be indexed alongside the frames.
+    pub header_only_files: bool,
+    /// Return a frame without rows instead of `NoData` when a directory or
+    /// pattern matches no FITS file, e.g. for a loop polling a directory before
+    /// the first frame is written. It holds the header item, file and
+    /// calculated columns; image columns are only known once a file is read,
+    /// and `Custom` header items are `Null` typed. A missing directory is
+    /// still `NoData`.
+    pub allow_empty: bool,
+    /// Dark frame subtracted from every frame, see `ImageOptions::dark`. The
+    /// file is read once per load, before the files are loaded in parallel,
+    /// from the HDU picked by `image_hdu` and through the same deinterlacing
+    /// and overscan as the frames, its own overscan bias subtracted.
+    pub dark_frame: Option<PathBuf>,
+    /// Flat field every frame is divided by, see `ImageOptions::flat`. Read
+    /// once like `dark_frame`, dark-subtracted when both are set and normalized
+    /// to a mean of `1` over its finite pixels. Pixels at or below zero become
+    /// `NaN`, so the frames show no counts there.
+    pub flat_frame: Option<PathBuf>,
+    /// Options applied to the image HDU of every file.
+    pub image: ImageOptions,
+    /// Divide the integrated intensities (and optionally the image) of every frame
+    /// by its `Beam Current` card, see `CurrentNormalization`.
+    pub normalize_beam_current: Option<CurrentNormalization>,
+    /// Divide the integrated intensities (and optionally the image) of every frame
+    /// by its `I0` diode reading instead, see `I0Normalization`. Setting both
+    /// normalizations is an `InvalidConfig` error.
+    pub normalize_i0: Option<I0Normalization>,
+    /// Divide the integrated intensities by the transmission of the absorber in
+    /// the beam, see `FilterCorrection`.
+    pub filter_correction: Option<FilterCorrection>,
+    /// Divide the integrated intensities by a composed normalization factor, see
+    /// `Normalization`. Exclusive with the three options above, combining them
+    /// is an `InvalidConfig` error.
+    pub normalization: Option<Normalization>,
+    /// Slices to keep when the image HDU is a 3D cube. Every cube slice becomes a
+    /// row of its own with the slice index in `Frame`, all slices by default.
+    /// Ranges past the cube depth are an error, 2D images ignore this.
+    pub frames: Option<Range<usize>>,
+    /// Binary table holding one row per cube slice, e.g. the polarization of a
+    /// sweep within one file. Row `i` is joined onto the row of slice `i`: its
+    /// fields replace the header columns they name, `EPU Polarization` giving
+    /// `EPU Polarization [deg]`, and the others are added by their field name.
+    /// The table must have as many rows as the cube has slices; 2D images
+    /// ignore this.
+    pub slice_table: Option<TableHduSelection>,
+    /// Emit per-pixel `Qxy`/`Qz` maps aligned with the image. These are as large
+    /// as the image itself, so they are only computed on request.
+    pub q_map: Option<QMapOptions>,
+    /// Tag every row with the experiment type as the `Experiment Type` column, an
+    /// enum of the `ExperimentType::name` spellings. Frames loaded for different
+    /// types can then be concatenated without losing which reduction a row came
+    /// from. Set by `ExperimentType::default_options`.
+    pub experiment_type: Option<ExperimentType>,
+    /// Calibrate `Beamline Energy [eV]` before lambda and q are computed, see
+    /// `EnergyCalibration`. The reading as recorded is kept as `Beamline Energy
+    /// Raw [eV]`.
+    pub energy_calibration: Option<EnergyCalibration>,
+    /// Add `Sample Theta [rad]` and `CCD Theta [rad]` for the degree columns that
+    /// are loaded, which stay as they are. The radians follow the calculated
+    /// domains and come before `expressions`, which can refer to them.
+    pub angle_radians: bool,
+    /// Flag frames whose `EXPOSURE [s]` deviates from the median exposure of the
+    /// load by more than this fraction in the boolean `Exposure Outlier`
+    /// column, e.g. `0.1` for 10%. A short exposure within a scan usually means
+    /// a beam dump or shutter glitch. Needs `HeaderValue::Exposure` among the
+    /// header items; frames without an exposure get a null flag.
+    pub exposure_outlier: Option<f64>,
+    /// Drop frames whose `EXPOSURE [s]` is at or below this many seconds, or of
+    /// the unit `unit_overrides` gives the exposure, e.g. `Some(0.0)` for the
+    /// shutter-closed frames of an aborted scan. Off by default so intentional
+    /// dark frames are kept; the dropped rows are listed by
+    /// `crate::loader::find_closed_shutter_frames`. Frames are dropped before
+    /// anything is derived from the load as a whole, such as `exposure_outlier`
+    /// or beam offsets. Needs `HeaderValue::Exposure` among the header items;
+    /// frames without an exposure are kept.
+    pub min_exposure: Option<f64>,
+    /// Add the `DATE-OBS` of every frame as `Observation Time`, with its
+    /// sub-second part, and the cadence of time-resolved scans: the time since
+    /// the previous frame as `Frame Interval [s]` and the fraction of it spent
+    /// exposing, `EXPOSURE [s] / Frame Interval [s]`, as `Duty Cycle`.
+    ///
+    /// The previous frame is the one observed before, whatever the row order,
+    /// so dropped frames show up as longer intervals. The first frame and
+    /// frames without a `DATE-OBS` get null intervals. Needs
+    /// `HeaderValue::Exposure` among the header items.
+    pub frame_interval: bool,
+    /// Unit of the calculated momentum transfer, which also names the column:
+    /// `Q [Å⁻¹]` by default or `Q [nm⁻¹]`. The reductions in `reduce` expect
+    /// `Q [Å⁻¹]`.
+    pub q_unit: QUnit,
+    /// Add the canonicalized path of the originating file as `Source Path`, so
+    /// every row can be traced back to its file. On by default.
+    pub source_path: bool,
+    /// User-defined columns, e.g. `col("Beamline Energy [eV]") * lit(2)`. The
+    /// expressions run after the built-in lambda/q calculation, so they can refer
+    /// to `Q [Å⁻¹]`, and before the `columns` projection.
+    pub expressions: Vec<Expr>,
+    /// Keep only these output columns. The projection is applied to the lazy
+    /// frame after the calculated domains, so it may name e.g. `Q [Å⁻¹]`.
+    pub columns: Option<Vec<String>>,
+}
+
+impl Default for LoaderOptions {
+    fn default() -> Self {
+        LoaderOptions {
+            unit_overrides: HashMap::new(),
+            list_cards: HashMap::new(),
+            card_aliases: HashMap::new(),
+            string_padding: StringPadding::default(),
+            commentary: false,
+            header_json: false,
+            strict_schema: false,
+            verify_checksums: false,
+            fail_fast: false,
+            parallelism: Parallelism::default(),
+            drop_duplicates: false,
+            min_file_size: None,
+            chunk_size: None,
+            metadata_hdu: 0,
+            metadata_cache: None,
+            image_hdu: ImageHduSelection::default(),
+            combine_extensions: vec![],
+            header_only_files: false,
+            slice_table: None,
+            allow_empty: false,
+            dark_frame: None,
+            flat_frame: None,
+            image: ImageOptions::default(),
+            normalize_beam_current: None,
+            normalize_i0: None,
+            filter_correction: None,
+            normalization: None,
+            frames: None,
+            q_map: None,
+            experiment_type: None,
+            energy_calibration: None,
+            angle_radians: false,
+            exposure_outlier: None,
+            min_exposure: None,
+            frame_interval: false,
+            q_unit: QUnit::default(),
+            source_path: true,
+            expressions: vec![],
+            columns: None,
+        }
+    }
+}
+
+/// Treatment of the blanks around string card values, see
+/// `LoaderOptions::string_padding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StringPadding {
+    /// Strip leading and trailing blanks.
+    #[default]
+    Trim,
+    /// Strip only the trailing padding. The FITS standard treats leading blanks
+    /// as significant, so values indented on purpose keep their indentation.
+    TrimEnd,
+    /// Keep the value exactly as stored between the quotes.
+    Preserve,
+}
+
+impl StringPadding {
+    pub fn apply<'a>(&self, value: &'a str) -> &'a str {
+        match self {
+            StringPadding::Trim => value.trim(),
+            StringPadding::TrimEnd => value.trim_end(),
+            StringPadding::Preserve => value,
+        }
+    }
+}
+
+/// Unit of the momentum transfer `q` emitted by the calculated domains.
+///
+/// `1 Å⁻¹ = 10 nm⁻¹`, so a q in inverse nanometers is ten times the value in
+/// inverse angstroms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QUnit {
+    #[default]
+    InverseAngstrom,
+    InverseNanometer,
+}
+
+impl QUnit {
+    /// Name of the q column in this unit.
+    pub fn column(&self) -> &'static str {
+        match self {
+            QUnit::InverseAngstrom => "Q [Å⁻¹]",
+            QUnit::InverseNanometer => "Q [nm⁻¹]",
+        }
+    }
+
+    /// Factor taking a q in inverse angstroms to this unit.
+    pub fn per_inverse_angstrom(&self) -> f64 {
+        match self {
+            QUnit::InverseAngstrom => 1.0,
+            QUnit::InverseNanometer => 10.0,
+        }
+    }
+}
+
+/// Monitor normalization by the storage-ring current.
+///
+/// The integrated columns (`Total Counts`, `Aperture Counts`, `Background
+/// Counts`, `Net Counts`, `Net Counts Error`, `Intensity` and the two ROI
+/// counts of `roi_ratio`) become counts per mA. Frames whose current is
+/// missing, zero or negative are left as is, the `Current Normalized` column
+/// tells the two apart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CurrentNormalization {
+    /// Also divide the pixels, which promotes `Raw` to `Float64` like `gain`.
+    pub image: bool,
+}
+
+/// Monitor normalization by the incident flux measured by a beamline diode.
+///
+/// The integrated columns, as for `CurrentNormalization`, are divided by the
+/// monitor reading of the frame. Unlike the ring current, the diode also sees
+/// flux changes from the optics, e.g. with energy. Frames whose reading is
+/// missing, zero or negative are left as is, the `I0 Normalized` column tells
+/// the two apart. Request `HeaderValue::I0Monitor` to keep the reading itself.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct I0Normalization {
+    /// Card holding the monitor reading, for stations with several diodes. By
+    /// default the `HeaderValue::I0Monitor` candidates and their aliases.
+    pub card: Option<String>,
+    /// Also divide the pixels, which promotes `Raw` to `Float64` like `gain`.
+    pub image: bool,
+}
+
+/// Correction for absorber foils that attenuate the beam.
+///
+/// The integrated columns, as for `CurrentNormalization`, are divided by the
+/// transmission of the frame, which puts frames taken through different foils
+/// on one scale for stitching. The transmission is emitted as `Filter
+/// Transmission`, null for frames left as is because the card is missing, not
+/// in the table or the transmission is not in `(0, 1]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FilterCorrection {
+    /// Read the transmission from the `Filter Transmission` card.
+    #[default]
+    Transmission,
+    /// Read an absorber index from `card` and look its transmission up in
+    /// `transmissions`, index `0` being the first entry.
+    Table {
+        card: String,
+        transmissions: Vec<f64>,
+    },
+}
+
+/// Composed normalization of the integrated intensities, applied to the
+/// combined frame in one expression chain.
+///
+/// The integrated columns, as for `CurrentNormalization`, are divided by the
+/// product of the columns of `steps`, taken in order, which is emitted as
+/// `Normalization Factor`. Each step needs its column loaded through the
+/// matching header item; a frame where any of them is null, zero, negative or
+/// not finite gets a null factor. The image is not scaled, use
+/// `normalize_beam_current` or `normalize_i0` with `image` for that.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Normalization {
+    pub steps: Vec<NormalizationStep>,
+    pub output: NormalizationOutput,
+}
+
+/// Where a `Normalization` puts the normalized values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalizationOutput {
+    /// Keep the integrated columns as raw counts and add the normalized values
+    /// next to them under `NormalizationOutput::intensity_name`, e.g. `Net
+    /// Counts` and `Net Intensity`, so the data can be normalized differently
+    /// later. The normalized columns are null for frames without a factor.
+    #[default]
+    Paired,
+    /// Overwrite the integrated columns with the normalized values, leaving
+    /// frames without a factor as they are.
+    Replace,
+}
+
+impl NormalizationOutput {
+    /// Name of the normalized counterpart of the integrated column `name`:
+    /// `Counts` becomes `Intensity`, and `Intensity` itself `Normalized
+    /// Intensity`.
+    pub fn intensity_name(name: &str) -> String {
+        if name.contains("Counts") {
+            name.replace("Counts", "Intensity")
+        } else {
+            format!("Normalized {}", name)
+        }
+    }
+}
+
+/// A factor of a `Normalization`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalizationStep {
+    /// `EXPOSURE [s]`, giving counts per second.
+    Exposure,
+    /// `Beam Current [mA]`, giving counts per mA.
+    BeamCurrent,
+    /// The diode reading `I0 Monitor`.
+    I0,
+    /// The absorber transmission `Filter Transmission`, read from the card;
+    /// transmission tables need `filter_correction`.
+    FilterTransmission,
+}
+
+impl NormalizationStep {
+    /// Column holding the factor of this step.
+    pub fn column(&self) -> &'static str {
+        match self {
+            NormalizationStep::Exposure => HeaderValue::Exposure.name(),
+            NormalizationStep::BeamCurrent => HeaderValue::BeamCurrent.name(),
+            NormalizationStep::I0 => HeaderValue::I0Monitor.name(),
+            NormalizationStep::FilterTransmission => HeaderValue::FilterTransmission.name(),
+        }
+    }
+}
+
+/// Calibration polynomial of the monochromator energy,
+/// `E = c[0] + c[1]·E_raw + c[2]·E_raw² + …` in eV, with the coefficients `c` in
+/// ascending powers of the recorded `E_raw`. `[0.0, 1.0]` is the identity,
+/// `[-1.2, 1.0]` removes a constant offset of 1.2 eV.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnergyCalibration {
+    pub coefficients: Vec<f64>,
+}
+
+impl EnergyCalibration {
+    pub fn new(coefficients: Vec<f64>) -> Self {
+        EnergyCalibration { coefficients }
+    }
+
+    /// Reads the coefficients of a run from a CSV file, in ascending powers and
+    /// separated by commas or line breaks. Blank lines, `#` comments and a
+    /// header line that is not numeric are skipped.
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, FitsLoaderError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let invalid = |reason: String| {
+            FitsLoaderError::InvalidConfig(format!("{}: {}", path.display(), reason))
+        };
+        let mut coefficients = vec![];
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for (i, line) in lines.enumerate() {
+            let values: Result<Vec<f64>, _> = line
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::parse)
+                .collect();
+            match values {
+                Ok(values) => coefficients.extend(values),
+                Err(_) if i == 0 => {}
+                Err(_) => return Err(invalid(format!("invalid coefficient in {:?}", line))),
+            }
+        }
+        if coefficients.is_empty() {
+            return Err(invalid("no calibration coefficients".into()));
+        }
+        Ok(EnergyCalibration { coefficients })
+    }
+
+    /// The calibrated energy of a recorded `raw` energy.
+    pub fn apply(&self, raw: f64) -> f64 {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |energy, c| energy * raw + c)
+    }
+
+    /// `apply` as an expression on the recorded energy `raw`.
+    pub(crate) fn expr(&self, raw: Expr) -> Expr {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(lit(0.0), |energy, &c| energy * raw.clone() + lit(c))
+    }
+}
+
+/// Options for the per-pixel q-map, see `crate::image::q_map`.
+#[derive(Clone, Debug, Default)]
+pub struct QMapOptions {
+    /// Restrict the maps to this region instead of the full frame.
+    pub roi: Option<Roi>,
+}
+
+/// Strategy for picking the image HDU out of a file.
+///
+/// A primary HDU holding data, e.g. a thumbnail next to the full frame in an
+/// extension, counts as an image at index `0`. Set `ImageOptions::hdu_info` to
+/// record the HDU picked for every frame as `Image HDU`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ImageHduSelection {
+    /// A fixed HDU index, e.g. the third HDU (`2`) of the detector layout.
+    Index(usize),
+    /// The image with the most pixels, the default, for files where the science
+    /// frame does not sit at a fixed position. Ties go to the later HDU.
+    #[default]
+    LargestImage,
+    /// The first image extension, or the primary HDU for files without one.
+    PreferExtension,
+    /// The primary HDU when it holds data, otherwise the first image extension.
+    PreferPrimary,
+    /// The image whose `EXTNAME` card matches.
+    ExtName(String),
+}
+
+/// How an extension enters the frame of `LoaderOptions::combine_extensions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtensionOp {
+    /// Added to the frame.
+    Sum,
+    /// Averaged with the extensions combined into the frame before it, so a
+    /// list of `Mean` entries gives their mean.
+    Mean,
+    /// Subtracted from the frame.
+    Diff,
+}
+
+/// Table extension for `CcdFits::get_table`, by HDU index or `EXTNAME`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableHduSelection {
+    Index(usize),
+    ExtName(String),
+}
+
+impl From<usize> for TableHduSelection {
+    fn from(index: usize) -> Self {
+        TableHduSelection::Index(index)
+    }
+}
+
+impl From<&str> for TableHduSelection {
+    fn from(name: &str) -> Self {
+        TableHduSelection::ExtName(name.to_string())
+    }
+}
+
+impl From<String> for TableHduSelection {
+    fn from(name: String) -> Self {
+        TableHduSelection::ExtName(name)
+    }
+}
+
+/// Options for the per-frame image processing done in `process_image`.
+///
+/// Pixel transforms run before any statistic is computed, so every derived
+/// column is in the corrected units.
+#[derive(Clone, Debug, Default)]
+pub struct ImageOptions {
+    /// Detector gain used to convert ADU into photons. When set, pixel values are
+    /// multiplied by the gain and the stored image is promoted to `Float64`.
+    ///
+    /// The gain is the first correction applied; normalizing by `EXPOSURE`
+    /// afterwards yields photons per second rather than ADU per second.
+    pub gain: Option<f64>,
+    /// Factor applied to the stored pixels only, `Raw` and `Thumbnail`, after
+    /// every statistic has been computed from the unscaled frame. Promotes `Raw`
+    /// to `Float64` like `gain`. The monitor normalizations use it to divide the
+    /// image while the integrated columns are divided on their own, since `Net
+    /// Counts Error`, `SNR` and peak detection do not scale with the pixels.
+    pub pixel_scale: Option<f64>,
+    /// Radius in pixels of a circular aperture, placed according to
+    /// `aperture_center`. When set, the counts inside it are emitted as
+    /// `Aperture Counts` and the center used as `Aperture X/Y [px]`.
+    pub aperture_radius: Option<f64>,
+    /// Where the aperture is placed on each frame.
+    pub aperture_center: ApertureCenter,
+    /// Inner and outer radius in pixels of a background annulus around the
+    /// aperture center. When set together with `aperture_radius`, the background
+    /// scaled to the aperture area is emitted as `Background Counts`, along with
+    /// `Net Counts` and its Poisson uncertainty `Net Counts Error`.
+    ///
+    /// The error assumes Poisson statistics on pixel values that are photon
+    /// counts, i.e. a gain of 1 or the `gain` option converting ADU to photons.
+    pub background_annulus: Option<(f64, f64)>,
+    /// Emit the signal-to-noise ratio `Net Counts / Net Counts Error` as `SNR`,
+    /// null when the error is zero. Needs `background_annulus`.
+    pub snr: bool,
+    /// Emit the sum over every pixel of the frame as `Total Counts`.
+    pub total_counts: bool,
+    /// How non-finite pixels enter `Total Counts` and the aperture and background
+    /// sums. By default they propagate, so a single `NaN` pixel turns the sums of
+    /// its frame into `NaN`.
+    pub nan_policy: NanPolicy,
+    /// Emit the `BITPIX` of the image HDU and, when loading files, the index of
+    /// the HDU the frame was read from as `Image HDU`. For tracking down dtype
+    /// issues, e.g. a directory mixing integer and float frames.
+    pub hdu_info: bool,
+    /// Emit a `Thumbnail` preview whose longer side is at most this many pixels,
+    /// see `crate::image::thumbnail` for the resampling.
+    pub thumbnail_size: Option<usize>,
+    /// Emit a contrast-stretched, colormapped preview of the frame as a base64
+    /// PNG string in `PNG Thumbnail`, see `crate::image::png_thumbnail`.
+    #[cfg(feature = "png")]
+    pub png_thumbnail: Option<PngThumbnailOptions>,
+    /// Fit a 2D Gaussian to the specular peak and emit its parameters as the
+    /// `Peak *` columns, null when the fit does not converge.
+    pub peak_fit: Option<PeakFitOptions>,
+    /// Emit the full width at half maximum of the specular peak as `FWHM X
+    /// [px]` and `FWHM Y [px]`, see `crate::image::peak_fwhm`. Each is null
+    /// when the peak is narrower than a pixel or not contained in the region.
+    pub fwhm: Option<FwhmOptions>,
+    /// Emit whether the frame holds a peak at all as `Peak Found`, see
+    /// `PeakDetection`. On frames without one, e.g. with the beam blocked, the
+    /// `Peak *` fit and `FWHM X/Y [px]` columns, a centroid-placed `Aperture
+    /// X/Y [px]` and the `Beam Offset X/Y [px]` are null.
+    pub peak_detection: Option<PeakDetection>,
+    /// Store `Raw` as a flat `FixedSizeList` of `rows * cols` pixels in row-major
+    /// order, with the frame dimensions in `Image Rows`/`Image Cols`.
+    ///
+    /// A single fixed-size list is one contiguous buffer in Arrow, so the Python
+    /// side can hand the whole stack to numpy without copying and reshape it to
+    /// `(frames, rows, cols)`, while the nested layout has to be rebuilt row by
+    /// row. Every frame of a load must then share the same dimensions.
+    pub fixed_size_list: bool,
+    /// Store `Raw` as `Int32` for every source data type, gain-corrected pixels
+    /// included, rounding and saturating at the `Int32` bounds instead of
+    /// wrapping or turning into nulls.
+    ///
+    /// Prefer this for integer corrections such as dark subtraction, which need
+    /// signed headroom but no fractions, at half the size of `Float64`. Keep the
+    /// float path (`gain` without this flag) for corrections producing fractional
+    /// values, e.g. flat-field division.
+    pub int32: bool,
+    /// Store `Raw` as this numeric type whatever the source `BITPIX`, e.g.
+    /// `DataType::Float32` for a uniform dtype across an archive mixing integer
+    /// and float frames. Takes precedence over `int32`, which is the same as
+    /// `Some(DataType::Int32)`.
+    ///
+    /// The stored values are the pixels after `BZERO` and the corrections. Float
+    /// types take them as they are, `Float32` rounding to the nearest
+    /// representable value. Integer types round half away from zero and
+    /// saturate at the bounds of the type, e.g. negative bias-subtracted pixels
+    /// become `0` in `UInt16`, and `NaN` pixels become `0`, dead zones stay null.
+    /// Any other type is an `InvalidConfig` error.
+    pub image_dtype: Option<DataType>,
+    /// Store `Raw` and `Thumbnail` transposed, as `(cols, rows)`, for tools that
+    /// index frames as `[col, row]`. The outer list then runs along the FITS
+    /// columns (`NAXIS1`), so `Raw[i][j]` is the pixel in column `i` and row `j`.
+    ///
+    /// By default images are stored the way astropy and numpy read FITS,
+    /// `(rows, cols)` = `(NAXIS2, NAXIS1)` with `Raw[i][j]` in row `i` and
+    /// column `j`, `NAXIS1` being the axis that varies fastest on disk. Only
+    /// the stored columns change: ROIs, centroids and every other pixel
+    /// coordinate keep `x` = column and `y` = row, while `Image Rows`/`Image
+    /// Cols` give the stored shape, i.e. the swapped dimensions.
+    ///
+    /// ```rust
+    /// use ndarray::array;
+    /// use pyref_core::fits::write_image_fits;
+    /// use pyref_core::io::image_from_column;
+    /// use pyref_core::loader::read_fits_with_options;
+    /// use pyref_core::options::{ImageHduSelection, LoaderOptions};
+    ///
+    /// let path = std::env::temp_dir().join("pyref_core_transpose.fits");
+    /// write_image_fits(&array![[1i16, 2, 3], [4, 5, 6]], &[], &path)?;
+    /// let mut options = LoaderOptions {
+    ///     image_hdu: ImageHduSelection::Index(1),
+    ///     ..LoaderOptions::default()
+    /// };
+    /// options.image.transpose = true;
+    /// let df = read_fits_with_options(path, &[], &options)?;
+    /// let raw = image_from_column(df.column("Raw")?, 0)?;
+    /// assert_eq!(raw, array![[1.0, 4.0], [2.0, 5.0], [3.0, 6.0]]);
+    /// # Ok::<(), pyref_core::errors::FitsLoaderError>(())
+    /// ```
+    pub transpose: bool,
+    /// Leave the `Raw` image column out of the output. The pixels are still
+    /// decoded and corrected for the statistics, thumbnail and q map, only the
+    /// nested column, by far the largest part of a saved frame, is not stored.
+    pub drop_image: bool,
+    /// Store a lightweight `Image Handle` instead of `Raw` when loading files,
+    /// for large scans where only a few images are ever looked at: a struct of
+    /// the `Source Path`, the `HDU` index, the `Frame` of a cube slice and the
+    /// `Rows` and `Cols` of the image. The statistics are still computed at load
+    /// time, `crate::loader::materialize_image` reads the pixels of a row.
+    pub image_handle: bool,
+    /// Subtract the mean of the overscan columns/rows from every pixel as a
+    /// per-frame bias, emitted as `Bias` in ADU, and cut the overscan off the
+    /// frame. `Raw` and every statistic then cover the science region only, and
+    /// `Raw` is promoted to `Float64` like with `gain`.
+    pub overscan: Option<OverscanOptions>,
+    /// Put the rows of detectors that read out in two halves stored interlaced
+    /// back in place, see `crate::image::deinterlace_rows`. This is the first
+    /// transform, so `Raw`, the overscan and every pixel coordinate refer to the
+    /// reassembled frame. Integer `Raw` stays integer.
+    pub deinterlace: Option<RowReadout>,
+    /// Rectangular regions excluded from every frame, e.g. the gaps between the
+    /// chips of a tiled detector, in pixels of the science region. The pixels are
+    /// null in `Raw` and left out of every statistic and the background fit, the
+    /// sums skip them whatever the `nan_policy`. A region that does not lie inside
+    /// the frame is an error.
+    pub dead_zones: Vec<Roi>,
+    /// Fit a smooth 2D polynomial to the pixels outside the peak and subtract it
+    /// from the frame before any statistic, see `PolyBackgroundOptions`. `Raw` is
+    /// promoted to `Float64` like with `gain`.
+    pub poly_background: Option<PolyBackgroundOptions>,
+    /// Dark frame subtracted from every frame after the overscan bias and before
+    /// the gain, in ADU of the science region. Promotes `Raw` to `Float64` like
+    /// `gain`. The frames of a load share the buffer, `LoaderOptions::dark_frame`
+    /// reads it from a file.
+    pub dark: Option<Arc<Array2<f64>>>,
+    /// Flat field every frame is divided by after the dark, used as is, so it
+    /// should be normalized to a mean of `1`. Shared and promoted like `dark`,
+    /// `LoaderOptions::flat_frame` reads and normalizes it from a file.
+    pub flat: Option<Arc<Array2<f64>>>,
+    /// Run the frame quality checks and emit the failed ones as the `Quality`
+    /// bit set, see `crate::image::FrameQuality` for the flags. Clean frames are
+    /// `0`, so `col("Quality").eq(lit(0))` keeps only those.
+    pub quality: Option<QualityOptions>,
+    /// Emit the integrated specular intensity as `Intensity`, together with the
+    /// method used as `Integration Method`.
+    pub integration: Option<IntegrationOptions>,
+    /// Emit the summed counts of two regions as `Peak ROI Counts` and `Reference
+    /// ROI Counts`, and their ratio as `ROI Ratio`, null when the reference holds
+    /// no counts. A cheap per-frame monitor for alignment scans.
+    pub roi_ratio: Option<RoiRatioOptions>,
+    /// Integrate a band through the specular peak and two off-specular bands
+    /// beside it, emitting `Specular`, `Off-Specular` and the diffuse-corrected
+    /// `Specular Net`, see `crate::image::specular_bands`. Normalized like the
+    /// other integrated intensities, and null when a band leaves the frame.
+    pub specular_bands: Option<SpecularBandOptions>,
+    /// Emit the summed counts over the finite pixels of every region as `{name}
+    /// ROI Counts`, e.g. `Specular ROI Counts`, in one pass over the frame. The
+    /// regions may overlap but must lie inside the frame, and their names must
+    /// be unique. Unlike the built-in integrated columns they are not scaled by
+    /// the monitor normalizations.
+    pub named_rois: Vec<NamedRoi>,
+    /// Emit the azimuthally averaged intensity as the `Radial Profile` list
+    /// column, with the ring radii in `Radial Profile R [px]`.
+    pub radial_profile: Option<RadialProfileOptions>,
+    /// Emit how far the intensity centroid of every frame lies from a
+    /// reference center as `Beam Offset X [px]` and `Beam Offset Y [px]`, for
+    /// tracking the beam position over a scan. Frames without positive counts
+    /// have no centroid and get null offsets, as do frames without a peak when
+    /// `peak_detection` is set.
+    pub beam_offset: Option<BeamReference>,
+}
+
+/// Where the loaders spend the thread pool on a multi-file load.
+///
+/// The data frame is the same either way, only the throughput differs: many
+/// small frames load fastest one file per thread, a few multi-megapixel frames
+/// one file at a time with their pixels copied into `Raw` in parallel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Parallelism {
+    /// `WithinFrames` when there are fewer files than threads and they average
+    /// 8 MiB or more, judged by their size on disk, `AcrossFiles` otherwise.
+    #[default]
+    Auto,
+    /// Load the files in parallel, each on one thread.
+    AcrossFiles,
+    /// Load the files one after the other, each packaged on the whole pool.
+    WithinFrames,
+}
+
+/// Treatment of `NaN` and infinite pixels in the summed statistics.
+///
+/// The centroid, peak fit, radial profile and `Intensity` always ignore
+/// non-finite pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// Sum every pixel, so non-finite values carry into the result.
+    #[default]
+    Propagate,
+    /// Leave non-finite pixels out of the sums and the annulus pixel count, and
+    /// emit how many were skipped as `NaN Pixels`.
+    Skip,
+}
+
+/// Placement of the integration aperture.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApertureCenter {
+    /// Recenter on the intensity centroid of every frame, following a beam that
+    /// walks across the detector. Frames without positive counts use `fallback`,
+    /// or get null counts when it is not set.
+    Centroid { fallback: Option<(f64, f64)> },
+    /// The same `(x, y)` pixel for every frame.
+    Fixed((f64, f64)),
+}
+
+impl Default for ApertureCenter {
+    fn default() -> Self {
+        ApertureCenter::Centroid { fallback: None }
+    }
+}
+
+/// Reference center of `ImageOptions::beam_offset`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum BeamReference {
+    /// The centroid of the first frame, in path order, that has one, so the
+    /// offsets start at zero. Applied once the frames are combined.
+    #[default]
+    FirstFrame,
+    /// The same `(x, y)` pixel for every frame, e.g. the aligned beam position.
+    Fixed((f64, f64)),
+}
+
+/// Options for the Gaussian peak fit, see `crate::image::fit_peak`.
+#[derive(Clone, Debug, Default)]
+pub struct PeakFitOptions {
+    /// Fit only this region instead of the full frame.
+    pub roi: Option<Roi>,
+}
+
+/// Options for the peak width, see `crate::image::peak_fwhm`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FwhmOptions {
+    pub method: FwhmMethod,
+    /// Measure the peak in this region instead of the full frame.
+    pub roi: Option<Roi>,
+}
+
+/// How the full width at half maximum of a peak is estimated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FwhmMethod {
+    /// `2·sqrt(2·ln 2)·σ` from the Gaussian of `crate::image::fit_peak`,
+    /// null when the fit fails.
+    #[default]
+    GaussianFit,
+    /// Distance between the half-maximum crossings of the column and row sums,
+    /// interpolated linearly between pixels. Makes no assumption on the peak
+    /// shape, e.g. for flat-topped or asymmetric beams.
+    HalfMaxCrossing,
+}
+
+/// Criterion for `ImageOptions::peak_detection`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PeakDetection {
+    /// `Net Counts / Net Counts Error` reaches this value, so `aperture_radius`
+    /// and `background_annulus` must be set. Frames where the ratio is null have
+    /// no peak.
+    Snr(f64),
+    /// The brightest finite pixel is at least this many counts above the median
+    /// of the finite pixels, taken as the background.
+    AboveBackground(f64),
+}
+
+/// Options for the radial profile, see `crate::image::radial_profile`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadialProfileOptions {
+    /// Number of rings.
+    pub bins: usize,
+    /// Center of the rings, the intensity centroid by default.
+    pub center: ApertureCenter,
+}
+
+impl Default for RadialProfileOptions {
+    fn default() -> Self {
+        RadialProfileOptions {
+            bins: 100,
+            center: ApertureCenter::default(),
+        }
+    }
+}
+
+/// Options for the PNG preview, see `crate::image::png_thumbnail`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngThumbnailOptions {
+    /// Longer side of the PNG in pixels.
+    pub size: usize,
+    pub colormap: Colormap,
+    /// Lower and upper percentile of the frame mapped to the ends of the
+    /// colormap, in percent.
+    pub contrast: (f64, f64),
+}
+
+impl Default for PngThumbnailOptions {
+    fn default() -> Self {
+        PngThumbnailOptions {
+            size: 128,
+            colormap: Colormap::default(),
+            contrast: (1.0, 99.0),
+        }
+    }
+}
+
+/// Colormap of the PNG preview.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Colormap {
+    /// 8-bit grayscale, black to white.
+    #[default]
+    Gray,
+    /// matplotlib's `viridis`, dark blue to yellow.
+    Viridis,
+    /// matplotlib's `inferno`, black to pale yellow.
+    Inferno,
+}
+
+/// Options for the integrated intensity, see `crate::image::integrate`.
+#[derive(Clone, Debug, Default)]
+pub struct IntegrationOptions {
+    pub method: IntegrationMethod,
+    /// Integrate only this region instead of the full frame.
+    pub roi: Option<Roi>,
+    /// Weight every pixel by this profile instead, e.g. a matched filter, see
+    /// `crate::image::weighted_sum`. The mask must have the shape of the frame
+    /// after binning and overscan removal; a mask of ones and zeros gives the
+    /// sum of an ROI or aperture. It replaces `roi` and the `Sum` method, so it
+    /// cannot be combined with either, and `Integration Method` is `weighted`.
+    pub weight_mask: Option<Arc<Array2<f64>>>,
+}
+
+/// Bands of `ImageOptions::specular_bands`, in pixels relative to the peak.
+///
+/// Every band runs across the whole frame perpendicular to `axis` and is
+/// `2 * half_width + 1` pixels wide along it. The off-specular bands are
+/// centered `offset` pixels to either side of the specular one, which must be
+/// far enough, `offset > 2 * half_width`, that the bands do not overlap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpecularBandOptions {
+    pub half_width: usize,
+    pub offset: usize,
+    /// Direction the off-specular bands are displaced in.
+    pub axis: BandAxis,
+}
+
+/// Image axis of `SpecularBandOptions::axis`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BandAxis {
+    /// Along the columns, so the bands are vertical stripes.
+    #[default]
+    X,
+    /// Along the rows, so the bands are horizontal stripes.
+    Y,
+}
+
+/// Regions of the two-ROI monitor, see `ImageOptions::roi_ratio`. Both are
+/// summed over their finite pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoiRatioOptions {
+    /// The specular peak, the numerator of the ratio.
+    pub peak: Roi,
+    /// The reference region, the denominator.
+    pub reference: Roi,
+}
+
+/// How the specular intensity of a frame is integrated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntegrationMethod {
+    /// Plain sum over the pixels of the region.
+    #[default]
+    Sum,
+    /// Volume under the 2D Gaussian fitted to the region, which excludes a flat
+    /// background and counts from neighbouring features.
+    FittedArea,
+}
+
+impl IntegrationMethod {
+    /// Name stored in the `Integration Method` column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegrationMethod::Sum => "sum",
+            IntegrationMethod::FittedArea => "fitted area",
+        }
+    }
+}
+
+/// How the grouping reductions in `crate::reduce` bucket float coordinates,
+/// so that e.g. `10.0001` and `10.0` count as the same `Sample Theta`.
+///
+/// Integer and string coordinates are always grouped by exact value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoordinateTolerance {
+    /// Round to this many decimal places before grouping.
+    Decimals(u32),
+    /// Group values whose sorted neighbours are at most this far apart. A
+    /// chain of close values forms one group even when its ends are further
+    /// apart than the tolerance.
+    Absolute(f64),
+}
+
+/// How `crate::reduce::resample_q` interpolates between measured points.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QInterpolation {
+    /// Straight lines in `R`.
+    #[default]
+    Linear,
+    /// Straight lines in `log R`, which follows the steep decay of a
+    /// reflectivity curve more closely. Points with `R <= 0` are left out.
+    Log,
+}
+
+/// Overscan bands of the detector, see `crate::image::split_overscan`.
+///
+/// Each band is a range of pixel indices along its axis and has to lie at an
+/// edge of the frame, ranges that do not are rejected when a frame is loaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OverscanOptions {
+    /// Overscan columns, e.g. `1024..1056` for 32 columns past a 1024 wide chip.
+    pub columns: Option<Range<usize>>,
+    /// Overscan rows.
+    pub rows: Option<Range<usize>>,
+}
+
+/// Order in which a split-readout detector stores its rows, see
+/// `crate::image::deinterlace_rows`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowReadout {
+    /// The stored rows alternate between the two halves, both read from the
+    /// top: even rows `0, 2, 4, ...` are the top half, odd rows the bottom half.
+    /// With an odd number of rows the top half has the extra row.
+    Interlaced,
+    /// Like `Interlaced` with the bottom half read from the bottom edge
+    /// towards the center, as by an amplifier at each end of the chip, so the
+    /// first odd row is the last row of the frame.
+    InterlacedMirrored,
+    /// Any other pattern: stored row `i` is row `order[i]` of the frame, which
+    /// has to be a permutation of `0..rows`.
+    Order(Vec<usize>),
+}
+
+/// Options for the polynomial background, see
+/// `crate::image::subtract_poly_background`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolyBackgroundOptions {
+    /// Total degree of the polynomial, `1` (a tilted plane) by default.
+    pub order: usize,
+    /// Peak region left out of the fit, the whole frame is fitted without one.
+    pub mask: Option<Roi>,
+    /// Emit the fitted coefficients as `Background c{i}{j}` columns, see
+    /// `crate::image::fit_poly_background` for their order and coordinates.
+    pub coefficients: bool,
+}
+
+impl Default for PolyBackgroundOptions {
+    fn default() -> Self {
+        PolyBackgroundOptions {
+            order: 1,
+            mask: None,
+            coefficients: false,
+        }
+    }
+}
+
+/// Thresholds for the frame quality checks, see `crate::image::frame_quality`.
+///
+/// The checks run on the detector ADU, before the gain is applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityOptions {
+    /// Pixel value at or above which a frame counts as saturated, `65535` by
+    /// default for the unsigned 16-bit detector.
+    pub saturation_level: f64,
+    /// Also emit the share of pixels at or above `saturation_level` as
+    /// `Saturation Fraction`, a graded counterpart to the `SATURATED` flag that
+    /// can be thresholded per analysis, e.g.
+    /// `col("Saturation Fraction").lt(lit(0.05))`.
+    pub saturation_fraction: bool,
+    /// Frames summing to less than this are flagged as low counts.
+    pub min_counts: Option<f64>,
+    /// Expected `(rows, cols)` of every frame.
+    pub expected_shape: Option<(usize, usize)>,
+}
+
+impl Default for QualityOptions {
+    fn default() -> Self {
+        QualityOptions {
+            saturation_level: u16::MAX as f64,
+            saturation_fraction: false,
+            min_counts: None,
+            expected_shape: None,
+        }
+    }
+}
+
+/// How `crate::loader::watch_experiment_with_options` looks for new files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchOptions {
+    /// Interval at which the directory is scanned when no file system event
+    /// arrives, `crate::loader::WATCH_INTERVAL` by default.
+    pub interval: Duration,
+    /// Subscribe to file system events, so new files are picked up as soon as
+    /// they are written, on by default. The interval scans go on regardless,
+    /// they catch the writes the events miss, e.g. from other machines on a
+    /// network share. Without the subscription the watch only polls.
+    pub notify: bool,
+    /// How long a new file may fail to load without growing before the watch
+    /// gives up on it and hands its error to the callback, 30 s by default.
+    /// A detector still writing the file keeps it growing, so it is retried.
+    pub give_up_after: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            interval: crate::loader::WATCH_INTERVAL,
+            notify: true,
+            give_up_after: Duration::from_secs(30),
+        }
+    }
+}
@@ -0,0 +1,1381 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+use ndarray::{Array2, Zip};
+use polars::prelude::*;
+
+use crate::enums::{ExperimentType, HeaderValue};
+use crate::errors::FitsLoaderError;
+use crate::image::{integrate, Roi};
+use crate::io::{image_from_column, images_to_column};
+use crate::loader::{read_experiment_with_options, read_fits_with_options};
+use crate::options::{CoordinateTolerance, IntegrationMethod, IntegrationOptions, QInterpolation};
+
+// ================== Frame Reductions ==================
+// Reductions operate on the combined DataFrame returned by the loaders and
+// produce a new, usually shorter, DataFrame.
+
+/// Element-wise mean of the images in `rows` of `image_col`, null rows are skipped.
+fn mean_image(
+    df: &DataFrame,
+    image_col: &str,
+    rows: &[usize],
+) -> Result<Option<Array2<f64>>, FitsLoaderError> {
+    weighted_mean_image(df, image_col, rows, &vec![1.0; df.height()])
+}
+
+/// Element-wise `Σ wᵢ·Iᵢ / Σ wᵢ` over the images in `rows` of `image_col`, with
+/// `weights` indexed by row. Null rows and rows with a null weight are skipped,
+/// and weights summing to zero give `None` like the float columns.
+fn weighted_mean_image(
+    df: &DataFrame,
+    image_col: &str,
+    rows: &[usize],
+    weights: &[f64],
+) -> Result<Option<Array2<f64>>, FitsLoaderError> {
+    let column = df.column(image_col)?;
+    let valid = column.is_not_null();
+    let rows: Vec<usize> = rows
+        .iter()
+        .copied()
+        .filter(|&row| valid.get(row).unwrap_or(false) && !weights[row].is_nan())
+        .collect();
+    let mut sum: Option<Array2<f64>> = None;
+    let mut total_weight = 0.0;
+    for &row in &rows {
+        let image = image_from_column(column, row)? * weights[row];
+        total_weight += weights[row];
+        sum = match sum {
+            None => Some(image),
+            Some(acc) if acc.dim() == image.dim() => Some(acc + image),
+            Some(acc) => {
+                return Err(FitsLoaderError::Other(format!(
+                    "Image shapes {:?} and {:?} differ",
+                    acc.dim(),
+                    image.dim()
+                )))
+            }
+        };
+    }
+    Ok(sum
+        .filter(|_| total_weight != 0.0)
+        .map(|sum| sum / total_weight))
+}
+
+fn is_image(dtype: &DataType) -> bool {
+    matches!(dtype, DataType::List(_) | DataType::Array(_, _))
+}
+
+/// Values of a numeric column as `f64`, nulls become `NaN`.
+fn float_values(df: &DataFrame, name: &str) -> Result<Vec<f64>, FitsLoaderError> {
+    let values = df.column(name)?.cast(&DataType::Float64)?;
+    Ok(values
+        .f64()?
+        .into_iter()
+        .map(|v| v.unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// Reduces every group to one row: image columns become the element-wise
+/// weighted mean, float columns the weighted mean and every other column keeps
+/// the value of the first frame. Without `weight_col` all frames weigh the same.
+fn reduce_groups(
+    df: &DataFrame,
+    groups: &[DataFrame],
+    coords: &[&str],
+    weight_col: Option<&str>,
+) -> Result<Vec<Column>, FitsLoaderError> {
+    let weights = groups
+        .iter()
+        .map(|group| match weight_col {
+            Some(weight_col) => float_values(group, weight_col),
+            None => Ok(vec![1.0; group.height()]),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut columns = Vec::with_capacity(df.width() + 1);
+    for column in df.get_columns() {
+        let name = column.name().clone();
+        let reduced = if !coords.contains(&name.as_str()) && is_image(column.dtype()) {
+            let images = groups
+                .iter()
+                .zip(&weights)
+                .map(|(group, weights)| {
+                    let rows: Vec<usize> = (0..group.height()).collect();
+                    weighted_mean_image(group, name.as_str(), &rows, weights)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            images_to_column(name, &images)?
+        } else if !coords.contains(&name.as_str()) && column.dtype().is_float() {
+            let means = groups
+                .iter()
+                .zip(&weights)
+                .map(|(group, weights)| {
+                    let (sum, total) = float_values(group, name.as_str())?
+                        .into_iter()
+                        .zip(weights)
+                        .filter(|(v, w)| !v.is_nan() && !w.is_nan())
+                        .fold((0.0, 0.0), |(sum, total), (v, w)| (sum + v * w, total + w));
+                    Ok((total != 0.0).then_some(sum / total))
+                })
+                .collect::<Result<Vec<Option<f64>>, FitsLoaderError>>()?;
+            Column::new(name, means)
+        } else {
+            let mut first = Column::new_empty(name.clone(), column.dtype());
+            for group in groups {
+                first.append(&group.column(name.as_str())?.head(Some(1)))?;
+            }
+            first
+        };
+        columns.push(reduced);
+    }
+    Ok(columns)
+}
+
+/// Splits `df` into groups of equal `coords`, in order of first appearance.
+///
+/// With a `tolerance`, float coordinates are compared by their bucket instead
+/// of their exact value; the rows keep their original values.
+fn partition_coords(
+    df: &DataFrame,
+    coords: &[&str],
+    tolerance: Option<CoordinateTolerance>,
+) -> Result<Vec<DataFrame>, FitsLoaderError> {
+    let Some(tolerance) = tolerance else {
+        return Ok(df.partition_by_stable(coords.iter().copied(), true)?);
+    };
+    let mut keyed = df.clone();
+    let mut keys = vec![];
+    for &coord in coords {
+        if !df.column(coord)?.dtype().is_float() {
+            keys.push(coord.to_string());
+            continue;
+        }
+        let values = float_values(df, coord)?;
+        let buckets: Vec<Option<f64>> = match tolerance {
+            CoordinateTolerance::Decimals(decimals) => {
+                let scale = 10f64.powi(decimals as i32);
+                values
+                    .iter()
+                    .map(|v| (!v.is_nan()).then(|| (v * scale).round() / scale))
+                    .collect()
+            }
+            CoordinateTolerance::Absolute(tolerance) => {
+                // Each value is keyed by the smallest value of its chain.
+                let mut order: Vec<usize> =
+                    (0..values.len()).filter(|&i| !values[i].is_nan()).collect();
+                order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+                let mut buckets = vec![None; values.len()];
+                let mut chain: Option<(f64, f64)> = None;
+                for i in order {
+                    let value = values[i];
+                    let bucket = match chain {
+                        Some((first, last)) if value - last <= tolerance => first,
+                        _ => value,
+                    };
+                    chain = Some((bucket, value));
+                    buckets[i] = Some(bucket);
+                }
+                buckets
+            }
+        };
+        let key = format!("{} Bucket", coord);
+        keyed.with_column(Column::new(key.as_str().into(), buckets))?;
+        keys.push(key);
+    }
+    let added: Vec<&String> = keys.iter().filter(|key| df.column(key).is_err()).collect();
+    Ok(keyed
+        .partition_by_stable(keys.iter().map(String::as_str), true)?
+        .into_iter()
+        .map(|group| group.drop_many(added.iter().map(|key| key.as_str())))
+        .collect())
+}
+
+/// Averages repeated exposures taken at the same scan coordinate.
+///
+/// Frames are grouped by the `coords` columns and reduced to one row per group:
+/// image columns (nested lists/arrays) are averaged element-wise, float columns
+/// such as the header values and image statistics are averaged, and every other
+/// column keeps the value of the first frame. The number of frames that went into
+/// each row is reported as `N Averaged`.
+pub fn average_repeats(df: &DataFrame, coords: &[&str]) -> Result<DataFrame, FitsLoaderError> {
+    average_repeats_with_tolerance(df, coords, None)
+}
+
+/// Same as `average_repeats`, with float coordinates grouped within `tolerance`,
+/// or by exact value for `None`. Each row reports the coordinates of the first
+/// frame of its group.
+pub fn average_repeats_with_tolerance(
+    df: &DataFrame,
+    coords: &[&str],
+    tolerance: Option<CoordinateTolerance>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let groups = partition_coords(df, coords, tolerance)?;
+    if groups.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+
+    let mut columns = reduce_groups(df, &groups, coords, None)?;
+    let counts: Vec<u32> = groups.iter().map(|group| group.height() as u32).collect();
+    columns.push(Column::new("N Averaged".into(), counts));
+
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Merges co-added frames with different integration times.
+///
+/// Like `average_repeats`, but every frame is weighted by its `exposure_col`
+/// value. For images normalized to counts per second the weighted mean
+/// `Σ tᵢ·Iᵢ / Σ tᵢ` equals the summed counts over the summed exposure, which is
+/// what the error propagation of the merged frame has to start from. The
+/// exposure column is replaced by `Total Exposure`, the sum over each group.
+pub fn merge_exposures(
+    df: &DataFrame,
+    coords: &[&str],
+    exposure_col: &str,
+) -> Result<DataFrame, FitsLoaderError> {
+    merge_exposures_with_tolerance(df, coords, exposure_col, None)
+}
+
+/// Same as `merge_exposures`, with float coordinates grouped within `tolerance`,
+/// or by exact value for `None`.
+pub fn merge_exposures_with_tolerance(
+    df: &DataFrame,
+    coords: &[&str],
+    exposure_col: &str,
+    tolerance: Option<CoordinateTolerance>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let groups = partition_coords(df, coords, tolerance)?;
+    if groups.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+
+    let mut columns = reduce_groups(df, &groups, coords, Some(exposure_col))?;
+    columns.retain(|column| column.name() != exposure_col);
+    let totals = groups
+        .iter()
+        .map(|group| {
+            let total: f64 = float_values(group, exposure_col)?
+                .into_iter()
+                .filter(|t| !t.is_nan())
+                .sum();
+            Ok(total)
+        })
+        .collect::<Result<Vec<f64>, FitsLoaderError>>()?;
+    columns.push(Column::new("Total Exposure".into(), totals));
+
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Computes the circular dichroism asymmetry `(I+ - I-) / (I+ + I-)` image.
+///
+/// Frames are grouped by the scan coordinates in `coords` (e.g. sample theta and
+/// energy). Each group must hold exactly two distinct values of `pol_col`; `I+`
+/// is the mean image at the larger polarization value and `I-` the one at the
+/// smaller. Groups without a partner polarization give a null `Asymmetry`, as do
+/// pixels where `I+ + I-` is zero (`NaN`).
+///
+/// Returns one row per coordinate with the `coords` columns and `Asymmetry`.
+pub fn polarization_asymmetry(
+    df: &DataFrame,
+    pol_col: &str,
+    image_col: &str,
+    coords: &[&str],
+) -> Result<DataFrame, FitsLoaderError> {
+    polarization_asymmetry_with_tolerance(df, pol_col, image_col, coords, None)
+}
+
+/// Same as `polarization_asymmetry`, with float coordinates grouped within
+/// `tolerance`, or by exact value for `None`. The polarization values
+/// themselves are still compared exactly.
+pub fn polarization_asymmetry_with_tolerance(
+    df: &DataFrame,
+    pol_col: &str,
+    image_col: &str,
+    coords: &[&str],
+    tolerance: Option<CoordinateTolerance>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let mut keys: Option<DataFrame> = None;
+    let mut images = vec![];
+    for group in partition_coords(df, coords, tolerance)? {
+        let key = group.select(coords.iter().copied())?.head(Some(1));
+        keys = match keys {
+            None => Some(key),
+            Some(mut acc) => {
+                acc.vstack_mut(&key)?;
+                Some(acc)
+            }
+        };
+
+        let pol = group.column(pol_col)?.cast(&DataType::Float64)?;
+        let pol: Vec<Option<f64>> = pol.f64()?.into_iter().collect();
+        let mut values: Vec<f64> = pol.iter().flatten().copied().collect();
+        values.sort_by(|a, b| a.total_cmp(b));
+        values.dedup();
+        if values.len() != 2 {
+            images.push(None);
+            continue;
+        }
+
+        let rows_at = |target: f64| -> Vec<usize> {
+            pol.iter()
+                .enumerate()
+                .filter(|(_, p)| **p == Some(target))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let minus = mean_image(&group, image_col, &rows_at(values[0]))?;
+        let plus = mean_image(&group, image_col, &rows_at(values[1]))?;
+        let asymmetry = match (plus, minus) {
+            (Some(plus), Some(minus)) if plus.dim() == minus.dim() => {
+                Some(Zip::from(&plus).and(&minus).map_collect(|&p, &m| {
+                    if p + m == 0.0 {
+                        f64::NAN
+                    } else {
+                        (p - m) / (p + m)
+                    }
+                }))
+            }
+            _ => None,
+        };
+        images.push(asymmetry);
+    }
+
+    let mut out = keys.ok_or(FitsLoaderError::NoData)?;
+    out.with_column(images_to_column("Asymmetry".into(), &images)?)?;
+    Ok(out)
+}
+
+// ================== XRR Reduction ==================
+
+/// Loads an XRR scan and reduces it to a reflectivity curve in one call.
+///
+/// Every frame in `dir` is read with the XRR header set, its counts are summed
+/// over `roi`, the ROI sum of `dark_path` is subtracted and the result divided
+/// by the exposure. The returned frame holds `Q [Å⁻¹]`, `R`, `R Err`,
+/// `Sample Theta [deg]`, `Beamline Energy [eV]` and `EPU Polarization [deg]`
+/// in scan order.
+///
+/// `R` is in counts per second and is not normalized to the direct beam. The
+/// dark frame is subtracted as is, so it should be taken with the same exposure
+/// as the scan. `R Err` is the Poisson uncertainty `sqrt(counts + dark) / exposure`.
+/// The frame converts into a `ReflectivityCurve` with `try_into`.
+pub fn reduce_xrr(
+    dir: &str,
+    roi: Roi,
+    dark_path: Option<&Path>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let mut options = ExperimentType::Xrr.default_options();
+    options.image.integration = Some(IntegrationOptions {
+        method: IntegrationMethod::Sum,
+        roi: Some(roi),
+        weight_mask: None,
+    });
+    options.source_path = false;
+    // Only the ROI sum is kept, the images are dropped as soon as it is taken.
+    options.image.drop_image = true;
+
+    let dark = match dark_path {
+        Some(path) => read_fits_with_options(path.to_path_buf(), &[], &options)?
+            .column("Intensity")?
+            .f64()?
+            .get(0)
+            .unwrap_or(0.0),
+        None => 0.0,
+    };
+
+    let df = read_experiment_with_options(dir, &ExperimentType::Xrr.get_keys(), &options)?;
+    let exposure = col(HeaderValue::Exposure.name());
+    let reduced = df
+        .lazy()
+        .select([
+            col("Q [Å⁻¹]"),
+            ((col("Intensity") - lit(dark)) / exposure.clone()).alias("R"),
+            ((col("Intensity") + lit(dark)).sqrt() / exposure).alias("R Err"),
+            col(HeaderValue::SampleTheta.name()),
+            col(HeaderValue::BeamlineEnergy.name()),
+            col(HeaderValue::EPUPolarization.name()),
+        ])
+        .collect()?;
+    Ok(reduced)
+}
+
+/// A reflectivity curve pulled out of the frame returned by `reduce_xrr`.
+///
+/// `q`, `r` and `r_err` hold the `Q [Å⁻¹]`, `R` and `R Err` columns with nulls
+/// as `NaN`, `meta` every other column in the same row order.
+#[derive(Clone, Debug)]
+pub struct ReflectivityCurve {
+    pub q: Vec<f64>,
+    pub r: Vec<f64>,
+    pub r_err: Vec<f64>,
+    pub meta: DataFrame,
+}
+
+impl TryFrom<DataFrame> for ReflectivityCurve {
+    type Error = FitsLoaderError;
+
+    /// Fails with `MissingColumn` naming the first of `Q [Å⁻¹]`, `R` and `R Err`
+    /// that the frame lacks.
+    fn try_from(df: DataFrame) -> Result<Self, Self::Error> {
+        const COLUMNS: [&str; 3] = ["Q [Å⁻¹]", "R", "R Err"];
+        if let Some(missing) = COLUMNS.iter().find(|name| df.column(name).is_err()) {
+            return Err(FitsLoaderError::MissingColumn(missing.to_string()));
+        }
+        Ok(ReflectivityCurve {
+            q: float_values(&df, COLUMNS[0])?,
+            r: float_values(&df, COLUMNS[1])?,
+            r_err: float_values(&df, COLUMNS[2])?,
+            meta: df.drop_many(COLUMNS),
+        })
+    }
+}
+
+/// Corrects `r_col` for the part of the beam that misses the sample at low angles.
+///
+/// The beam is modelled as a uniform band of height `beam_height` perpendicular
+/// to its direction, hitting a flat sample of length `sample_length` along the
+/// beam, both in the same unit. At incidence angle `θ` (`theta_col`, in degrees)
+/// the beam spreads over a footprint of `beam_height / sin θ`, and the sample
+/// intercepts the fraction
+///
+/// `f(θ) = min(1, sample_length · sin θ / beam_height)`
+///
+/// of it. `r_col` is divided by `f`, so angles past the overfilling angle
+/// `asin(beam_height / sample_length)` are left unchanged, and `θ <= 0` gives a
+/// null, as do null values. A Gaussian beam profile or a beam narrower than
+/// the sample in the other direction is not modelled.
+pub fn footprint_correction(
+    df: &DataFrame,
+    theta_col: &str,
+    r_col: &str,
+    sample_length: f64,
+    beam_height: f64,
+) -> Result<DataFrame, FitsLoaderError> {
+    if !(sample_length > 0.0 && beam_height > 0.0) {
+        return Err(FitsLoaderError::Other(format!(
+            "Sample length {} and beam height {} must be positive",
+            sample_length, beam_height
+        )));
+    }
+    let theta = float_values(df, theta_col)?;
+    let r = float_values(df, r_col)?;
+    let corrected: Vec<Option<f64>> = theta
+        .into_iter()
+        .zip(r)
+        .map(|(theta, r)| {
+            let fraction = (sample_length * theta.to_radians().sin() / beam_height).min(1.0);
+            (fraction > 0.0 && !r.is_nan()).then(|| r / fraction)
+        })
+        .collect();
+    let mut out = df.clone();
+    out.with_column(Column::new(r_col.into(), corrected))?;
+    Ok(out)
+}
+
+// ================== Rocking Curves ==================
+
+/// Integrated intensity of `roi` against `theta_col`, e.g. `Sample Theta
+/// [deg]`, for rocking curves and other alignment scans taken at a fixed
+/// detector position.
+///
+/// Every frame of the nested `Raw` column of a loaded frame is summed over the
+/// finite pixels of `roi`. The result holds `theta_col`, `Intensity` and its
+/// Poisson uncertainty `Intensity Err`, `sqrt(Intensity)`, sorted by angle.
+/// Unlike `reduce_xrr` nothing is converted to q or divided by the exposure,
+/// and the error assumes photon counts, see `ImageOptions::gain`. Frames
+/// without an image get a null intensity, a `roi` outside a frame is an error.
+pub fn rocking_curve(
+    df: &DataFrame,
+    theta_col: &str,
+    roi: Roi,
+) -> Result<DataFrame, FitsLoaderError> {
+    let theta = df.column(theta_col)?;
+    let intensity = raw_sums(df, Some(roi))?;
+    let err: Vec<Option<f64>> = intensity.iter().map(|i| i.map(f64::sqrt)).collect();
+    let curve = DataFrame::new(vec![
+        theta.clone(),
+        Column::new("Intensity".into(), intensity),
+        Column::new("Intensity Err".into(), err),
+    ])?;
+    Ok(curve.sort(
+        [theta_col],
+        SortMultipleOptions::default().with_maintain_order(true),
+    )?)
+}
+
+/// Sum of every frame of the nested `Raw` column over the finite pixels of
+/// `roi`, or of the whole frame, null for frames without an image.
+fn raw_sums(df: &DataFrame, roi: Option<Roi>) -> Result<Vec<Option<f64>>, FitsLoaderError> {
+    let raw = df.column("Raw")?;
+    let mut sums = Vec::with_capacity(df.height());
+    for row in 0..df.height() {
+        if raw.get(row)?.is_null() {
+            sums.push(None);
+            continue;
+        }
+        let image = image_from_column(raw, row)?;
+        if let Some(roi) = roi {
+            roi.validate(image.dim())?;
+        }
+        sums.push(integrate(&image.view(), roi, IntegrationMethod::Sum));
+    }
+    Ok(sums)
+}
+
+// ================== Experiment Intensity ==================
+
+/// Adds the observable of `experiment_type` to a loaded frame as `Intensity`,
+/// replacing any `Intensity` already there, so that later steps need not know
+/// which experiment the frame came from.
+///
+/// * `Xrr` - the specular spot: every frame of `Raw` summed over `roi`, which
+///   is required, as `rocking_curve` does.
+/// * `Xrs` - the transmitted beam: `Total Counts`, as loaded with
+///   `ExperimentType::default_options`, or the sum of the whole `Raw` frame
+///   when the load did not emit it. `roi` is ignored.
+/// * `Other` - no convention, an `InvalidConfig` error.
+///
+/// Counts are not divided by the exposure.
+pub fn intensity_for(
+    experiment_type: ExperimentType,
+    df: &DataFrame,
+    roi: Option<Roi>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let intensity = match experiment_type {
+        ExperimentType::Xrr => {
+            let roi = roi.ok_or_else(|| {
+                FitsLoaderError::InvalidConfig(
+                    "XRR intensity needs the ROI of the specular spot".into(),
+                )
+            })?;
+            Column::new("Intensity".into(), raw_sums(df, Some(roi))?)
+        }
+        ExperimentType::Xrs => match df.column("Total Counts") {
+            Ok(total) => total
+                .cast(&DataType::Float64)?
+                .with_name("Intensity".into()),
+            Err(_) => Column::new("Intensity".into(), raw_sums(df, None)?),
+        },
+        ExperimentType::Other => {
+            return Err(FitsLoaderError::InvalidConfig(format!(
+                "No intensity convention for the {} experiment type",
+                experiment_type.name()
+            )))
+        }
+    };
+    let mut out = df.clone();
+    out.with_column(intensity)?;
+    Ok(out)
+}
+
+// ================== Energy Matrix ==================
+
+/// Linear interpolation of the points `(x, y)`, sorted by `x`, at `at`. Outside
+/// the sampled range, and for a `NaN` position, the result is `None`.
+fn interpolate(points: &[(f64, f64)], at: f64) -> Option<f64> {
+    let (first, last) = (points.first()?, points.last()?);
+    if !(first.0..=last.0).contains(&at) {
+        return None;
+    }
+    let upper = points.partition_point(|&(x, _)| x < at);
+    let (x1, y1) = points[upper];
+    if x1 == at || upper == 0 {
+        return Some(y1);
+    }
+    let (x0, y0) = points[upper - 1];
+    Some(y0 + (y1 - y0) * (at - x0) / (x1 - x0))
+}
+
+/// Collapses runs of equal `x` in sorted `points` into their mean `y`.
+fn average_duplicates(points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    let mut merged: Vec<(f64, f64, usize)> = vec![];
+    for (x, y) in points {
+        match merged.last_mut() {
+            Some((last, sum, n)) if *last == x => {
+                *sum += y;
+                *n += 1;
+            }
+            _ => merged.push((x, y, 1)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(x, sum, n)| (x, sum / n as f64))
+        .collect()
+}
+
+/// Pivots a tall reflectivity frame into a matrix `R(q, energy)`.
+///
+/// The first column of the result is `q_col`, holding the common q grid: the
+/// sorted distinct q values over every energy. Each distinct value of
+/// `energy_col` then becomes one column, named after the energy and in
+/// ascending order, with that energy's `r_col` linearly interpolated onto the
+/// grid.
+///
+/// Energies measured on different q points thus share the union of all points,
+/// and where an energy was measured the grid reproduces its values exactly.
+/// Grid points outside the q range of an energy are null rather than
+/// extrapolated. Repeated q points of one energy are averaged, and rows with a
+/// null or `NaN` q or reflectivity are dropped before interpolating.
+pub fn reflectivity_matrix(
+    df: &DataFrame,
+    q_col: &str,
+    energy_col: &str,
+    r_col: &str,
+) -> Result<DataFrame, FitsLoaderError> {
+    let q = float_values(df, q_col)?;
+    let energy = float_values(df, energy_col)?;
+    let r = float_values(df, r_col)?;
+
+    let mut curves: Vec<(f64, Vec<(f64, f64)>)> = vec![];
+    for ((q, energy), r) in q.into_iter().zip(energy).zip(r) {
+        if q.is_nan() || energy.is_nan() || r.is_nan() {
+            continue;
+        }
+        match curves.iter_mut().find(|(e, _)| *e == energy) {
+            Some((_, points)) => points.push((q, r)),
+            None => curves.push((energy, vec![(q, r)])),
+        }
+    }
+    if curves.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+    curves.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut grid: Vec<f64> = curves
+        .iter()
+        .flat_map(|(_, points)| points.iter().map(|&(q, _)| q))
+        .collect();
+    grid.sort_by(|a, b| a.total_cmp(b));
+    grid.dedup();
+
+    let mut columns = vec![Column::new(q_col.into(), &grid)];
+    for (energy, mut points) in curves {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let points = average_duplicates(points);
+        let values: Vec<Option<f64>> = grid.iter().map(|&q| interpolate(&points, q)).collect();
+        columns.push(Column::new(energy.to_string().into(), values));
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+// ================== Resampling ==================
+
+/// Resamples the reflectivity `r_col` onto the q grid `target_q`.
+///
+/// The result holds `q_col` with `target_q` in the given order and `r_col`
+/// interpolated from the measured points as set by `interpolation`. Targets
+/// outside the measured q range are null rather than extrapolated. As in
+/// `reflectivity_matrix`, repeated q points are averaged and rows with a null
+/// or `NaN` q or reflectivity are dropped first. Curves resampled onto one grid
+/// can then be averaged or compared point by point.
+pub fn resample_q(
+    df: &DataFrame,
+    q_col: &str,
+    r_col: &str,
+    target_q: &[f64],
+    interpolation: QInterpolation,
+) -> Result<DataFrame, FitsLoaderError> {
+    let q = float_values(df, q_col)?;
+    let r = float_values(df, r_col)?;
+    let mut points: Vec<(f64, f64)> = q
+        .into_iter()
+        .zip(r)
+        .filter(|(q, r)| !q.is_nan() && !r.is_nan())
+        .filter_map(|(q, r)| match interpolation {
+            QInterpolation::Linear => Some((q, r)),
+            QInterpolation::Log => (r > 0.0).then(|| (q, r.ln())),
+        })
+        .collect();
+    if points.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let points = average_duplicates(points);
+
+    let values: Vec<Option<f64>> = target_q
+        .iter()
+        .map(|&q| {
+            let r = interpolate(&points, q)?;
+            Some(match interpolation {
+                QInterpolation::Linear => r,
+                QInterpolation::Log => r.exp(),
+            })
+        })
+        .collect();
+    Ok(DataFrame::new(vec![
+        Column::new(q_col.into(), target_q),
+        Column::new(r_col.into(), values),
+    ])?)
+}
+
+/// Rebins the reflectivity `r_col` with its uncertainty `err_col` into the q
+/// bins between consecutive `bin_edges`, for oversampled curves.
+///
+/// Bin `i` holds the points with `bin_edges[i] <= q < bin_edges[i + 1]`, the
+/// last bin including its upper edge. Within a bin `q` and R are averaged with
+/// the weights `w = 1 / err²` and the error becomes `1 / sqrt(Σ w)`. Points with
+/// a null or `NaN` value or a non-positive error, which has no finite weight,
+/// are left out. The result has one row per bin under the input column names,
+/// with nulls for bins that hold no point.
+pub fn rebin_q(
+    df: &DataFrame,
+    q_col: &str,
+    r_col: &str,
+    err_col: &str,
+    bin_edges: &[f64],
+) -> Result<DataFrame, FitsLoaderError> {
+    if bin_edges.len() < 2
+        || bin_edges
+            .windows(2)
+            .any(|pair| pair[0].partial_cmp(&pair[1]) != Some(Ordering::Less))
+    {
+        return Err(FitsLoaderError::Other(format!(
+            "Bin edges {:?} must be at least two increasing values",
+            bin_edges
+        )));
+    }
+    let n_bins = bin_edges.len() - 1;
+    // Sums of w, w * q and w * R per bin.
+    let mut sums = vec![(0.0, 0.0, 0.0); n_bins];
+    let points = float_values(df, q_col)?
+        .into_iter()
+        .zip(float_values(df, r_col)?)
+        .zip(float_values(df, err_col)?);
+    for ((q, r), err) in points {
+        if q.is_nan() || r.is_nan() || err.is_nan() || err <= 0.0 {
+            continue;
+        }
+        let bin = match bin_edges.partition_point(|&edge| edge <= q) {
+            0 => continue,
+            i if i <= n_bins => i - 1,
+            _ if q == bin_edges[n_bins] => n_bins - 1,
+            _ => continue,
+        };
+        let w = 1.0 / (err * err);
+        let (sum_w, sum_q, sum_r) = &mut sums[bin];
+        *sum_w += w;
+        *sum_q += w * q;
+        *sum_r += w * r;
+    }
+
+    let filled = |value: fn(f64, f64, f64) -> f64| -> Vec<Option<f64>> {
+        sums.iter()
+            .map(|&(w, q, r)| (w > 0.0).then(|| value(w, q, r)))
+            .collect()
+    };
+    Ok(DataFrame::new(vec![
+        Column::new(q_col.into(), filled(|w, q, _| q / w)),
+        Column::new(r_col.into(), filled(|w, _, r| r / w)),
+        Column::new(err_col.into(), filled(|w, _, _| 1.0 / w.sqrt())),
+    ])?)
+}
+
+// ================== Direct Beam ==================
+
+/// Loads a direct-beam measurement, the beam without a sample, for normalizing
+/// reflectivity.
+///
+/// `path` is a single FITS file or a directory of them, read with
+/// `header_items`, which should include the energy and polarization the frames
+/// are matched on later. Every frame gets its summed counts as `I0`, divided by
+/// `EXPOSURE [s]` when that is loaded so that it is in counts per second like
+/// the `R` of `reduce_xrr`. The images themselves are not kept.
+pub fn load_direct_beam(
+    path: impl AsRef<Path>,
+    header_items: &[HeaderValue],
+) -> Result<DataFrame, FitsLoaderError> {
+    let path = path.as_ref();
+    let mut options = ExperimentType::Xrr.default_options();
+    options.image.total_counts = true;
+    options.image.drop_image = true;
+    let df = if path.is_dir() {
+        let dir = path
+            .to_str()
+            .ok_or_else(|| FitsLoaderError::InvalidFileName(path.to_string_lossy().into_owned()))?;
+        read_experiment_with_options(dir, header_items, &options)?
+    } else {
+        read_fits_with_options(path.to_path_buf(), header_items, &options)?
+    };
+    let exposure = HeaderValue::Exposure.name();
+    let i0 = match df.column(exposure) {
+        Ok(_) => col("Total Counts") / col(exposure),
+        Err(_) => col("Total Counts"),
+    };
+    Ok(df.lazy().with_column(i0.alias("I0")).collect()?)
+}
+
+/// Adds the direct-beam intensity of `direct` to every frame of `df` as `I0`,
+/// ready for `R = I / I0`.
+///
+/// Frames are matched on `energy_col` and, when given, `pol_col`, which both
+/// frames need to have. For each polarization the `I0` of `direct` is linearly
+/// interpolated in energy, so sample energies between the direct-beam energies
+/// are covered, and repeated direct-beam energies are averaged. Frames at an
+/// energy outside the direct-beam range or at a polarization without a direct
+/// beam get a null `I0`.
+pub fn join_direct_beam(
+    df: &DataFrame,
+    direct: &DataFrame,
+    energy_col: &str,
+    pol_col: Option<&str>,
+) -> Result<DataFrame, FitsLoaderError> {
+    let pol_values = |df: &DataFrame| match pol_col {
+        Some(pol_col) => float_values(df, pol_col),
+        None => Ok(vec![0.0; df.height()]),
+    };
+
+    let mut curves: Vec<(f64, Vec<(f64, f64)>)> = vec![];
+    let points = float_values(direct, energy_col)?
+        .into_iter()
+        .zip(float_values(direct, "I0")?)
+        .zip(pol_values(direct)?);
+    for ((energy, i0), pol) in points {
+        if energy.is_nan() || i0.is_nan() || pol.is_nan() {
+            continue;
+        }
+        match curves.iter_mut().find(|(p, _)| *p == pol) {
+            Some((_, points)) => points.push((energy, i0)),
+            None => curves.push((pol, vec![(energy, i0)])),
+        }
+    }
+    if curves.is_empty() {
+        return Err(FitsLoaderError::NoData);
+    }
+    let curves: Vec<(f64, Vec<(f64, f64)>)> = curves
+        .into_iter()
+        .map(|(pol, mut points)| {
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            (pol, average_duplicates(points))
+        })
+        .collect();
+
+    let i0: Vec<Option<f64>> = float_values(df, energy_col)?
+        .into_iter()
+        .zip(pol_values(df)?)
+        .map(|(energy, pol)| {
+            let (_, points) = curves.iter().find(|(p, _)| *p == pol)?;
+            interpolate(points, energy)
+        })
+        .collect();
+    let mut out = df.clone();
+    out.with_column(Column::new("I0".into(), i0))?;
+    Ok(out)
+}
+
+// ================== Scan Completeness ==================
+
+/// The coordinates of `expected` with no frame in `df`, for catching aborted
+/// scans before they are reduced.
+///
+/// A coordinate counts as present when some value of `coord_col` is within
+/// `tol` of it, so `tol` should be below half the step of the scan. Null
+/// values never match. The missing coordinates are returned in the order of
+/// `expected`, an empty vector means the scan is complete.
+pub fn check_scan_completeness(
+    df: &DataFrame,
+    coord_col: &str,
+    expected: &[f64],
+    tol: f64,
+) -> Result<Vec<f64>, FitsLoaderError> {
+    let mut values = float_values(df, coord_col)?;
+    values.retain(|v| !v.is_nan());
+    values.sort_by(f64::total_cmp);
+    Ok(expected
+        .iter()
+        .copied()
+        .filter(|&coord| {
+            let i = values.partition_point(|&v| v < coord - tol);
+            values.get(i).is_none_or(|&v| v > coord + tol)
+        })
+        .collect())
+}
+
+// ================== Scan Direction ==================
+
+/// How a scan coordinate moves through the frames of a scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanDirection {
+    /// Never decreases from one frame to the next, repeated points allowed.
+    Increasing,
+    /// Never increases from one frame to the next, repeated points allowed.
+    Decreasing,
+    /// The same value, or at most one value, in every frame.
+    Constant,
+    /// Both rises and falls, e.g. a bidirectional scan that goes out and back.
+    NonMonotonic,
+}
+
+impl ScanDirection {
+    pub fn is_monotonic(&self) -> bool {
+        !matches!(self, ScanDirection::NonMonotonic)
+    }
+}
+
+/// Row indices of `df` in the order the frames were taken: by `Point Index`,
+/// then `Frame Number`, as far as `df` has them, row order otherwise. Rows
+/// without a `Point Index` come after those with one.
+fn acquisition_order(df: &DataFrame) -> Result<Vec<usize>, FitsLoaderError> {
+    let mut order: Vec<usize> = (0..df.height()).collect();
+    let names = df.get_column_names();
+    for key in ["Frame Number", "Point Index"] {
+        if names.iter().any(|c| c.as_str() == key) {
+            // Stable, so the later key wins and the earlier breaks its ties.
+            let values = float_values(df, key)?;
+            order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+        }
+    }
+    Ok(order)
+}
+
+/// Direction of `coord_col`, e.g. `Sample Theta [deg]`, in acquisition order.
+///
+/// The loaders sort by the scan axis, so the frames are first put back in the
+/// order they were taken, by `Point Index` and `Frame Number` when `df` has
+/// them and in row order otherwise. Null values are skipped. A
+/// `ScanDirection::NonMonotonic` scan has to be split, e.g. at its turning
+/// point, before it is stitched.
+pub fn scan_direction(df: &DataFrame, coord_col: &str) -> Result<ScanDirection, FitsLoaderError> {
+    let values = float_values(df, coord_col)?;
+    let mut values: Vec<f64> = acquisition_order(df)?
+        .into_iter()
+        .map(|i| values[i])
+        .collect();
+    values.retain(|v| !v.is_nan());
+    let (mut rises, mut falls) = (false, false);
+    for step in values.windows(2) {
+        match step[1].total_cmp(&step[0]) {
+            Ordering::Greater => rises = true,
+            Ordering::Less => falls = true,
+            Ordering::Equal => {}
+        }
+    }
+    Ok(match (rises, falls) {
+        (true, true) => ScanDirection::NonMonotonic,
+        (true, false) => ScanDirection::Increasing,
+        (false, true) => ScanDirection::Decreasing,
+        (false, false) => ScanDirection::Constant,
+    })
+}
+
+// ================== Stitching ==================
+
+/// Factor putting one reflectivity segment on the scale of another, see
+/// `stitch_scale`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StitchScale {
+    /// Factor to multiply the `R` and `R Err` of the segment by.
+    pub scale: f64,
+    /// One-sigma uncertainty of `scale` from the weighted fit.
+    pub scale_err: f64,
+    /// Number of overlap points the scale was fitted to.
+    pub points: usize,
+}
+
+/// Weighted least-squares scale of `segment` onto `reference` over the q range
+/// the two share, e.g. two parts of a scan taken through different filters.
+///
+/// The reference is interpolated linearly onto the q values of the segment
+/// inside its range. The scale `s` minimizes `Σ (R_ref - s·R_seg)² / σ²` with
+/// `σ² = σ_ref² + s₀²·σ_seg²`, `s₀ = ΣR_ref / ΣR_seg` being the plain ratio, so
+/// points with poor statistics weigh less than a plain ratio would let them.
+/// With the `R Err` of `reduce_xrr`, `sqrt(counts) / exposure`, that are the
+/// low-count and short-exposure points. `scale_err` is `1 / sqrt(Σ R_seg² / σ²)`.
+///
+/// Points with a non-finite value or without a positive `σ²` are skipped, and
+/// segments without a usable overlap point are a `NoData` error.
+pub fn stitch_scale(
+    reference: &ReflectivityCurve,
+    segment: &ReflectivityCurve,
+) -> Result<StitchScale, FitsLoaderError> {
+    let sorted = |values: &[f64]| {
+        let mut points: Vec<(f64, f64)> = reference
+            .q
+            .iter()
+            .copied()
+            .zip(values.iter().copied())
+            .filter(|(q, v)| q.is_finite() && v.is_finite())
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        average_duplicates(points)
+    };
+    let (r_ref, err_ref) = (sorted(&reference.r), sorted(&reference.r_err));
+    // (R_seg, σ_seg, R_ref, σ_ref) at every segment point inside the reference.
+    let overlap: Vec<(f64, f64, f64, f64)> = segment
+        .q
+        .iter()
+        .zip(segment.r.iter().zip(&segment.r_err))
+        .filter(|(q, (r, err))| q.is_finite() && r.is_finite() && err.is_finite())
+        .filter_map(|(&q, (&r, &err))| {
+            Some((r, err, interpolate(&r_ref, q)?, interpolate(&err_ref, q)?))
+        })
+        .collect();
+    let (sum_ref, sum_seg) = overlap
+        .iter()
+        .fold((0.0, 0.0), |(y, x), point| (y + point.2, x + point.0));
+    let s0 = sum_ref / sum_seg;
+    let (mut sxx, mut sxy, mut points) = (0.0, 0.0, 0);
+    for (x, sx, y, sy) in overlap {
+        let variance = sy * sy + s0 * s0 * sx * sx;
+        if !(variance.is_finite() && variance > 0.0) {
+            continue;
+        }
+        sxx += x * x / variance;
+        sxy += x * y / variance;
+        points += 1;
+    }
+    if points == 0 || sxx <= 0.0 {
+        return Err(FitsLoaderError::NoData);
+    }
+    Ok(StitchScale {
+        scale: sxy / sxx,
+        scale_err: sxx.sqrt().recip(),
+        points,
+    })
+}
+
+// ================== Scan Splitting ==================
+
+/// Splits a frame loaded from several scans into one frame per value of
+/// `scan_id_col`, e.g. `Scan ID`, in order of first appearance and keeping the
+/// row order within each scan. Rows with a null id form a scan of their own.
+pub fn split_scans(df: &DataFrame, scan_id_col: &str) -> Result<Vec<DataFrame>, FitsLoaderError> {
+    df.column(scan_id_col)?;
+    Ok(df.partition_by_stable([scan_id_col], true)?)
+}
+
+/// Splits a frame into scans where `coord_col`, e.g. `Sample Theta [deg]` or
+/// `Beamline Energy [eV]`, breaks its monotonicity, for directories without a
+/// usable scan id.
+///
+/// The frames are taken in acquisition order, as by `scan_direction`. The
+/// first step that changes the coordinate sets the direction of a scan, and a
+/// new scan starts at the first frame stepping against it, e.g. when the angle
+/// resets to the start of the next scan. Repeated points and null values never
+/// start a scan. A bidirectional scan is therefore split at its turning point.
+/// The scans come in acquisition order, each with its rows in that order.
+pub fn split_scans_at_resets(
+    df: &DataFrame,
+    coord_col: &str,
+) -> Result<Vec<DataFrame>, FitsLoaderError> {
+    let values = float_values(df, coord_col)?;
+    let mut scans: Vec<Vec<IdxSize>> = vec![];
+    let mut direction = Ordering::Equal;
+    let mut previous: Option<f64> = None;
+    for i in acquisition_order(df)? {
+        let value = values[i];
+        let step = match previous {
+            Some(previous) if !value.is_nan() => value.total_cmp(&previous),
+            _ => Ordering::Equal,
+        };
+        match scans.last_mut() {
+            Some(scan) if step == Ordering::Equal => scan.push(i as IdxSize),
+            Some(scan) if direction == Ordering::Equal || step == direction => {
+                direction = step;
+                scan.push(i as IdxSize);
+            }
+            _ => {
+                scans.push(vec![i as IdxSize]);
+                direction = Ordering::Equal;
+            }
+        }
+        if !value.is_nan() {
+            previous = Some(value);
+        }
+    }
+    scans
+        .into_iter()
+        .map(|rows| Ok(df.take(&IdxCa::from_vec("".into(), rows))?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::test_util::{test_dir, u16_image, write_fits};
+
+    /// Header cards of an XRR frame at `theta` with a half-second exposure.
+    fn xrr_cards(theta: &'static str) -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("Sample Theta", theta),
+            ("CCD Theta", "0.5"),
+            ("Beamline Energy", "250.0"),
+            ("Beam Current", "500.0"),
+            ("EPU Polarization", "100.0"),
+            ("Horizontal Exit Slit Size", "100.0"),
+            ("Higher Order Suppressor", "5.5"),
+            ("EXPOSURE", "0.5"),
+        ]
+    }
+
+    #[test]
+    fn xrr_scans_reduce_to_a_reflectivity_curve() {
+        let dir = test_dir("reduce_xrr");
+        let scan = dir.join("scan");
+        std::fs::create_dir_all(&scan).unwrap();
+        for (i, (theta, pixels)) in [("2.0", [9, 12, 1, 1]), ("1.0", [20, 30, 1, 1])]
+            .into_iter()
+            .enumerate()
+        {
+            write_fits(
+                &scan.join(format!("film_{:05}.fits", i + 1)),
+                &xrr_cards(theta),
+                Some(u16_image(2, 2, &pixels)),
+            );
+        }
+        let dark = dir.join("dark.fits");
+        write_fits(
+            &dark,
+            &xrr_cards("0.0"),
+            Some(u16_image(2, 2, &[2, 3, 1, 1])),
+        );
+
+        // The top row only, holding 21 and 50 counts over a dark of 5.
+        let roi = Roi {
+            x0: 0,
+            y0: 0,
+            w: 2,
+            h: 1,
+        };
+        let df = reduce_xrr(scan.to_str().unwrap(), roi, Some(&dark)).unwrap();
+        let names: Vec<&str> = df.get_column_names().iter().map(|n| n.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Q [Å⁻¹]",
+                "R",
+                "R Err",
+                "Sample Theta [deg]",
+                "Beamline Energy [eV]",
+                "EPU Polarization [deg]",
+            ]
+        );
+        // In scan order, the order of the files.
+        assert_eq!(float_values(&df, "Sample Theta [deg]").unwrap(), [2.0, 1.0]);
+        assert_eq!(float_values(&df, "R").unwrap(), [32.0, 90.0]);
+        let err = float_values(&df, "R Err").unwrap();
+        assert_eq!(err, [26f64.sqrt() / 0.5, 55f64.sqrt() / 0.5]);
+    }
+
+    #[test]
+    fn merged_exposures_are_weighted_and_zero_weights_are_null() {
+        let images = [
+            array![[1.0, 2.0]],
+            array![[5.0, 6.0]],
+            array![[7.0, 7.0]],
+            array![[9.0, 9.0]],
+        ];
+        let images: Vec<Option<Array2<f64>>> = images.into_iter().map(Some).collect();
+        let df = DataFrame::new(vec![
+            Column::new("Sample Theta [deg]".into(), [1.0, 1.0, 2.0, 2.0]),
+            Column::new("EXPOSURE [s]".into(), [1.0, 3.0, 0.0, 0.0]),
+            Column::new("Intensity".into(), [10.0, 30.0, 5.0, 6.0]),
+            images_to_column("Raw".into(), &images).unwrap(),
+        ])
+        .unwrap();
+        let merged = merge_exposures(&df, &["Sample Theta [deg]"], "EXPOSURE [s]").unwrap();
+        let names: Vec<&str> = merged
+            .get_column_names()
+            .iter()
+            .map(|n| n.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            ["Sample Theta [deg]", "Intensity", "Raw", "Total Exposure"]
+        );
+        assert_eq!(float_values(&merged, "Total Exposure").unwrap(), [4.0, 0.0]);
+        let intensity: Vec<Option<f64>> = merged
+            .column("Intensity")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(intensity, [Some(25.0), None]);
+
+        // Shutter-closed frames weigh nothing, so their mean image is null too.
+        let raw = merged.column("Raw").unwrap();
+        assert_eq!(image_from_column(raw, 0).unwrap(), array![[4.0, 5.0]]);
+        assert!(raw.get(1).unwrap().is_null());
+    }
+
+    fn nullable_floats(column: &Column) -> Vec<Option<f64>> {
+        column.f64().unwrap().into_iter().collect()
+    }
+
+    /// Asserts that `found` holds the nulls of `expected` and its values up to
+    /// rounding.
+    fn assert_close(found: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(found.len(), expected.len(), "{:?} != {:?}", found, expected);
+        for (found, expected) in found.iter().zip(expected) {
+            match (found, expected) {
+                (Some(f), Some(e)) => {
+                    assert!((f - e).abs() < 1e-12, "{:?} != {:?}", found, expected)
+                }
+                _ => assert_eq!(found, expected),
+            }
+        }
+    }
+
+    fn curve(q: &[f64], r: &[f64], r_err: &[f64]) -> ReflectivityCurve {
+        ReflectivityCurve {
+            q: q.to_vec(),
+            r: r.to_vec(),
+            r_err: r_err.to_vec(),
+            meta: DataFrame::empty(),
+        }
+    }
+
+    #[test]
+    fn stitch_scale_fits_the_weighted_ratio_over_the_overlap() {
+        let reference = curve(
+            &[0.1, 0.2, 0.3, 0.4],
+            &[8.0, 6.0, 4.0, 2.0],
+            &[0.4, 0.2, 0.2, 0.1],
+        );
+        // Half the reference, with a last point past its q range.
+        let segment = curve(
+            &[0.2, 0.3, 0.4, 0.5],
+            &[3.0, 2.0, 1.0, 0.5],
+            &[0.1, 0.1, 0.05, 0.05],
+        );
+        let fit = stitch_scale(&reference, &segment).unwrap();
+        assert_eq!(fit.points, 3);
+        assert!((fit.scale - 2.0).abs() < 1e-12);
+        // σ² = σ_ref² + 2²·σ_seg² is 0.08, 0.08 and 0.02 at the overlap points.
+        let sxx: f64 = 9.0 / 0.08 + 4.0 / 0.08 + 1.0 / 0.02;
+        assert!((fit.scale_err - sxx.sqrt().recip()).abs() < 1e-12);
+
+        let apart = curve(&[1.0, 2.0], &[1.0, 1.0], &[0.1, 0.1]);
+        assert!(matches!(
+            stitch_scale(&reference, &apart),
+            Err(FitsLoaderError::NoData)
+        ));
+    }
+
+    #[test]
+    fn rebinned_points_are_inverse_variance_averages() {
+        let df = df!(
+            "Q" => [0.5, 0.7, 1.5, 1.2, 3.0, 2.5, -0.5, 3.5],
+            "R" => [4.0, 8.0, 2.0, 3.0, 10.0, 6.0, 1.0, 1.0],
+            "R Err" => [1.0, 0.5, 0.0, -1.0, 1.0, 1.0, 1.0, 1.0],
+        )
+        .unwrap();
+        let binned = rebin_q(&df, "Q", "R", "R Err", &[0.0, 1.0, 2.0, 3.0]).unwrap();
+        let column = |name| nullable_floats(binned.column(name).unwrap());
+        // Bin 0 weighs its points 1 and 4. The points of bin 1 have no positive
+        // error, and the last edge belongs to bin 2. Points outside every bin
+        // are left out.
+        let q = [Some((0.5 + 4.0 * 0.7) / 5.0), None, Some(2.75)];
+        assert_close(&column("Q"), &q);
+        let r = [Some((4.0 + 4.0 * 8.0) / 5.0), None, Some(8.0)];
+        assert_close(&column("R"), &r);
+        let err = [Some(5f64.sqrt().recip()), None, Some(2f64.sqrt().recip())];
+        assert_close(&column("R Err"), &err);
+    }
+
+    #[test]
+    fn repeats_are_averaged_within_the_coordinate_tolerance() {
+        let images: Vec<Option<Array2<f64>>> = (0..4)
+            .map(|i| Some(array![[i as f64, 2.0 * i as f64]]))
+            .collect();
+        let df = DataFrame::new(vec![
+            Column::new("Sample Theta [deg]".into(), [1.001, 2.0, 1.004, 1.008]),
+            Column::new("Frame Number".into(), [1u32, 2, 3, 4]),
+            images_to_column("Raw".into(), &images).unwrap(),
+        ])
+        .unwrap();
+        let coords = ["Sample Theta [deg]"];
+        let counts = |df: &DataFrame| -> Vec<u32> {
+            let counts = df.column("N Averaged").unwrap().u32().unwrap();
+            counts.into_no_null_iter().collect()
+        };
+        assert_eq!(
+            counts(&average_repeats(&df, &coords).unwrap()),
+            [1, 1, 1, 1]
+        );
+
+        // Neighbours within 0.005 chain into one group, 1.008 included.
+        let tolerance = Some(CoordinateTolerance::Absolute(0.005));
+        let chained = average_repeats_with_tolerance(&df, &coords, tolerance).unwrap();
+        assert_eq!(counts(&chained), [3, 1]);
+        assert_eq!(float_values(&chained, coords[0]).unwrap(), [1.001, 2.0]);
+        let frames = chained.column("Frame Number").unwrap().u32().unwrap();
+        assert_eq!(frames.into_no_null_iter().collect::<Vec<_>>(), [1, 2]);
+        let raw = chained.column("Raw").unwrap();
+        let mean = image_from_column(raw, 0).unwrap();
+        assert!((mean - array![[5.0 / 3.0, 10.0 / 3.0]])
+            .iter()
+            .all(|d| d.abs() < 1e-12));
+
+        // Two decimals put 1.008 in a bucket of its own.
+        let tolerance = Some(CoordinateTolerance::Decimals(2));
+        let rounded = average_repeats_with_tolerance(&df, &coords, tolerance).unwrap();
+        assert_eq!(counts(&rounded), [2, 1, 1]);
+        assert_eq!(
+            float_values(&rounded, coords[0]).unwrap(),
+            [1.001, 2.0, 1.008]
+        );
+        let raw = rounded.column("Raw").unwrap();
+        assert_eq!(image_from_column(raw, 0).unwrap(), array![[1.0, 2.0]]);
+    }
+
+    #[test]
+    fn energies_on_different_q_grids_share_the_union_grid() {
+        let df = df!(
+            "Q" => [0.1, 0.3, 0.3, 0.2, 0.4, 0.5],
+            "Energy" => [250.0, 250.0, 250.0, 260.0, 260.0, 260.0],
+            "R" => [1.0, 3.0, 5.0, 2.0, 6.0, f64::NAN],
+        )
+        .unwrap();
+        let matrix = reflectivity_matrix(&df, "Q", "Energy", "R").unwrap();
+        let names: Vec<&str> = matrix
+            .get_column_names()
+            .iter()
+            .map(|n| n.as_str())
+            .collect();
+        assert_eq!(names, ["Q", "250", "260"]);
+        // The `NaN` row is dropped before its q joins the grid.
+        assert_close(
+            &nullable_floats(matrix.column("Q").unwrap()),
+            &[Some(0.1), Some(0.2), Some(0.3), Some(0.4)],
+        );
+        // 250 eV averages its two points at 0.3, neither energy is extrapolated.
+        assert_close(
+            &nullable_floats(matrix.column("250").unwrap()),
+            &[Some(1.0), Some(2.5), Some(4.0), None],
+        );
+        assert_close(
+            &nullable_floats(matrix.column("260").unwrap()),
+            &[None, Some(2.0), Some(4.0), Some(6.0)],
+        );
+    }
+
+    #[test]
+    fn resampled_curves_interpolate_linearly_or_in_log_r() {
+        let df = df!(
+            "Q" => [0.1, 0.3, 0.3, 0.5],
+            "R" => [1.0, 0.01, 0.0001, -1.0],
+        )
+        .unwrap();
+        let targets = [0.05, 0.2, 0.3, 0.5];
+        let resampled = |interpolation| {
+            let df = resample_q(&df, "Q", "R", &targets, interpolation).unwrap();
+            assert_eq!(float_values(&df, "Q").unwrap(), targets);
+            nullable_floats(df.column("R").unwrap())
+        };
+        // The repeated points at 0.3 average to 0.00505.
+        assert_close(
+            &resampled(QInterpolation::Linear),
+            &[None, Some(1.00505 / 2.0), Some(0.00505), Some(-1.0)],
+        );
+        // In log R they average to 0.001, and the negative point is left out.
+        assert_close(
+            &resampled(QInterpolation::Log),
+            &[None, Some(0.001f64.sqrt()), Some(0.001), None],
+        );
+    }
+}
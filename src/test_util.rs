@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::BLOCK;
+
+// ================== Test Fixtures ==================
+// FITS files written byte by byte for the unit tests, so that what the loader
+// reads back is checked against the standard layout rather than against the
+// astrors writer: big-endian values with NAXIS1 varying fastest.
+
+/// An empty directory for the files of test `name`, removed first if present.
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pyref_core_test_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// One 80-byte card. Keywords longer than eight characters or holding spaces
+/// become `HIERARCH` cards. String values are passed quoted, e.g. `"'P123'"`.
+fn card(key: &str, value: &str) -> String {
+    let card = if key.len() > 8 || key.contains(' ') {
+        format!("HIERARCH {} = {:>20}", key, value)
+    } else {
+        format!("{:<8}= {:>20}", key, value)
+    };
+    format!("{:<80}", card)
+}
+
+/// Header cards and `END`, padded with blanks to whole blocks.
+fn header_bytes(cards: &[(&str, &str)]) -> Vec<u8> {
+    let mut text: String = cards.iter().map(|(key, value)| card(key, value)).collect();
+    text.push_str(&format!("{:<80}", "END"));
+    let mut bytes = text.into_bytes();
+    bytes.resize(bytes.len().div_ceil(BLOCK) * BLOCK, b' ');
+    bytes
+}
+
+/// `values` as big-endian bytes, e.g. `be_bytes(&[1i16, 2], i16::to_be_bytes)`.
+pub(crate) fn be_bytes<T: Copy, const N: usize>(values: &[T], to_be: fn(T) -> [u8; N]) -> Vec<u8> {
+    values.iter().flat_map(|&value| to_be(value)).collect()
+}
+
+/// An image extension: `BITPIX`, the axis lengths from `NAXIS1` on, further
+/// cards such as `BZERO`, and the data unit.
+pub(crate) struct Extension {
+    pub bitpix: i32,
+    pub axes: Vec<usize>,
+    pub cards: Vec<(&'static str, &'static str)>,
+    pub data: Vec<u8>,
+}
+
+/// Writes a file with a data-less primary HDU holding `primary` cards, followed
+/// by `image` as its only extension, at HDU index `1`.
+pub(crate) fn write_fits(path: &Path, primary: &[(&str, &str)], image: Option<Extension>) {
+    let mut cards = vec![("SIMPLE", "T"), ("BITPIX", "8"), ("NAXIS", "0")];
+    if image.is_some() {
+        cards.push(("EXTEND", "T"));
+    }
+    cards.extend_from_slice(primary);
+    let mut bytes = header_bytes(&cards);
+    if let Some(image) = image {
+        let bitpix = image.bitpix.to_string();
+        let naxis = image.axes.len().to_string();
+        let lengths: Vec<(String, String)> = image
+            .axes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (format!("NAXIS{}", i + 1), n.to_string()))
+            .collect();
+        let mut cards = vec![
+            ("XTENSION", "'IMAGE   '"),
+            ("BITPIX", bitpix.as_str()),
+            ("NAXIS", naxis.as_str()),
+        ];
+        cards.extend(lengths.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        cards.extend([("PCOUNT", "0"), ("GCOUNT", "1")]);
+        cards.extend_from_slice(&image.cards);
+        bytes.extend(header_bytes(&cards));
+        let mut data = image.data;
+        data.resize(data.len().div_ceil(BLOCK) * BLOCK, 0);
+        bytes.extend(data);
+    }
+    fs::write(path, bytes).unwrap();
+}
+
+/// A 16-bit extension of `(rows, cols)` holding `values` in row-major order,
+/// stored with `BZERO = 32768` as the detector writes them.
+pub(crate) fn u16_image(rows: usize, cols: usize, values: &[u16]) -> Extension {
+    let stored: Vec<i16> = values.iter().map(|&v| (v as i32 - 32768) as i16).collect();
+    Extension {
+        bitpix: 16,
+        axes: vec![cols, rows],
+        cards: vec![("BZERO", "32768")],
+        data: be_bytes(&stored, i16::to_be_bytes),
+    }
+}